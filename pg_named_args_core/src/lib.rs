@@ -0,0 +1,686 @@
+//! The rewriter of the queries of `pg_named_args`, for tools that need the same semantics as
+//! the macros, like linters, code generators, fuzzers and editors.
+//!
+//! The macros of `pg_named_args` use this crate to rewrite the named arguments, the parameter
+//! groups and the runtime slots of a query. The other options and checks of the macros are
+//! not part of this crate.
+
+/// The kinds of values that are formatted into the query at runtime.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Slot {
+    /// A `${fragment}` of sql.
+    Fragment,
+    /// The `$schema` of a `$schema.table` name.
+    Schema,
+    /// A `${fragment}` at the end of a `$[...]` group, which is appended to the group with a
+    /// comma when it isn't empty.
+    Items,
+    /// The `$[...]` group with optional columns, which is written at runtime.
+    Columns,
+    /// The `$[..]` group of the group with optional columns, which is written at runtime.
+    Values,
+    /// A `${fragment: order_by}` of the columns of an `ORDER BY` clause.
+    OrderBy,
+    /// A `${fragment: where}` of a condition.
+    Where,
+    /// A `${fragment: ident}` of a quoted identifier.
+    Ident,
+    /// The list of a `$list~in` membership test, which is either the parameter or a subquery
+    /// that unnests the parameter, depending on the length of the list at runtime. The name is
+    /// the argument of the list.
+    List,
+}
+
+/// A column of the `$[...]` group with optional columns, like `$[id, name?, email?]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Column {
+    /// The column as it is written in the group, without the `?`.
+    pub sql: String,
+    /// The name of the argument of the column.
+    pub arg: String,
+    /// Whether the column is only written when its argument is `Some`. The parameters of the
+    /// optional columns are numbered after the other parameters, and are not part of the names.
+    pub optional: bool,
+}
+
+/// The result of [rewrite].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Rewrite {
+    /// The query with positional parameters, as a format string with a `{}` for every slot.
+    pub template: String,
+    /// The names and kinds of the slots, in the order of the `{}` in the template. The
+    /// [Slot::Columns] and [Slot::Values] slots have an empty name.
+    pub fragments: Vec<(String, Slot)>,
+    /// The defaults of the fragments, given like `${order_by = ORDER BY id}`.
+    pub defaults: Vec<(String, String)>,
+    /// The columns of the group with optional columns, a query can have one such group.
+    pub columns: Vec<Column>,
+    /// The syntax errors. After an error, the template stops at the error, but the names of
+    /// the arguments in the rest of the query are still added.
+    pub errors: Vec<String>,
+}
+
+/// Rewrites the named arguments, groups and modifiers of a query to positional parameters.
+///
+/// The names of the new arguments are appended to `names`, and the names that are already
+/// there keep their parameters, so multiple parts of a query can share their arguments.
+/// The braces of the query are escaped in the template, which is a format string.
+///
+/// ```
+/// let mut names = vec![];
+/// let rewrite = pg_named_args_core::rewrite(
+///     "INSERT INTO t($[a, b]) VALUES($[..]) RETURNING ${c}",
+///     &mut names,
+/// );
+/// assert_eq!(rewrite.template, "INSERT INTO t(a, b) VALUES($1, $2) RETURNING {}");
+/// assert_eq!(names, ["a", "b"]);
+/// assert_eq!(rewrite.fragments, [("c".to_owned(), pg_named_args_core::Slot::Fragment)]);
+/// assert!(rewrite.errors.is_empty());
+/// ```
+pub fn rewrite(inp: &str, names: &mut Vec<String>) -> Rewrite {
+    let mut errors = vec![];
+    let mut fragments = vec![];
+    let mut defaults = vec![];
+    let mut columns = vec![];
+    let mut push_err = |message: &str| errors.push(message.to_owned());
+
+    let mut inp = &*inp.replace("{", "{{").replace("}", "}}");
+
+    let mut template = String::new();
+    let mut batch = None::<String>;
+
+    let mut get_idx = |ident: &str| {
+        if let Some(idx) = names.iter().position(|x| x == ident) {
+            idx
+        } else {
+            names.push(ident.to_owned());
+            names.len() - 1
+        }
+    };
+
+    fn ident_char(x: char) -> bool {
+        x.is_alphanumeric() || x == '_'
+    }
+
+    loop {
+        let Some(dollar_pos) = inp.find('$') else {
+            template.push_str(inp);
+            break;
+        };
+
+        template.push_str(&inp[..dollar_pos]);
+        inp = &inp[dollar_pos + 1..];
+
+        let mut is_fragment = false;
+        // braces have been pre-escaped
+        if inp.get(..2) == Some("{{") {
+            is_fragment = true;
+            inp = &inp[2..];
+        }
+
+        let ident_len = inp.find(|x: char| !ident_char(x)).unwrap_or(inp.len());
+        let ident = &inp[..ident_len];
+        inp = &inp[ident_len..];
+
+        if ident.is_empty() {
+            if is_fragment {
+                push_err("expected an identifer after `{`");
+                arg_names(inp).for_each(|x| {
+                    get_idx(x);
+                });
+                return Rewrite {
+                    template,
+                    fragments,
+                    defaults,
+                    columns,
+                    errors,
+                };
+            }
+
+            let Some("[") = inp.get(..1) else {
+                push_err("expected identifier or `[` after `$`");
+                arg_names(inp).for_each(|x| {
+                    get_idx(x);
+                });
+                return Rewrite {
+                    template,
+                    fragments,
+                    defaults,
+                    columns,
+                    errors,
+                };
+            };
+            inp = &inp[1..];
+
+            // line comments are stripped from the group, the newline is kept
+            let mut group = String::new();
+            loop {
+                let until = inp
+                    .find(|x: char| {
+                        !ident_char(x)
+                            && !x.is_ascii_whitespace()
+                            && x != ','
+                            && x != '.'
+                            && x != '?'
+                    })
+                    .unwrap_or(inp.len());
+                group.push_str(&inp[..until]);
+                inp = &inp[until..];
+
+                if inp.starts_with('"') {
+                    let len = quoted_len(inp);
+                    group.push_str(&inp[..len]);
+                    inp = &inp[len..];
+                } else if inp.starts_with("--") {
+                    inp = &inp[inp.find('\n').unwrap_or(inp.len())..];
+                } else {
+                    break;
+                }
+            }
+
+            // a fragment at the end of the group adds columns or values at runtime
+            let mut items = None;
+            if let Some(rest) = inp.strip_prefix("${{") {
+                let len = rest.find(|x: char| !ident_char(x)).unwrap_or(rest.len());
+                if let Some(rest) = rest[len..].strip_prefix("}}").filter(|_| len > 0) {
+                    items = Some(&inp[3..3 + len]);
+                    inp = rest.trim_start();
+                }
+            }
+
+            let Some("]") = inp.get(..1) else {
+                push_err("expected closing `]`");
+                // the group is unfinished, so the query continues after the last column
+                for column in split_columns(&group) {
+                    let column = column.split_whitespace().next().unwrap_or_default();
+                    let ident = column_arg(column.trim_end_matches('?'));
+                    if is_arg(&ident) {
+                        get_idx(&ident);
+                    }
+                }
+                arg_names(inp).for_each(|x| {
+                    get_idx(x);
+                });
+                return Rewrite {
+                    template,
+                    fragments,
+                    defaults,
+                    columns,
+                    errors,
+                };
+            };
+            inp = &inp[1..];
+
+            if items.is_some() {
+                let trimmed = group.trim_end();
+                group = trimmed.strip_suffix(',').unwrap_or(trimmed).to_owned();
+            }
+
+            if group == ".." {
+                let Some(values) = batch.take() else {
+                    push_err("parameter group is used, but not defined");
+                    continue;
+                };
+
+                // the values of a group with optional columns are written at runtime
+                if values == OPTIONAL_VALUES {
+                    fragments.push((String::new(), Slot::Values));
+                }
+                template.push_str(&values);
+            } else {
+                // a trailing comma is allowed, like in rust lists
+                let trimmed = group.trim_end();
+                if let Some(head) = trimmed.strip_suffix(',') {
+                    group = format!("{head}{}", &group[trimmed.len()..]);
+                }
+
+                let mut out = vec![];
+                let mut group_columns: Vec<Column> = vec![];
+                for column in split_columns(&group) {
+                    let column = column.trim();
+                    let (column, optional) = match column.strip_suffix('?') {
+                        Some(column) => (column.trim_end(), true),
+                        None => (column, false),
+                    };
+                    let ident = column_arg(column);
+                    if ident.is_empty() {
+                        push_err(
+                            "expected identifier between all of `$[`, every `,` and final `]`",
+                        );
+                        continue;
+                    }
+                    if !is_arg(&ident) {
+                        push_err(&format!(
+                            "the argument `{ident}` of the column `{column}` is not an identifier"
+                        ));
+                        continue;
+                    }
+                    // the columns would be bound to the same value
+                    if let Some(other) = group_columns.iter().find(|x| x.arg == ident) {
+                        push_err(&format!(
+                            "the columns `{}` and `{column}` have the same argument `{ident}`",
+                            other.sql
+                        ));
+                        continue;
+                    }
+
+                    // the parameters of the optional columns are numbered at runtime
+                    if !optional {
+                        let idx = get_idx(&ident);
+                        out.push(format!("${}", idx + 1));
+                    }
+                    group_columns.push(Column {
+                        sql: column.to_owned(),
+                        arg: ident,
+                        optional,
+                    });
+                }
+
+                let mut values = out.join(", ");
+                if group_columns.iter().any(|x| x.optional) {
+                    if !columns.is_empty() {
+                        push_err("only a single group can have optional columns");
+                    }
+                    columns = group_columns;
+                    fragments.push((String::new(), Slot::Columns));
+                    group = "{}".to_owned();
+                    values = OPTIONAL_VALUES.to_owned();
+                }
+
+                if batch.replace(values).is_some() {
+                    push_err("previous parameter group is not used");
+                }
+
+                template.push_str(&group);
+            }
+
+            if let Some(ident) = items {
+                fragments.push((ident.to_owned(), Slot::Items));
+                template.push_str("{}");
+            }
+        } else if is_fragment {
+            // the kind of the fragment restricts the fragments that can be used in the slot
+            let mut slot = Slot::Fragment;
+            if let Some(rest) = inp.trim_start().strip_prefix(':') {
+                let rest = rest.trim_start();
+                let len = rest.find(|x: char| !ident_char(x)).unwrap_or(rest.len());
+                slot = match &rest[..len] {
+                    "order_by" => Slot::OrderBy,
+                    "where" => Slot::Where,
+                    "ident" => Slot::Ident,
+                    kind => {
+                        push_err(&format!(
+                            "unknown fragment kind `{kind}`, expected `order_by`, `where` or `ident`"
+                        ));
+                        Slot::Fragment
+                    }
+                };
+                inp = &rest[len..];
+            }
+            // the default is the sql until the end of the fragment
+            if let Some(rest) = inp.trim_start().strip_prefix('=') {
+                let len = rest.find("}}").unwrap_or(rest.len());
+                let default = rest[..len].trim();
+                if default.contains(['$', '{', '}']) {
+                    push_err("the default of a fragment is not allowed to contain `$` or braces");
+                } else {
+                    defaults.push((ident.to_owned(), default.to_owned()));
+                }
+                inp = &rest[len..];
+            }
+            // braces have been pre-escaped
+            if inp.get(..2) == Some("}}") {
+                inp = &inp[2..];
+            } else {
+                push_err("fragment should end with `}`")
+            }
+            fragments.push((ident.to_owned(), slot));
+            template.push_str("{}");
+        } else if inp
+            .strip_prefix('.')
+            .is_some_and(|x| x.starts_with(|x: char| ident_char(x) || x == '"'))
+        {
+            // the table name remains in the query
+            fragments.push((ident.to_owned(), Slot::Schema));
+            template.push_str("{}");
+        } else {
+            let idx = get_idx(ident);
+            let param = format!("${}", idx + 1);
+
+            let Some(modifier) = modifier(inp) else {
+                template.push_str(&param);
+                continue;
+            };
+            inp = &inp[modifier.len() + 1..];
+
+            // the text search configuration is an optional named argument, `$a~websearch($b)`
+            let mut config = None;
+            if let Some(rest) = inp.strip_prefix("($") {
+                let len = rest.find(|x: char| !ident_char(x)).unwrap_or(rest.len());
+                let Some(rest) = rest[len..].strip_prefix(')').filter(|_| len > 0) else {
+                    push_err("expected a named argument in `~modifier($config)`");
+                    arg_names(inp).for_each(|x| {
+                        get_idx(x);
+                    });
+                    return Rewrite {
+                        template,
+                        fragments,
+                        defaults,
+                        columns,
+                        errors,
+                    };
+                };
+                config = Some(format!("${}::regconfig, ", get_idx(&inp[2..len + 2]) + 1));
+                inp = rest;
+            }
+
+            // the pattern characters of the value are escaped with the default escape character
+            let escaped =
+                format!(r"replace(replace(replace({param}, '\', '\\'), '%', '\%'), '_', '\_')");
+            let function = match modifier {
+                "contains" | "starts_with" | "ends_with" | "any" | "unnest" | "in"
+                    if config.is_some() =>
+                {
+                    push_err(&format!(
+                        "the `{modifier}` modifier doesn't take a text search configuration"
+                    ));
+                    continue;
+                }
+                "contains" => format!("('%' || {escaped} || '%')"),
+                "starts_with" => format!("({escaped} || '%')"),
+                "ends_with" => format!("('%' || {escaped})"),
+                // the planner estimates an array by its elements, a subquery is joined
+                "any" => format!("ANY({param})"),
+                "unnest" => format!("ANY(SELECT unnest({param}))"),
+                "in" => {
+                    fragments.push((ident.to_owned(), Slot::List));
+                    "ANY({})".to_owned()
+                }
+                "websearch" | "plain" | "phrase" => {
+                    let config = config.unwrap_or_default();
+                    let function = match modifier {
+                        "websearch" => "websearch_to_tsquery",
+                        "plain" => "plainto_tsquery",
+                        _ => "phraseto_tsquery",
+                    };
+                    format!("{function}({config}{param})")
+                }
+                _ => unreachable!("unknown modifier `{modifier}`"),
+            };
+            template.push_str(&function);
+        }
+    }
+
+    if batch.is_some() {
+        push_err("last parameter group is not used");
+    }
+
+    Rewrite {
+        template,
+        fragments,
+        defaults,
+        columns,
+        errors,
+    }
+}
+
+/// The values of a group with optional columns, which can't be the values of another group,
+/// because the braces of the query are escaped.
+const OPTIONAL_VALUES: &str = "{}";
+
+/// The names of the arguments in the rest of a query with a syntax error, so the `Args` struct
+/// keeps its fields and rust-analyzer can still complete them.
+fn arg_names(inp: &str) -> impl Iterator<Item = &str> {
+    arguments(inp).map(|(_, name)| name)
+}
+
+/// The names of the `$name` arguments in the query, with the position of their `$`.
+///
+/// The `$schema` of a `$schema.table` name is not an argument.
+pub fn arguments(inp: &str) -> impl Iterator<Item = (usize, &str)> {
+    inp.match_indices('$').filter_map(|(pos, _)| {
+        let rest = &inp[pos + 1..];
+        let len = rest.find(|x: char| !x.is_alphanumeric() && x != '_');
+        let len = len.unwrap_or(rest.len());
+        // a schema is not an argument
+        let is_schema = rest[len..]
+            .strip_prefix('.')
+            .is_some_and(|x| x.starts_with(|x: char| x.is_alphanumeric() || x == '_' || x == '"'));
+        (len > 0 && !is_schema).then_some((pos, &rest[..len]))
+    })
+}
+
+/// The modifiers that can follow an argument, like `$name~contains`.
+const MODIFIERS: [&str; 9] = [
+    "contains",
+    "starts_with",
+    "ends_with",
+    "websearch",
+    "plain",
+    "phrase",
+    "any",
+    "unnest",
+    "in",
+];
+
+/// The modifier directly after an argument, without the `~`.
+///
+/// Any other word is left alone, as `$name~x` and `$name ~ x` are regex matches.
+///
+/// ```
+/// assert_eq!(pg_named_args_core::modifier("~contains AND b"), Some("contains"));
+/// assert_eq!(pg_named_args_core::modifier("~containsx"), None);
+/// assert_eq!(pg_named_args_core::modifier("~x"), None);
+/// ```
+pub fn modifier(inp: &str) -> Option<&str> {
+    let rest = inp.strip_prefix('~')?;
+    let len = rest.find(|x: char| !x.is_alphanumeric() && x != '_');
+    let word = &rest[..len.unwrap_or(rest.len())];
+    MODIFIERS.contains(&word).then_some(word)
+}
+
+/// The length of the quoted identifier at the start of the input, including the quotes.
+pub fn quoted_len(inp: &str) -> usize {
+    let mut len = 1;
+    while let Some(pos) = inp[len..].find('"') {
+        len += pos + 1;
+        // a quote is escaped by doubling it
+        if !inp[len..].starts_with('"') {
+            return len;
+        }
+        len += 1;
+    }
+    inp.len()
+}
+
+/// Whether the name of an argument can be a field of the `Args` struct.
+fn is_arg(name: &str) -> bool {
+    name.starts_with(|x: char| x.is_alphabetic() || x == '_')
+        && name.chars().all(|x| x.is_alphanumeric() || x == '_')
+        // the keywords that can't be raw identifiers
+        && !["_", "self", "Self", "super", "crate"].contains(&name)
+}
+
+/// Splits the columns of a group on the commas outside of quoted identifiers.
+fn split_columns(columns: &str) -> Vec<&str> {
+    let mut out = vec![];
+    let mut start = 0;
+    let mut idx = 0;
+    while let Some(x) = columns[idx..].chars().next() {
+        match x {
+            '"' => idx += quoted_len(&columns[idx..]),
+            ',' => {
+                out.push(&columns[start..idx]);
+                idx += 1;
+                start = idx;
+            }
+            _ => idx += x.len_utf8(),
+        }
+    }
+    out.push(&columns[start..]);
+    out
+}
+
+/// The name of the argument for a column of a group.
+///
+/// Quoted identifiers are converted to snake case, so `"Order Total"` becomes `order_total`.
+///
+/// ```
+/// assert_eq!(pg_named_args_core::column_arg("\"Order Total\""), "order_total");
+/// assert_eq!(pg_named_args_core::column_arg("location"), "location");
+/// ```
+pub fn column_arg(column: &str) -> String {
+    let Some(quoted) = column.strip_prefix('"').and_then(|x| x.strip_suffix('"')) else {
+        return column.to_owned();
+    };
+
+    let mut name = String::new();
+    for x in quoted.chars() {
+        if x.is_alphanumeric() {
+            name.extend(x.to_lowercase());
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    name.trim_end_matches('_').to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewrite_should_keep_the_names_after_an_error() {
+        let mut names = vec!["a".to_owned()];
+        let rewrite = rewrite("SELECT $b, $[c WHERE $a = $d", &mut names);
+        assert_eq!(rewrite.template, "SELECT $2, ");
+        assert_eq!(names, ["a", "b", "c", "d"]);
+        assert_eq!(rewrite.errors, ["expected closing `]`"]);
+    }
+
+    #[test]
+    fn rewrite_should_reject_invalid_and_shared_column_arguments() {
+        let mut names = vec![];
+        let rewrite = rewrite(
+            "INSERT INTO t($[\"2fa\", \"Order Total\", order_total]) VALUES($[..])",
+            &mut names,
+        );
+        assert_eq!(names, ["order_total"]);
+        assert_eq!(
+            rewrite.errors,
+            [
+                "the argument `2fa` of the column `\"2fa\"` is not an identifier",
+                "the columns `\"Order Total\"` and `order_total` have the same argument `order_total`",
+            ]
+        );
+    }
+
+    #[test]
+    fn rewrite_should_parse_the_defaults_of_fragments() {
+        let res = rewrite("SELECT 1 ${order_by = ORDER BY id } ${limit}", &mut vec![]);
+        assert_eq!(res.template, "SELECT 1 {} {}");
+        assert_eq!(
+            res.defaults,
+            [("order_by".to_owned(), "ORDER BY id".to_owned())]
+        );
+        assert!(res.errors.is_empty());
+
+        let res = rewrite("SELECT 1 ${filter = WHERE a = $a}", &mut vec![]);
+        assert_eq!(
+            res.errors,
+            ["the default of a fragment is not allowed to contain `$` or braces"]
+        );
+    }
+
+    #[test]
+    fn rewrite_should_append_fragments_to_groups() {
+        let mut names = vec![];
+        let res = rewrite(
+            "INSERT INTO t($[a, b, ${audit}]) VALUES($[.., ${audit_values} ])",
+            &mut names,
+        );
+        assert_eq!(res.template, "INSERT INTO t(a, b{}) VALUES($1, $2{})");
+        assert_eq!(
+            res.fragments,
+            [
+                ("audit".to_owned(), Slot::Items),
+                ("audit_values".to_owned(), Slot::Items)
+            ]
+        );
+        assert_eq!(names, ["a", "b"]);
+        assert!(res.errors.is_empty());
+    }
+
+    #[test]
+    fn rewrite_should_write_groups_with_optional_columns_at_runtime() {
+        let mut names = vec![];
+        let res = rewrite(
+            "INSERT INTO t($[a, \"B\"?]) VALUES($[..]) RETURNING $c",
+            &mut names,
+        );
+        assert_eq!(res.template, "INSERT INTO t({}) VALUES({}) RETURNING $2");
+        assert_eq!(
+            res.fragments,
+            [
+                (String::new(), Slot::Columns),
+                (String::new(), Slot::Values)
+            ]
+        );
+        let columns: Vec<_> = res
+            .columns
+            .iter()
+            .map(|x| (&*x.sql, &*x.arg, x.optional))
+            .collect();
+        assert_eq!(columns, [("a", "a", false), ("\"B\"", "b", true)]);
+        assert_eq!(names, ["a", "c"]);
+        assert!(res.errors.is_empty());
+    }
+
+    #[test]
+    fn rewrite_should_parse_the_kinds_of_fragments() {
+        let res = rewrite(
+            "SELECT * FROM t WHERE ${filter: where} ORDER BY ${order: order_by = id}",
+            &mut vec![],
+        );
+        assert_eq!(res.template, "SELECT * FROM t WHERE {} ORDER BY {}");
+        assert_eq!(
+            res.fragments,
+            [
+                ("filter".to_owned(), Slot::Where),
+                ("order".to_owned(), Slot::OrderBy)
+            ]
+        );
+        assert_eq!(res.defaults, [("order".to_owned(), "id".to_owned())]);
+
+        let res = rewrite("SELECT ${a: limit}", &mut vec![]);
+        assert_eq!(
+            res.errors,
+            ["unknown fragment kind `limit`, expected `order_by`, `where` or `ident`"]
+        );
+    }
+
+    #[test]
+    fn rewrite_should_expand_the_membership_modifiers() {
+        let mut names = vec![];
+        let res = rewrite(
+            "SELECT * FROM t WHERE a = $a~any AND b = $b~unnest AND c = $c~in",
+            &mut names,
+        );
+        assert_eq!(
+            res.template,
+            "SELECT * FROM t WHERE a = ANY($1) AND b = ANY(SELECT unnest($2)) AND c = ANY({})"
+        );
+        assert_eq!(res.fragments, [("c".to_owned(), Slot::List)]);
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(res.errors.is_empty());
+    }
+
+    #[test]
+    fn arguments_should_skip_schemas() {
+        let args: Vec<_> = arguments("SELECT $a FROM $tenant.reports WHERE b = $b_2").collect();
+        assert_eq!(args, [(7, "a"), (41, "b_2")]);
+    }
+}
@@ -1,16 +1,18 @@
 use std::collections::HashMap;
 
-use proc_macro2::{Ident, Span, TokenStream};
+use pg_named_args_core::{arguments, column_arg, modifier, quoted_len, Column, Slot};
+use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
 use quote::{quote, quote_spanned, ToTokens};
 use syn::{
-    braced,
+    braced, bracketed,
     ext::IdentExt,
-    parse::{Parse, ParseStream},
+    parenthesized,
+    parse::{Parse, ParseStream, Parser},
     parse2, parse_macro_input, parse_quote,
     punctuated::Punctuated,
     spanned::Spanned,
-    token::Brace,
-    ExprStruct, ItemStruct, LitStr, Member, Token,
+    token::{Brace, Bracket, Paren},
+    Expr, ExprStruct, FieldValue, ItemFn, ItemStruct, LitStr, Member, Pat, Token,
 };
 
 /// The macro returns a tuple containing the query and the parameter slice that
@@ -33,13 +35,233 @@ use syn::{
 /// ```
 #[proc_macro]
 pub fn query_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input_raw = TokenStream::from(input.clone());
     let format = parse_macro_input!(input as Format);
-    let mut errors = vec![];
+    if format.batch {
+        return batch_error(format.raw).into();
+    }
+    expand(format, Output::Args).into()
+}
 
-    let mut names = vec![];
-    let mut fragments = vec![];
-    let template = rewrite_query(format.template, &mut names, &mut errors, &mut fragments);
+/// The macro returns a tuple containing the query wrapped in `SELECT EXISTS(...)` and the
+/// parameter slice. The query returns a single row with a single boolean.
+///
+/// The syntax is the same as for [query_args!].
+///
+/// ```
+/// # use pg_named_args::exists_args;
+/// let name = "Fred";
+/// let (query, params) = exists_args!(r"SELECT 1 FROM flintstone WHERE name = $name", Args { name });
+/// assert_eq!(query, "SELECT EXISTS(SELECT 1 FROM flintstone WHERE name = $1)");
+/// ```
+/// ```ignore
+/// let exists: bool = txn.query_one(query, params).await?.get(0);
+/// ```
+#[proc_macro]
+pub fn exists_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let format = parse_macro_input!(input as Format);
+    if format.batch {
+        return batch_error(format.raw).into();
+    }
+    expand(format, Output::Exists).into()
+}
+
+/// The macro returns an array with a tuple of the query and the parameter slice for every
+/// statement. The statements are given between brackets and share a single `Args` struct,
+/// each statement only gets the parameters that it uses.
+///
+/// The syntax of the statements is the same as for [query_args!], options apply to all statements.
+///
+/// ```
+/// # use pg_named_args::batch_args;
+/// let name = "Fred";
+/// let town = "Bedrock";
+/// let statements = batch_args!(
+///     [
+///         r"INSERT INTO flintstone(name) VALUES($name)",
+///         r"INSERT INTO residence(name, town) VALUES($name, $town)",
+///         r"UPDATE town SET residents = residents + 1 WHERE name = $town",
+///     ],
+///     Args { name, town }
+/// );
+/// assert_eq!(statements[1].0, "INSERT INTO residence(name, town) VALUES($1, $2)");
+/// assert_eq!(statements[2].0, "UPDATE town SET residents = residents + 1 WHERE name = $1");
+/// ```
+/// ```ignore
+/// for (query, params) in statements {
+///     txn.execute(query, params).await?;
+/// }
+/// ```
+#[proc_macro]
+pub fn batch_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let format = parse_macro_input!(input as Format);
+    if !format.batch {
+        return syn::Error::new(Span::call_site(), "expected `[` with a list of statements")
+            .to_compile_error()
+            .into();
+    }
+    expand(format, Output::Args).into()
+}
+
+fn batch_error(raw: TokenStream) -> TokenStream {
+    syn::Error::new_spanned(raw, "multiple statements are supported by `batch_args!`")
+        .to_compile_error()
+}
+
+/// The macro returns a tuple containing an upsert and the parameter slice, the
+/// `ON CONFLICT ... DO UPDATE SET ...` clause is derived from the `$[...]` group of the insert.
+///
+/// The columns of the conflict target are given with the `conflict(...)` option, by the names
+/// of their arguments. The other columns of the group are updated with the values of the
+/// insert, and when all columns are part of the conflict target, the conflict is ignored with
+/// `DO NOTHING`. The clause is added before `RETURNING`, if the insert has one.
+/// The group can't have optional columns or a fragment, because the columns need to be known
+/// at compile time.
+///
+/// The syntax is otherwise the same as for [query_args!].
+///
+/// ```
+/// # use pg_named_args::upsert_args;
+/// let location = "sweden";
+/// let time = "monday";
+/// let report = "sunny";
+/// let (query, params) = upsert_args!(
+///     conflict(location, time),
+///     r"INSERT INTO weather_reports ($[location, time, report]) VALUES ($[..])",
+///     Args { location, time, report }
+/// );
+/// assert_eq!(
+///     query,
+///     "INSERT INTO weather_reports (location, time, report) VALUES ($1, $2, $3) \
+///     ON CONFLICT (location, time) DO UPDATE SET report = excluded.report"
+/// );
+/// ```
+/// ```ignore
+/// txn.execute(query, params).await?;
+/// ```
+#[proc_macro]
+pub fn upsert_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut format = parse_macro_input!(input as Format);
+    if format.batch {
+        return batch_error(format.raw).into();
+    }
+    let Some((_, keys)) = format.options.conflict.take() else {
+        let message = "expected the `conflict(...)` option with the columns of the conflict target";
+        return syn::Error::new(Span::call_site(), message)
+            .to_compile_error()
+            .into();
+    };
+    for template in &mut format.templates {
+        match upsert(&template.value(), &keys) {
+            Ok(value) => *template = LitStr::new(&value, template.span()),
+            Err(message) => {
+                return syn::Error::new(template.span(), message)
+                    .to_compile_error()
+                    .into()
+            }
+        }
+    }
+    expand(format, Output::Args).into()
+}
+
+/// The macro returns a tuple containing the columns of a `$[...]` group, the matching
+/// placeholders and the parameter slice, for statements that are assembled from pieces.
+///
+/// The query is only the group, its columns are listed like in the insert syntax of
+/// [query_args!], and the placeholders are the `$[..]` of the group. The columns need to be
+/// known at compile time, so optional columns and fragments are not supported.
+///
+/// ```
+/// # use pg_named_args::values_args;
+/// let location = "sweden";
+/// let report = "sunny";
+/// let (columns, values, params) = values_args!("$[location, report]", Args { location, report });
+/// assert_eq!(columns, "location, report");
+/// assert_eq!(values, "$1, $2");
+/// let query = format!("INSERT INTO weather_reports ({columns}) SELECT {values} WHERE NOT EXISTS (SELECT 1 FROM weather_reports)");
+/// ```
+/// ```ignore
+/// txn.execute(&query, params).await?;
+/// ```
+#[proc_macro]
+pub fn values_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let mut format = parse_macro_input!(input as Format);
+    if format.batch {
+        return batch_error(format.raw).into();
+    }
+    if let Some(name) = &format.options.prepare {
+        let message = "the `prepare` option is not supported by `values_args!`";
+        return syn::Error::new_spanned(name, message)
+            .to_compile_error()
+            .into();
+    }
+    for template in &mut format.templates {
+        let value = template.value();
+        let value = value.trim();
+        let group = value
+            .strip_prefix("$[")
+            .map(|x| fixed_columns(x, "values_args!"));
+        match group {
+            Some(Ok((columns, len))) if len + 3 == value.len() => {
+                // the columns are listed without the comments and the layout of the group
+                let value = format!("$[{}]{VALUES_MARKER}$[..]", columns.join(", "));
+                *template = LitStr::new(&value, template.span());
+            }
+            Some(Err(message)) => {
+                return syn::Error::new(template.span(), message)
+                    .to_compile_error()
+                    .into()
+            }
+            _ => {
+                let message = "expected only a `$[...]` group";
+                return syn::Error::new(template.span(), message)
+                    .to_compile_error()
+                    .into();
+            }
+        }
+    }
+    expand(format, Output::Values).into()
+}
+
+/// The macro returns a tuple containing a `Query` and the parameter slice.
+/// The `Query` contains the rewritten query together with information about the query,
+/// like its fingerprint.
+///
+/// The syntax is the same as for [query_args!].
+///
+/// ```
+/// # use pg_named_args::query;
+/// let name = "Fred";
+/// let (query, params) = query!(r"SELECT * FROM flintstone WHERE name = $name", Args { name });
+/// assert_eq!(query.sql(), "SELECT * FROM flintstone WHERE name = $1");
+/// ```
+/// ```ignore
+/// txn.query(query.sql(), params).await?;
+/// ```
+#[proc_macro]
+pub fn query(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let format = parse_macro_input!(input as Format);
+    if format.batch {
+        return batch_error(format.raw).into();
+    }
+    expand(format, Output::Query).into()
+}
+
+/// The different kinds of output generated by the macros.
+enum Output {
+    /// The query string and the parameters.
+    Args,
+    /// The `Query` struct and the parameters.
+    Query,
+    /// The query string wrapped in `SELECT EXISTS(...)` and the parameters.
+    Exists,
+    /// The columns and the values of a `$[...]` group, split at the [VALUES_MARKER], and the
+    /// parameters.
+    Values,
+}
+
+fn expand(mut format: Format, output: Output) -> TokenStream {
+    let input_raw = format.raw;
+    let mut errors = vec![];
 
     let mut args = HashMap::new();
     format
@@ -62,241 +284,2306 @@ pub fn query_args(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
                         ))
                     }
 
-                    inner.fields.into_iter().collect()
-                })
-                .unwrap_or_default();
+                    inner.fields.into_iter().collect()
+                })
+                .unwrap_or_default();
+
+            // something is always inserted here as a proof that rustc will check the struct fields.
+            if args.insert(x.name.to_string(), fields).is_some() {
+                errors.push(syn::Error::new_spanned(x.name, "duplicate struct name"));
+            }
+        });
+
+    let mut arg_fields = args.remove("Args");
+    let fragment_fields = args.remove("Sql");
+
+    // the fields of the structs are the union over all the statements.
+    let mut all_names = vec![];
+    let mut all_fragments = vec![];
+    let mut all_defaults = vec![];
+    let mut statements = vec![];
+
+    // the span of the first `$name` of every argument, or of the query when the compiler
+    // doesn't support spans inside of a string literal
+    let mut spans = HashMap::new();
+    // the line of the first `$name` of every argument, shown when hovering the argument
+    let mut usages = HashMap::new();
+    let query_span = format.templates.first().map(|x| x.span());
+    for template in &format.templates {
+        let value = template.value();
+        for (pos, name) in arguments(&value) {
+            usages
+                .entry(name.to_owned())
+                .or_insert_with(|| usage(&value, pos));
+        }
+        let token = template.token();
+        let source = token.to_string();
+        for (pos, name) in arguments(&source) {
+            let span = token.subspan(pos..pos + 1 + name.len());
+            spans
+                .entry(name.to_owned())
+                .or_insert_with(|| span.unwrap_or_else(|| template.span()));
+        }
+    }
+
+    // the expressions of `$(expr)` become arguments, which are bound like the fields of `Args`
+    let mut exprs = vec![];
+    let mut where_clauses = vec![];
+    let mut templates = vec![];
+    for template in format.templates {
+        if format.options.qmark && template.value().contains("$json{") {
+            let message = "`$json{...}` is not supported with the `qmark` option";
+            errors.push(syn::Error::new(template.span(), message));
+        }
+        let value = match json_objects(&template.value()) {
+            Ok(value) => value,
+            Err(message) => {
+                errors.push(syn::Error::new(template.span(), message));
+                // only the object is reported
+                template.value().replace("$json{", "json{")
+            }
+        };
+        let value = match expand_where_clauses(&value, &mut where_clauses) {
+            Ok(value) => value,
+            Err(message) => {
+                errors.push(syn::Error::new(template.span(), message));
+                // only the clause is reported
+                value.replace("$where{", "where{")
+            }
+        };
+        match inline_exprs(&value, &mut exprs) {
+            Ok(value) => templates.push(LitStr::new(&value, template.span())),
+            Err(message) => {
+                errors.push(syn::Error::new(template.span(), message));
+                // only the expression is reported
+                let value = value.replace("$(", "(");
+                templates.push(LitStr::new(&value, template.span()));
+            }
+        }
+    }
+    let mut expr_fields = vec![];
+    for (idx, expr) in exprs.iter().enumerate() {
+        let span = query_span.unwrap_or_else(Span::call_site);
+        // the errors of the expression are shown at the query
+        let tokens = syn::parse_str(expr).map(|tokens| respan(tokens, span));
+        match tokens.and_then(parse2::<Expr>) {
+            Ok(value) => expr_fields.push(FieldValue {
+                attrs: vec![],
+                member: Member::Named(Ident::new(&format!("{EXPR_ARG}{idx}"), span)),
+                colon_token: Some(Default::default()),
+                expr: value,
+            }),
+            Err(err) => {
+                let message = format!("expected an expression in `$({expr})`: {err}");
+                errors.push(syn::Error::new(span, message));
+            }
+        }
+    }
+    let has_args = arg_fields.is_some();
+    let capture = format.options.capture;
+    if !expr_fields.is_empty() {
+        arg_fields.get_or_insert_with(Vec::new).extend(expr_fields);
+    }
+    format.templates = templates;
+
+    // a query with `$when` and `$match` sections is expanded to a query for every combination
+    let mut choices = vec![];
+    let mut templates = vec![];
+    for template in format.templates {
+        match variants(&template.value()) {
+            Ok((template_choices, variants)) if !template_choices.is_empty() => {
+                if format.batch {
+                    let message = "`$when` and `$match` are not supported for multiple statements";
+                    errors.push(syn::Error::new(template.span(), message));
+                }
+                if let Some(name) = &format.options.prepare {
+                    let message = "the `prepare` option is not supported with `$when` and `$match`";
+                    errors.push(syn::Error::new_spanned(name, message));
+                }
+                choices = template_choices;
+                templates.extend(variants.iter().map(|x| LitStr::new(x, template.span())));
+            }
+            Ok(_) => templates.push(template),
+            Err(message) => {
+                errors.push(syn::Error::new(template.span(), message));
+                // keep the arguments of the sections, so only the section is reported
+                let mut value = template.value();
+                for start in ["$when(", "$match("] {
+                    for (pos, _) in value.match_indices(start) {
+                        let rest = &value[pos + start.len()..];
+                        let len = rest.find(|x: char| !x.is_alphanumeric() && x != '_');
+                        let arg = rest[..len.unwrap_or(rest.len())].to_owned();
+                        if !arg.is_empty() && !all_names.contains(&arg) {
+                            all_names.push(arg);
+                        }
+                    }
+                    value = value.replace(start, &start[1..]);
+                }
+                templates.push(LitStr::new(&value, template.span()));
+            }
+        }
+    }
+
+    if let Some((name, _)) = &format.options.conflict {
+        let message = "the `conflict` option is only supported by `upsert_args!`";
+        errors.push(syn::Error::new_spanned(name, message));
+    }
+    if let Some(name) = &format.options.template {
+        if !matches!(output, Output::Query) {
+            let message = "the `template` option is only supported by `query!`";
+            errors.push(syn::Error::new_spanned(name, message));
+        }
+    }
+    if let Some(name) = &format.options.array {
+        // the arms of the match need the same type
+        if !choices.is_empty() {
+            let message = "the `array` option is not supported with `$when` and `$match`";
+            errors.push(syn::Error::new_spanned(name, message));
+        }
+    }
+
+    if format.options.qmark {
+        let message = match () {
+            _ if format.batch => {
+                Some("multiple statements are not supported with the `qmark` option")
+            }
+            _ if !choices.is_empty() => {
+                Some("`$when` and `$match` are not supported with the `qmark` option")
+            }
+            _ => None,
+        };
+        if let Some(message) = message {
+            errors.push(syn::Error::new(Span::call_site(), message));
+        }
+        // only the last variant, which includes all `$when` sections, is expanded
+        // to prevent additional errors
+        if !choices.is_empty() {
+            all_names.extend(choices.drain(..).map(|choice| choice.arg));
+            templates.drain(..templates.len() - 1);
+        }
+        if let Some(name) = &format.options.prepare {
+            let message = "the `prepare` option is not supported with the `qmark` option";
+            errors.push(syn::Error::new_spanned(name, message));
+        }
+    }
+
+    for template in templates {
+        let mut template = template;
+        if format.options.dedent {
+            template = LitStr::new(&dedent(&template.value()), template.span());
+        }
+        if format.options.minify {
+            template = LitStr::new(&minify(&template.value()), template.span());
+        }
+        let original = template.value();
+        let keywords = keywords(&template.value());
+        if format.options.readonly {
+            if let Err(message) = check_read_only(&keywords) {
+                errors.push(syn::Error::new(template.span(), message));
+            }
+        }
+        if format.options.strict_insert && statement_kind(&keywords) == StatementKind::Insert {
+            if let Err(message) = check_insert_groups(&template.value()) {
+                errors.push(syn::Error::new(template.span(), message));
+            }
+        }
+        let kind = match statement_kind(&keywords) {
+            StatementKind::Select => quote!(Select),
+            StatementKind::Insert => quote!(Insert),
+            StatementKind::Update => quote!(Update),
+            StatementKind::Delete => quote!(Delete),
+            StatementKind::Other => quote!(Other),
+        };
+        if let Some(max) = format.options.max_rows {
+            if statement_kind(&keywords) == StatementKind::Select {
+                match cap_rows(&template.value(), max) {
+                    Ok(value) => template = LitStr::new(&value, template.span()),
+                    Err(message) => errors.push(syn::Error::new(template.span(), message)),
+                }
+            }
+        }
+        if let Output::Exists = output {
+            let inner = template.value();
+            let inner = inner.trim_end().trim_end_matches(';');
+            // the parenthesis goes on a new line when the query ends with a line comment
+            let separator = match inner.lines().next_back().unwrap_or_default().contains("--") {
+                true => "\n",
+                false => "",
+            };
+            template = LitStr::new(
+                &format!("SELECT EXISTS({inner}{separator})"),
+                template.span(),
+            );
+        }
+
+        let mut for_loop = match for_loop(&template.value()) {
+            Ok(for_loop) => for_loop,
+            Err(message) => {
+                errors.push(syn::Error::new(template.span(), message));
+                None
+            }
+        };
+        if let Some(parts) = &for_loop {
+            if format.batch {
+                let message = "`$for` is not supported for multiple statements";
+                errors.push(syn::Error::new(template.span(), message));
+            }
+            if let Some(name) = &format.options.prepare {
+                let message = "the `prepare` option is not supported with `$for`";
+                errors.push(syn::Error::new_spanned(name, message));
+            }
+            template = LitStr::new(&parts.outer, template.span());
+            if !all_names.contains(&parts.items) {
+                all_names.push(parts.items.clone());
+            }
+        }
+
+        let mut names = vec![];
+        let mut fragments = vec![];
+        let mut columns = vec![];
+        if format.options.qmark && has_modifier(&template.value()) {
+            let message = "modifiers are not supported with the `qmark` option";
+            errors.push(syn::Error::new(template.span(), message));
+        }
+        let mut template = rewrite_query(
+            template,
+            &mut names,
+            &mut errors,
+            &mut fragments,
+            &mut all_defaults,
+            &mut columns,
+        );
+        let optional: Vec<_> = columns.iter().filter(|x| x.optional).collect();
+        if !optional.is_empty() {
+            let unsupported = [
+                (format.options.qmark, "the `qmark` option"),
+                (format.options.prepare.is_some(), "the `prepare` option"),
+                (for_loop.is_some(), "`$for`"),
+            ];
+            for (_, name) in unsupported.iter().filter(|(x, _)| *x) {
+                let message = format!("optional columns are not supported with {name}");
+                errors.push(syn::Error::new(template.span(), message));
+            }
+        }
+        // a query written for another driver gets a server-side error otherwise
+        if names.is_empty() && !format.options.qmark && has_qmark_placeholder(&template.value()) {
+            let message = "`?` placeholders are not supported, use named arguments like \
+                `WHERE id = $id` with the values in an `Args` struct, \
+                the `qmark` option generates `?` placeholders from named arguments for other drivers";
+            errors.push(syn::Error::new(template.span(), message));
+        }
+        if format.options.qmark {
+            if for_loop.take().is_some() {
+                let message = "`$for` is not supported with the `qmark` option";
+                errors.push(syn::Error::new(template.span(), message));
+            }
+            let (qmark_template, occurrences) = qmark(&template.value(), &names);
+            template = LitStr::new(&qmark_template, template.span());
+            names = occurrences;
+        }
+
+        // the parameters of the body refer to the fields of the item or to the other arguments
+        let mut body = None;
+        if let Some(parts) = &for_loop {
+            let mut body_names = vec![];
+            let mut body_fragments = vec![];
+            let span = template.span();
+            let template = LitStr::new(&parts.body, span);
+            let template = rewrite_query(
+                template,
+                &mut body_names,
+                &mut errors,
+                &mut body_fragments,
+                &mut all_defaults,
+                &mut vec![],
+            );
+            if !body_fragments.is_empty() {
+                let message = "fragments and schemas are not supported in `$for`";
+                errors.push(syn::Error::new(span, message));
+            }
+
+            let mut fields = vec![];
+            let params: Vec<_> = body_names
+                .iter()
+                .map(|name| match name.strip_prefix(FOR_ITEM) {
+                    Some(field) => {
+                        fields.push(field.to_owned());
+                        BodyParam::Field(fields.len() - 1)
+                    }
+                    None => BodyParam::Outer(match names.iter().position(|x| x == name) {
+                        Some(idx) => idx,
+                        None => {
+                            names.push(name.clone());
+                            names.len() - 1
+                        }
+                    }),
+                })
+                .collect();
+            body = Some((template, fields, params));
+        }
+
+        // the fingerprint is of the statement itself, without the comment with the call site
+        // and the `PREPARE` of the options
+        let mut fingerprint_sql = unescape(&template.value());
+        if let Some((body, _, _)) = &body {
+            fingerprint_sql = fingerprint_sql.replace(FOR_MARKER, &unescape(&body.value()));
+        }
+        let fingerprint = fingerprint(&fingerprint_sql);
+        let statement = template.value();
+
+        if let Some(tags) = &format.options.comment {
+            let mut tags = tags.clone();
+            let call_site = proc_macro::Span::call_site();
+            tags.push((
+                "file".to_owned(),
+                format!("{}:{}", call_site.file(), call_site.line()),
+            ));
+            template = LitStr::new(&sqlcommenter(&template.value(), tags), template.span());
+        }
+
+        let mut execute = None;
+        if let Some(name) = &format.options.prepare {
+            let args: Vec<_> = (1..=names.len()).map(|idx| format!("${idx}")).collect();
+            execute = Some(match args.is_empty() {
+                true => format!("EXECUTE {name}"),
+                false => format!("EXECUTE {name}({})", args.join(", ")),
+            });
+            let prepare = format!("PREPARE {name} AS {}", template.value());
+            template = LitStr::new(&prepare, template.span());
+        }
+
+        // this will only be a list of the fields that actually exist.
+        // if not all fields are specified it is a struct init error.
+        let params: Vec<_> = names
+            .iter()
+            .filter_map(|search| arg_expr(arg_fields.as_deref(), capture, search))
+            .map(|res| {
+                // Use res.span() so that ToSql errors are shown at the argument.
+                let res =
+                    quote_spanned!(res.span()=> &#res as &dyn ::pg_named_args::__private::Param);
+                // Cast to &dyn without span to hide unnecessary cast warning
+                quote!(#res as &(dyn ::pg_named_args::postgres_types::ToSql + Sync))
+            })
+            .collect();
+
+        let origins = matches!(output, Output::Query);
+        let fragment_args: Vec<_> = fragments
+            .iter()
+            .filter_map(|(search, slot)| {
+                match slot {
+                    Slot::Columns => {
+                        return optional_columns(&columns, arg_fields.as_deref(), capture)
+                    }
+                    Slot::Values => {
+                        return optional_values(&columns, &names, arg_fields.as_deref(), capture)
+                    }
+                    Slot::List => {
+                        let res = arg_expr(arg_fields.as_deref(), capture, search)?;
+                        let idx = names.iter().position(|x| x == search)?;
+                        let param = format!("${}", idx + 1);
+                        let unnest_above = format.options.unnest_above.unwrap_or(UNNEST_ABOVE);
+                        return Some(
+                            quote_spanned!(res.span()=> ::pg_named_args::__private::list(
+                                &#res,
+                                #param,
+                                #unnest_above,
+                            )),
+                        );
+                    }
+                    _ => {}
+                }
+                if let Some(idx) = search.strip_prefix(WHERE_CLAUSE) {
+                    // the index of the clause is set by `where_clauses`
+                    let clause = &where_clauses[idx.parse::<usize>().unwrap()];
+                    let conditions = clause
+                        .fragments
+                        .iter()
+                        .map(|(search, slot)| {
+                            fragment_arg(
+                                search,
+                                slot,
+                                fragment_fields.as_deref(),
+                                &all_defaults,
+                                origins,
+                            )
+                        })
+                        .collect::<Option<Vec<_>>>()?;
+                    let has_conditions = clause.has_conditions;
+                    return Some(quote!(::pg_named_args::__private::conditions(
+                        #has_conditions,
+                        &[#(#conditions),*],
+                    )));
+                }
+                fragment_arg(
+                    search,
+                    slot,
+                    fragment_fields.as_deref(),
+                    &all_defaults,
+                    origins,
+                )
+            })
+            .collect();
+
+        if cfg!(feature = "manifest") {
+            let call_site = proc_macro::Span::call_site();
+            let call_site = format!("{}:{}", call_site.file(), call_site.line());
+            let name = format.options.prepare.as_ref().map(Ident::to_string);
+            let names: Vec<_> = names
+                .iter()
+                .map(|x| display_name(x, &exprs).to_owned())
+                .collect();
+            let (mut sql, slots) = mark_slots(&statement, &fragments);
+            if let Some((body, _, _)) = &body {
+                sql = sql.replace(FOR_MARKER, &unescape(&body.value()));
+            }
+            let entry = manifest_entry(
+                name.as_deref(),
+                &sql,
+                &slots,
+                &names,
+                &call_site,
+                fingerprint,
+            );
+            let file = proc_macro::Span::call_site().file();
+            if let Err(err) = write_manifest(&file, fingerprint, &entry) {
+                let message = format!("failed to write the query manifest: {err}");
+                errors.push(syn::Error::new(template.span(), message));
+            }
+        }
+
+        let mut sql = quote!(#template);
+        // prevent additional errors when the Sql struct is not complete yet
+        if fragment_args.len() == fragments.len() {
+            sql = quote!(::std::format!(#template #(,#fragment_args)*));
+        }
+        if let Output::Query = output {
+            if fragments.is_empty() {
+                sql =
+                    LitStr::new(&unescape(&template.value()), template.span()).into_token_stream();
+            }
+        }
+
+        // the body is repeated for every item, with the parameters of the items after the others
+        let items = for_loop.take().zip(body).and_then(|(parts, body)| {
+            Some((
+                arg_expr(arg_fields.as_deref(), capture, &parts.items)?,
+                parts,
+                body,
+            ))
+        });
+        // the parameters of the items or of the optional columns are after the others
+        let mut extra_params = None;
+        if let Some((items, parts, (body, fields, body_params))) = items {
+            let item = Ident::new(&parts.item, Span::call_site());
+            let separator = &parts.separator;
+            let count = fields.len();
+            let assignments = body_params.iter().enumerate().map(|(idx, param)| {
+                let name = Ident::new(&format!("p{}", idx + 1), Span::call_site());
+                match param {
+                    BodyParam::Outer(outer) => {
+                        let number = outer + 1;
+                        quote!(#name = #number)
+                    }
+                    BodyParam::Field(field) => {
+                        let number = names.len() + field + 1;
+                        quote!(#name = __idx * #count + #number)
+                    }
+                }
+            });
+            let body = LitStr::new(&body_placeholders(&body.value()), body.span());
+            sql = quote!(::std::primitive::str::replacen(
+                &#sql,
+                #FOR_MARKER,
+                &{
+                    let mut sql = ::std::string::String::new();
+                    for __idx in 0..(#items).len() {
+                        if __idx > 0 {
+                            sql.push_str(#separator);
+                        }
+                        sql.push_str(&::std::format!(#body #(,#assignments)*));
+                    }
+                    sql
+                },
+                1,
+            ));
+
+            let fields = fields.iter().map(|field| {
+                if field.is_empty() {
+                    return quote!(#item as &(dyn ::pg_named_args::postgres_types::ToSql + Sync));
+                }
+                match syn::parse_str::<Member>(field) {
+                    Ok(field) => quote!(&#item.#field as &(dyn ::pg_named_args::postgres_types::ToSql + Sync)),
+                    Err(_) => {
+                        let message = format!("expected a field name after `${}.`", parts.item);
+                        syn::Error::new(template.span(), message).to_compile_error()
+                    }
+                }
+            });
+            extra_params = Some(quote!(::std::iter::Iterator::flat_map(
+                (#items).iter(),
+                |#item| [#(#fields),*],
+            )));
+        }
+
+        // the names of the `$(expr)` arguments are the expressions
+        let display_names: Vec<_> = names.iter().map(|x| display_name(x, &exprs)).collect();
+        let original = restore_exprs(&original, &exprs);
+        let query = match output {
+            Output::Args | Output::Exists => quote!(&#sql),
+            Output::Values => {
+                // the group can't have fragments, so the parts are known at compile time
+                let sql = unescape(&template.value());
+                let (columns, values) = sql.split_once(VALUES_MARKER).unwrap_or((&sql, ""));
+                quote!(#columns, #values)
+            }
+            Output::Query => {
+                let kind = match format.options.prepare {
+                    Some(_) => quote!(Other),
+                    None => kind.clone(),
+                };
+                let query = quote!(::pg_named_args::Query::new_unchecked(
+                    #sql,
+                    #fingerprint,
+                    &[#(#display_names),*],
+                    ::std::concat!(::std::file!(), ":", ::std::line!()),
+                    ::pg_named_args::StatementKind::#kind,
+                ));
+                let query = match format.options.template {
+                    Some(_) => quote!(::pg_named_args::Query::with_template(#query, #original)),
+                    None => query,
+                };
+                match !fragments.is_empty() && fragment_args.len() == fragments.len() {
+                    true => quote!({
+                        #[allow(unused_mut)]
+                        let mut __origins = ::std::vec::Vec::<&'static str>::new();
+                        ::pg_named_args::Query::with_fragment_origins(#query, __origins)
+                    }),
+                    false => query,
+                }
+            }
+        };
+
+        let execute = execute.map(|execute| match output {
+            Output::Args | Output::Exists | Output::Values => quote!(#execute),
+            Output::Query => {
+                let fingerprint = self::fingerprint(&execute);
+                quote!(::pg_named_args::Query::new_unchecked(
+                    #execute,
+                    #fingerprint,
+                    &[#(#display_names),*],
+                    ::std::concat!(::std::file!(), ":", ::std::line!()),
+                    ::pg_named_args::StatementKind::#kind,
+                ))
+            }
+        });
+
+        let optional_params: Option<Vec<_>> = optional
+            .iter()
+            .map(|column| {
+                let res = arg_expr(arg_fields.as_deref(), capture, &column.arg)?;
+                let res = quote_spanned!(res.span()=> ::std::option::Option::as_ref(&#res));
+                Some(quote!(::std::option::Option::map(#res, |x| {
+                    x as &(dyn ::pg_named_args::postgres_types::ToSql + Sync)
+                })))
+            })
+            .collect();
+        if let Some(optional_params) = optional_params.filter(|x| !x.is_empty()) {
+            extra_params = Some(quote!(::std::iter::Iterator::flatten(
+                ::std::iter::IntoIterator::into_iter([#(#optional_params),*]),
+            )));
+        }
+
+        let params = match (names.is_empty(), extra_params) {
+            // the parameters of other drivers are given as a tuple of references
+            _ if format.options.qmark => {
+                if let Some(buffer) = &format.options.buffer {
+                    let message = "the `buffer` option is not supported with the `qmark` option";
+                    errors.push(syn::Error::new_spanned(buffer, message));
+                }
+                if let Some(name) = &format.options.array {
+                    let message = "the `array` option is not supported with the `qmark` option";
+                    errors.push(syn::Error::new_spanned(name, message));
+                }
+                let params = names
+                    .iter()
+                    .filter_map(|search| arg_expr(arg_fields.as_deref(), capture, search))
+                    .map(|res| quote_spanned!(res.span()=> &#res));
+                quote!((#(#params,)*))
+            }
+            // the length of the array is known at compile time
+            (empty, extra_params) if format.options.array.is_some() => {
+                let name = format.options.array.as_ref().unwrap();
+                if extra_params.is_some() {
+                    let message = "the `array` option needs a fixed number of parameters, \
+                        which `$for` and optional columns don't have";
+                    errors.push(syn::Error::new_spanned(name, message));
+                }
+                if let Some(buffer) = &format.options.buffer {
+                    let message = "the `buffer` option is not supported with the `array` option";
+                    errors.push(syn::Error::new_spanned(buffer, message));
+                }
+                match empty {
+                    true => quote!([] as [&(dyn ::pg_named_args::postgres_types::ToSql + Sync); 0]),
+                    false => quote!([#(#params),*]),
+                }
+            }
+            // the buffer is cleared and filled, so its allocation is reused by the next call
+            (empty, extra_params) if format.options.buffer.is_some() => {
+                let buffer = format.options.buffer.as_ref().unwrap();
+                let params =
+                    (!empty).then(|| quote!(::std::iter::Extend::extend(buffer, [#(#params),*]);));
+                let extra_params =
+                    extra_params.map(|x| quote!(::std::iter::Extend::extend(buffer, #x);));
+                quote!({
+                    let buffer = &mut *#buffer;
+                    buffer.clear();
+                    #params
+                    #extra_params
+                    &buffer[..]
+                })
+            }
+            (_, Some(extra_params)) => quote!(&::std::iter::Iterator::collect::<
+                ::std::vec::Vec<&(dyn ::pg_named_args::postgres_types::ToSql + Sync)>,
+            >(::std::iter::Iterator::chain(
+                ::std::iter::IntoIterator::into_iter([#(#params),*]),
+                #extra_params,
+            ))[..]),
+            // the type of an empty array can't be inferred
+            (true, None) => {
+                quote!(&[] as &[&(dyn ::pg_named_args::postgres_types::ToSql + Sync); 0])
+            }
+            (false, None) => quote!(&[#(#params),*]),
+        };
+        statements.push((query, execute, params));
+        for name in names {
+            if !all_names.contains(&name) {
+                all_names.push(name);
+            }
+        }
+        for column in optional {
+            if !all_names.contains(&column.arg) {
+                all_names.push(column.arg.clone());
+            }
+        }
+        for fragment in fragments {
+            if let Slot::Columns | Slot::Values | Slot::List = fragment.1 {
+                continue;
+            }
+            if let Some(idx) = fragment.0.strip_prefix(WHERE_CLAUSE) {
+                let clause = &where_clauses[idx.parse::<usize>().unwrap()];
+                for fragment in &clause.fragments {
+                    if !all_fragments.iter().any(|(x, _)| *x == fragment.0) {
+                        all_fragments.push(fragment.clone());
+                    }
+                }
+                continue;
+            }
+            // a fragment can be used in a group and elsewhere in the query
+            if !all_fragments.iter().any(|(x, _)| *x == fragment.0) {
+                all_fragments.push(fragment);
+            }
+        }
+    }
+
+    for choice in &choices {
+        if !all_names.contains(&choice.arg) {
+            all_names.push(choice.arg.clone());
+        }
+    }
+
+    // the expressions are not fields of `Args`
+    all_names.retain(|name| !name.starts_with(EXPR_ARG));
+    // names that only differ by case or underscores are almost always a typo
+    let mut similar = false;
+    for (idx, name) in all_names.iter().enumerate() {
+        let other = all_names[..idx]
+            .iter()
+            .find(|x| fold_name(x) == fold_name(name));
+        if let Some(other) = other {
+            let span = spans.get(name).copied().unwrap_or_else(Span::call_site);
+            let message = format!(
+                "the arguments `${other}` and `${name}` only differ by case or underscores"
+            );
+            errors.push(syn::Error::new(span, message));
+            similar = true;
+        }
+    }
+    for field in arg_fields.as_deref().unwrap_or_default() {
+        let Member::Named(field) = &field.member else {
+            continue;
+        };
+        let field_name = field.unraw().to_string();
+        if field_name.starts_with(EXPR_ARG) || all_names.contains(&field_name) {
+            continue;
+        }
+        if let Some(name) = all_names
+            .iter()
+            .find(|x| fold_name(x) == fold_name(&field_name))
+        {
+            let message = format!(
+                "the field `{field_name}` only differs by case or underscores from the argument `${name}`"
+            );
+            errors.push(syn::Error::new_spanned(field, message));
+            similar = true;
+        }
+    }
+    // the captured locals are not fields of `Args`
+    if capture {
+        all_names.retain(|name| {
+            let fields = arg_fields.as_deref().unwrap_or_default();
+            find_field(fields, name).is_some()
+        });
+    }
+    if !has_args && !all_names.is_empty() {
+        errors.push(syn::Error::new(Span::call_site(), "expected `Args` struct"));
+    }
+    let required = all_fragments
+        .iter()
+        .any(|(name, _)| !all_defaults.iter().any(|(x, _)| x == name));
+    if fragment_fields.is_none() && required {
+        errors.push(syn::Error::new(Span::call_site(), "expected `Sql` struct"));
+    }
+
+    for key in args.keys() {
+        errors.push(syn::Error::new(
+            Span::call_site(),
+            format!("unknown struct name `{key}`"),
+        ));
+    }
+
+    // the columns of a group and the arguments of sections don't have a `$name`
+    for name in &all_names {
+        if let Some(span) = query_span {
+            spans.entry(name.clone()).or_insert(span);
+        }
+    }
+    let def = struct_def(&all_names, &spans, &usages);
+    let def2 = struct_def2(&all_fragments);
+    // the fragments with a default can be left out of `Sql`
+    let omitted: Vec<_> = all_defaults
+        .iter()
+        .map(|(name, _)| name)
+        .filter(|name| {
+            let fields = fragment_fields.as_deref().unwrap_or_default();
+            find_field(fields, name).is_none()
+        })
+        .collect();
+    let input_raw = with_defaults(input_raw, &omitted);
+    // the missing and unknown fields of a similar name would only repeat the error above
+    let check = (!similar).then(|| quote!((#input_raw);));
+
+    // the statements need the same type in an array or in the arms of a match
+    let unify = |(query, _, params): (TokenStream, Option<TokenStream>, TokenStream)| {
+        let query = match output {
+            Output::Args | Output::Exists => quote!(#query as &str),
+            Output::Query | Output::Values => query,
+        };
+        quote!((#query, #params as &[&(dyn ::pg_named_args::postgres_types::ToSql + Sync)]))
+    };
+
+    let output = if format.batch {
+        if let Some(name) = format.options.prepare {
+            errors.push(syn::Error::new_spanned(
+                name,
+                "the `prepare` option is not supported for multiple statements",
+            ));
+        }
+        if let Some(buffer) = format.options.buffer {
+            errors.push(syn::Error::new_spanned(
+                buffer,
+                "the `buffer` option is not supported for multiple statements",
+            ));
+        }
+        if let Some(name) = format.options.array {
+            errors.push(syn::Error::new_spanned(
+                name,
+                "the `array` option is not supported for multiple statements",
+            ));
+        }
+        let statements = statements.into_iter().map(unify);
+        quote!([#(#statements),*])
+    } else if !choices.is_empty() {
+        let values: Option<Vec<_>> = choices
+            .iter()
+            .map(|choice| arg_expr(arg_fields.as_deref(), capture, &choice.arg))
+            .collect();
+        match values {
+            Some(values) => {
+                let values = values.iter().map(|x| quote_spanned!(x.span()=> &#x));
+                let arms = statements.into_iter().enumerate().map(|(idx, statement)| {
+                    let mut stride = 1;
+                    let mut patterns = vec![];
+                    for choice in &choices {
+                        let arm = idx / stride % choice.patterns.len();
+                        stride *= choice.patterns.len();
+                        // the patterns are validated when they are parsed
+                        patterns.push(choice.patterns[arm].parse::<TokenStream>().unwrap());
+                    }
+                    let statement = unify(statement);
+                    quote!((#(#patterns,)*) => #statement)
+                });
+                quote!(match (#(#values,)*) {
+                    #(#arms,)*
+                })
+            }
+            // prevent additional errors when the Args struct is not complete yet
+            None => unify(statements.swap_remove(0)),
+        }
+    } else {
+        let (query, execute, params) = statements.pop().expect("a single template is parsed");
+        match execute {
+            Some(execute) => quote!((#query, #execute, #params)),
+            None => quote!((#query, #params)),
+        }
+    };
+
+    let errors = errors.into_iter().map(|err| err.to_compile_error());
+    quote!({
+        #(#errors;)*
+        #[allow(unreachable_code)]
+        if false {
+            unreachable!();
+            #def;
+            #def2;
+            #check
+        }
+        #output
+    })
+}
+
+/// Adds the omitted fragments with a default to the `Sql` struct literal of the input, so
+/// rustc still checks the other fields.
+fn with_defaults(raw: TokenStream, omitted: &[&String]) -> TokenStream {
+    let mut sql = false;
+    raw.into_iter()
+        .map(|tree| match tree {
+            TokenTree::Group(group) if sql && group.delimiter() == Delimiter::Brace => {
+                sql = false;
+                let mut inner = group.stream();
+                let comma = match inner.clone().into_iter().last() {
+                    Some(TokenTree::Punct(x)) => x.as_char() == ',',
+                    last => last.is_none(),
+                };
+                if !comma {
+                    inner.extend(quote!(,));
+                }
+                for name in omitted {
+                    let name = Ident::new_raw(name, group.span());
+                    inner.extend(quote!(#name: ::std::default::Default::default(),));
+                }
+                let mut res = Group::new(Delimiter::Brace, inner);
+                res.set_span(group.span());
+                TokenTree::Group(res)
+            }
+            tree => {
+                sql = matches!(&tree, TokenTree::Ident(x) if x == "Sql");
+                tree
+            }
+        })
+        .collect()
+}
+
+/// The columns of the group with optional columns that are written, an optional column is
+/// written when its argument is `Some`.
+fn optional_columns(
+    columns: &[Column],
+    fields: Option<&[FieldValue]>,
+    capture: bool,
+) -> Option<TokenStream> {
+    let columns = columns
+        .iter()
+        .map(|column| {
+            let sql = &column.sql;
+            let written = match column.optional {
+                true => {
+                    let res = arg_expr(fields, capture, &column.arg)?;
+                    quote_spanned!(res.span()=> ::std::option::Option::is_some(&#res))
+                }
+                false => quote!(true),
+            };
+            Some(quote!((#sql, #written)))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(quote!(::pg_named_args::__private::columns(&[#(#columns),*])))
+}
+
+/// The placeholders of the columns of [optional_columns], the optional columns get the
+/// parameters after the other parameters.
+fn optional_values(
+    columns: &[Column],
+    names: &[String],
+    fields: Option<&[FieldValue]>,
+    capture: bool,
+) -> Option<TokenStream> {
+    let values = columns
+        .iter()
+        .map(|column| {
+            Some(match column.optional {
+                true => {
+                    let res = arg_expr(fields, capture, &column.arg)?;
+                    let written =
+                        quote_spanned!(res.span()=> ::std::option::Option::is_some(&#res));
+                    quote!((::std::option::Option::None, #written))
+                }
+                false => {
+                    let number = names.iter().position(|x| *x == column.arg)? + 1;
+                    quote!((::std::option::Option::Some(#number), true))
+                }
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let count = names.len();
+    Some(quote!(::pg_named_args::__private::values(#count, &[#(#values),*])))
+}
+
+/// The argument of the `format!` of the query for a fragment, from the `Sql` struct or from
+/// the default of the fragment.
+fn fragment_arg(
+    search: &str,
+    slot: &Slot,
+    fields: Option<&[FieldValue]>,
+    defaults: &[(String, String)],
+    origins: bool,
+) -> Option<TokenStream> {
+    let field = fields.and_then(|fields| find_field(fields, search));
+    let res = match field {
+        Some(res) => res,
+        None => {
+            let (_, default) = defaults.iter().find(|(x, _)| x == search)?;
+            let default = match slot {
+                Slot::Items if !default.is_empty() => format!(", {default}"),
+                _ => default.clone(),
+            };
+            return Some(quote!(#default));
+        }
+    };
+    // the call sites of the fragments are recorded in `__origins` for `Query::fragment_origins`
+    let res = match origins {
+        true => {
+            quote_spanned!(res.span()=> ::pg_named_args::__private::record(&#res, &mut __origins))
+        }
+        false => quote_spanned!(res.span()=> &#res),
+    };
+    Some(match slot {
+        Slot::Fragment => {
+            quote_spanned!(res.span()=> ::pg_named_args::Fragment::get(#res))
+        }
+        Slot::Schema => {
+            quote_spanned!(res.span()=> ::pg_named_args::SchemaName::quoted(#res))
+        }
+        Slot::Items => {
+            quote_spanned!(res.span()=> ::pg_named_args::Fragment::items(#res))
+        }
+        Slot::OrderBy => {
+            quote_spanned!(res.span()=> ::pg_named_args::OrderByFragment::get(#res))
+        }
+        Slot::Where => {
+            quote_spanned!(res.span()=> ::pg_named_args::WhereFragment::get(#res))
+        }
+        Slot::Ident => {
+            quote_spanned!(res.span()=> ::pg_named_args::IdentFragment::get(#res))
+        }
+        // the groups and lists are written by the runtime crate
+        _ => unreachable!("slot {slot:?} is not a field of the `Sql` struct"),
+    })
+}
+
+/// Finds the expression of the named field in a struct literal.
+fn find_field(fields: &[FieldValue], search: &str) -> Option<Expr> {
+    fields.iter().find_map(|field| {
+        let Member::Named(name) = &field.member else {
+            return None;
+        };
+        (name.unraw() == *search).then_some(field.expr.clone())
+    })
+}
+
+/// The name without case and underscores, to find names that only differ by those.
+fn fold_name(name: &str) -> String {
+    name.chars()
+        .filter(|&x| x != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The expression of the argument, which is the local variable with the same name when it is
+/// not a field of `Args` in the `capture` mode.
+fn arg_expr(fields: Option<&[FieldValue]>, capture: bool, search: &str) -> Option<Expr> {
+    let field = fields.and_then(|fields| find_field(fields, search));
+    field.or_else(|| {
+        let local = Ident::new_raw(search, Span::call_site());
+        capture.then(|| parse_quote!(#local))
+    })
+}
+
+/// Expands every `$json{name, ..}` to `jsonb_build_object('name', $name, ..)`.
+fn json_objects(inp: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = inp;
+    while let Some(pos) = rest.find("$json{") {
+        out.push_str(&rest[..pos]);
+        let inner = &rest[pos + "$json{".len()..];
+        let len = inner.find('}').ok_or("unclosed `$json{`, expected `}`")?;
+        let names: Vec<_> = inner[..len].split(',').map(str::trim).collect();
+        // like in Rust lists, the names may end with a trailing comma
+        let names = match names.split_last() {
+            Some((&"", init)) if !init.is_empty() => init,
+            _ => &names,
+        };
+        let mut pairs = vec![];
+        for name in names {
+            let valid = name.starts_with(|x: char| x.is_alphabetic() || x == '_')
+                && name.chars().all(|x| x.is_alphanumeric() || x == '_');
+            if !valid {
+                return Err(format!("expected names in `$json{{...}}`, found `{name}`"));
+            }
+            pairs.push(format!("'{name}', ${name}"));
+        }
+        out.push_str(&format!("jsonb_build_object({})", pairs.join(", ")));
+        rest = &inner[len + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// The prefix of the names of the fragments of the `$where{...}` clauses, followed by the
+/// index of the clause.
+const WHERE_CLAUSE: &str = "__where_";
+
+/// A `$where{...}` clause, of which the conditions that are a single fragment are left out
+/// when the fragment is empty.
+struct WhereClause {
+    /// Whether the clause has conditions that are always included.
+    has_conditions: bool,
+    /// The fragments of the conditions that can be left out.
+    fragments: Vec<(String, Slot)>,
+}
+
+/// Expands every `$where{a AND ${b} AND ...}` to the `WHERE` clause with the conditions that
+/// are always included, followed by a fragment that adds the other conditions at runtime.
+fn expand_where_clauses(inp: &str, clauses: &mut Vec<WhereClause>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = inp;
+    while let Some(pos) = rest.find("$where{") {
+        out.push_str(&rest[..pos]);
+        let inner = &rest[pos + "$where{".len()..];
+        let len = closing_brace(inner).ok_or("unclosed `$where{`, expected `}`")?;
+        let mut conditions = vec![];
+        let mut fragments = vec![];
+        for condition in split_and(&inner[..len]) {
+            let condition = condition.trim();
+            match condition_fragment(condition) {
+                Some(fragment) => fragments.push(fragment),
+                None if condition.is_empty() => {
+                    return Err("expected a condition between every `AND` in `$where{...}`".into())
+                }
+                None => conditions.push(condition),
+            }
+        }
+        if !conditions.is_empty() {
+            // like the fragments, so an `OR` of a condition can't escape the other conditions
+            let conditions: Vec<_> = conditions.iter().map(|x| format!("({x})")).collect();
+            out.push_str(&format!("WHERE {}", conditions.join(" AND ")));
+        }
+        out.push_str(&format!("${{{WHERE_CLAUSE}{}}}", clauses.len()));
+        clauses.push(WhereClause {
+            has_conditions: !conditions.is_empty(),
+            fragments,
+        });
+        rest = &inner[len + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Splits the conditions of a `$where{...}` clause on the `AND`s outside of parentheses and
+/// quotes.
+fn split_and(inp: &str) -> Vec<&str> {
+    let mut out = vec![];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    for (pos, x) in inp.char_indices() {
+        match (quote, x) {
+            (Some(q), _) if x == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(x),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, _) if depth == 0 && pos >= start => {
+                let word = inp[pos..]
+                    .get(..3)
+                    .is_some_and(|x| x.eq_ignore_ascii_case("and"));
+                let is_ident = |x: Option<char>| x.is_some_and(|x| x.is_alphanumeric() || x == '_');
+                if word
+                    && !is_ident(inp[..pos].chars().next_back())
+                    && !is_ident(inp[pos + 3..].chars().next())
+                {
+                    out.push(&inp[start..pos]);
+                    start = pos + 3;
+                }
+            }
+            _ => {}
+        }
+    }
+    out.push(&inp[start..]);
+    out
+}
+
+/// The fragment of a condition that is a single `${name}` or `${name: where}` fragment.
+fn condition_fragment(condition: &str) -> Option<(String, Slot)> {
+    let inner = condition.strip_prefix("${")?.strip_suffix('}')?;
+    let (name, slot) = match inner.split_once(':') {
+        Some((name, kind)) if kind.trim() == "where" => (name, Slot::Where),
+        Some(_) => return None,
+        None => (inner, Slot::Fragment),
+    };
+    let name = name.trim();
+    let valid = name.starts_with(|x: char| x.is_alphabetic() || x == '_')
+        && name.chars().all(|x| x.is_alphanumeric() || x == '_');
+    valid.then(|| (name.to_owned(), slot))
+}
+
+/// The prefix of the names of the `$(expr)` arguments.
+const EXPR_ARG: &str = "__expr_";
+
+/// Replaces every `$(expr)` with an argument named after the index of the expression.
+///
+/// The same expression is the same argument.
+fn inline_exprs(inp: &str, exprs: &mut Vec<String>) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = inp;
+    while let Some(pos) = rest.find("$(") {
+        out.push_str(&rest[..pos]);
+        let inner = &rest[pos + 2..];
+        let len = closing_paren(inner).ok_or("unclosed `$(`, expected `$(expr)`")?;
+        let expr = inner[..len].trim();
+        if expr.is_empty() {
+            return Err("expected an expression in `$()`".to_owned());
+        }
+        let idx = match exprs.iter().position(|x| x == expr) {
+            Some(idx) => idx,
+            None => {
+                exprs.push(expr.to_owned());
+                exprs.len() - 1
+            }
+        };
+        out.push_str(&format!("${EXPR_ARG}{idx}"));
+        rest = &inner[len + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Replaces the `$(expr)` arguments with their expressions again.
+fn restore_exprs(inp: &str, exprs: &[String]) -> String {
+    let mut out = inp.to_owned();
+    // the highest index first, so `__expr_1` doesn't replace a part of `__expr_10`
+    for (idx, expr) in exprs.iter().enumerate().rev() {
+        out = out.replace(&format!("${EXPR_ARG}{idx}"), &format!("$({expr})"));
+    }
+    out
+}
+
+/// The name of the argument, which is the expression for a `$(expr)` argument.
+fn display_name<'a>(name: &'a str, exprs: &'a [String]) -> &'a str {
+    let idx = name
+        .strip_prefix(EXPR_ARG)
+        .and_then(|x| x.parse::<usize>().ok());
+    idx.and_then(|idx| exprs.get(idx))
+        .map_or(name, String::as_str)
+}
+
+/// The position of the parenthesis closing the expression, skipping the Rust literals.
+fn closing_paren(inp: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut chars = inp.char_indices();
+    while let Some((pos, x)) = chars.next() {
+        match x {
+            '(' | '[' | '{' => depth += 1,
+            ')' if depth == 0 => return Some(pos),
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            '"' => {
+                while let Some((_, x)) = chars.next() {
+                    match x {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Sets the span of all the tokens.
+fn respan(tokens: TokenStream, span: Span) -> TokenStream {
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            if let proc_macro2::TokenTree::Group(group) = &token {
+                let mut inner =
+                    proc_macro2::Group::new(group.delimiter(), respan(group.stream(), span));
+                inner.set_span(span);
+                token = inner.into();
+            } else {
+                token.set_span(span);
+            }
+            token
+        })
+        .collect()
+}
+
+/// Undoes the escaping of braces in the rewritten template.
+///
+/// The placeholders of the fragments remain as `{}`.
+fn unescape(template: &str) -> String {
+    template.replace("{{", "{").replace("}}", "}")
+}
+
+/// Calculates the 64 bit FNV-1a hash, this must match `pg_named_args::fingerprint`.
+fn fingerprint(sql: &str) -> u64 {
+    sql.bytes().fold(0xcbf29ce484222325, |hash, x| {
+        (hash ^ u64::from(x)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The line of the query around the `$` at `pos`, with the whitespace collapsed.
+fn usage(inp: &str, pos: usize) -> String {
+    let start = inp[..pos].rfind('\n').map_or(0, |x| x + 1);
+    let end = inp[pos..].find('\n').map_or(inp.len(), |x| pos + x);
+    let line = inp[start..end].split_whitespace().collect::<Vec<_>>();
+    format!("used in: {}", line.join(" "))
+}
+
+fn struct_def(
+    names: &[String],
+    spans: &HashMap<String, Span>,
+    usages: &HashMap<String, String>,
+) -> ItemStruct {
+    // the fields refer to the query, so go-to-definition on an argument shows its usage
+    let idents = names.iter().map(|x| {
+        let span = spans.get(x).copied().unwrap_or_else(Span::call_site);
+        Ident::new_raw(x, span)
+    });
+    let docs = names
+        .iter()
+        .map(|x| usages.get(x).map(|doc| quote!(#[doc = #doc])));
+    let generics = names
+        .iter()
+        .map(|x| Ident::new_raw(&format!("_{x}"), Span::call_site()));
+    let generics2 = generics.clone();
+
+    parse_quote!(struct Args<#(#generics),*> {
+        #(#docs #idents: #generics2,)*
+    })
+}
+
+fn struct_def2(fragments: &[(String, Slot)]) -> ItemStruct {
+    let fragment_idents = fragments
+        .iter()
+        .map(|(x, _)| Ident::new_raw(x, Span::call_site()));
+    let fragment_types = fragments.iter().map(|(_, slot)| match slot {
+        Slot::Schema => quote!(::pg_named_args::SchemaName),
+        Slot::OrderBy => quote!(::pg_named_args::OrderByFragment),
+        Slot::Where => quote!(::pg_named_args::WhereFragment),
+        Slot::Ident => quote!(::pg_named_args::IdentFragment),
+        _ => quote!(::pg_named_args::Fragment),
+    });
+
+    parse_quote!(struct Sql {
+        #(#fragment_idents: #fragment_types,)*
+    })
+}
+
+/// The default length above which the lists of `$list~in` are unnested.
+const UNNEST_ABOVE: usize = 100;
+
+/// The maximum number of variants of a query with `$when` and `$match` sections.
+const MAX_VARIANTS: usize = 16;
+
+/// A choice between parts of the query at runtime, which is made by a named argument.
+struct Choice {
+    /// The named argument.
+    arg: String,
+    /// The patterns of the arms, `false` and `true` for a `$when` section.
+    patterns: Vec<String>,
+}
 
-            // something is always inserted here as a proof that rustc will check the struct fields.
-            if args.insert(x.name.to_string(), fields).is_some() {
-                errors.push(syn::Error::new_spanned(x.name, "duplicate struct name"));
-            }
-        });
+/// Expands the `$when(flag) { ... }` and `$match(arg) { Pattern => { ... }, ... }` sections
+/// of the query.
+///
+/// Returns the choices and a variant of the query for every combination of their arms.
+/// The arm of the first choice changes the fastest between the variants.
+fn variants(inp: &str) -> Result<(Vec<Choice>, Vec<String>), String> {
+    const STARTS: [&str; 2] = ["$when(", "$match("];
+
+    // the text before every section, the choice and the body for every arm
+    let mut sections = vec![];
+    let mut choices: Vec<Choice> = vec![];
+    let mut rest = inp;
+    loop {
+        let start = STARTS
+            .iter()
+            .filter_map(|start| Some((rest.find(start)?, *start)))
+            .min();
+        let Some((pos, start)) = start else {
+            break;
+        };
+        let before = &rest[..pos];
+        rest = &rest[pos + start.len()..];
+        let is_when = start == STARTS[0];
+        let keyword = &start[..start.len() - 1];
+
+        let len = rest.find(|x: char| !x.is_alphanumeric() && x != '_');
+        let len = len.unwrap_or(rest.len());
+        let arg = &rest[..len];
+        let Some(body) = rest[len..]
+            .strip_prefix(')')
+            .map(str::trim_start)
+            .and_then(|x| x.strip_prefix('{'))
+            .filter(|_| !arg.is_empty())
+        else {
+            return Err(match is_when {
+                true => "expected `$when(flag) { ... }`".to_owned(),
+                false => "expected `$match(arg) { Pattern => { ... } }`".to_owned(),
+            });
+        };
 
-    let params: Vec<_> = args
-        .remove("Args")
-        .map(|fields| {
-            // this will only be a list of the fields that actually exist.
-            // if not all fields are specified it is a struct init error.
-            names
+        let Some(end) = closing_brace(body) else {
+            return Err(format!("expected closing `}}` of the `{keyword}` section"));
+        };
+        let inner = &body[..end];
+        rest = &body[end + 1..];
+        if STARTS.iter().any(|start| inner.contains(start)) {
+            return Err("`$when` and `$match` sections can't be nested".to_owned());
+        }
+
+        if is_when {
+            let patterns = ["false", "true"];
+            let idx = choices
                 .iter()
-                .filter_map(|search| {
-                    fields.iter().find_map(|field| {
-                        let Member::Named(name) = &field.member else {
-                            return None;
-                        };
-                        (name.unraw() == *search).then_some(field.expr.clone())
-                    })
-                })
-                .map(|res| {
-                    // Make a reference using res.span() so that ToSql errors are shown nicely.
-                    let res = quote_spanned!(res.span()=> &#res);
-                    // Cast to &dyn without span to hide unnecessary cast warning
-                    quote!(#res as &(dyn ::postgres_types::ToSql + Sync))
-                })
-                .collect()
+                .position(|x| x.arg == arg && x.patterns == patterns);
+            let idx = idx.unwrap_or_else(|| {
+                let patterns = patterns.map(str::to_owned).to_vec();
+                let arg = arg.to_owned();
+                choices.push(Choice { arg, patterns });
+                choices.len() - 1
+            });
+            sections.push((before, idx, vec!["", inner]));
+        } else {
+            let (patterns, bodies) = match_arms(inner)?.into_iter().unzip();
+            let arg = arg.to_owned();
+            choices.push(Choice { arg, patterns });
+            sections.push((before, choices.len() - 1, bodies));
+        }
+    }
+
+    let count = choices
+        .iter()
+        .try_fold(1_usize, |count, choice| {
+            count.checked_mul(choice.patterns.len())
         })
-        .unwrap_or_else(|| {
-            if !names.is_empty() {
-                errors.push(syn::Error::new(Span::call_site(), "expected `Args` struct"));
+        .filter(|&count| count <= MAX_VARIANTS);
+    let Some(count) = count else {
+        return Err(format!(
+            "a query can have at most {MAX_VARIANTS} combinations of `$when` and `$match` arms"
+        ));
+    };
+
+    let variants = (0..count)
+        .map(|variant| {
+            let mut arms = vec![];
+            let mut stride = 1;
+            for choice in &choices {
+                arms.push(variant / stride % choice.patterns.len());
+                stride *= choice.patterns.len();
             }
-            vec![]
-        });
 
-    let mut template = quote!(#template);
-    let fragment_args: Vec<_> = args
-        .remove("Sql")
-        .map(|fields| {
-            fragments
-                .iter()
-                .filter_map(|search| {
-                    fields.iter().find_map(|field| {
-                        let Member::Named(name) = &field.member else {
-                            return None;
-                        };
-                        (name.unraw() == *search).then_some(field.expr.clone())
-                    })
-                })
-                .map(|res| quote_spanned!(res.span()=> ::pg_named_args::Fragment::get(#res)))
-                .collect()
+            let mut out = String::new();
+            for (before, idx, bodies) in &sections {
+                out.push_str(before);
+                out.push_str(bodies[arms[*idx]]);
+            }
+            out.push_str(rest);
+            out
         })
-        .unwrap_or_else(|| {
-            if !fragments.is_empty() {
-                errors.push(syn::Error::new(Span::call_site(), "expected `Sql` struct"));
+        .collect();
+    Ok((choices, variants))
+}
+
+/// Parses the `Pattern => { ... }` arms of a `$match` section.
+fn match_arms(mut inp: &str) -> Result<Vec<(String, &str)>, String> {
+    let syntax = "expected `Pattern => { ... }` in `$match`";
+
+    let mut arms = vec![];
+    loop {
+        inp = inp.trim_start();
+        if inp.is_empty() {
+            break;
+        }
+        let Some(arrow) = inp.find("=>") else {
+            return Err(syntax.to_owned());
+        };
+        let pattern = inp[..arrow].trim();
+        let Some(body) = inp[arrow + 2..].trim_start().strip_prefix('{') else {
+            return Err(syntax.to_owned());
+        };
+        let Some(end) = closing_brace(body) else {
+            return Err(syntax.to_owned());
+        };
+
+        match Pat::parse_single.parse_str(pattern) {
+            Ok(Pat::Ident(_)) => {
+                return Err(format!(
+                    "expected the path of a variant, like `Mode::{pattern}`, \
+                    a single name matches any value"
+                ))
             }
-            vec![]
-        });
+            Ok(_) => {}
+            Err(_) => return Err(format!("expected a pattern, found `{pattern}`")),
+        }
 
-    // prevent additional errors when the Sql struct is not complete yet
-    if fragment_args.len() == fragments.len() {
-        template = quote!(&::std::format!(#template #(,#fragment_args)*));
+        arms.push((pattern.to_owned(), &body[..end]));
+        inp = body[end + 1..].trim_start();
+        inp = inp.strip_prefix(',').unwrap_or(inp);
     }
 
-    for key in args.keys() {
-        errors.push(syn::Error::new(
-            Span::call_site(),
-            format!("unknown struct name `{key}`"),
-        ));
+    if arms.is_empty() {
+        return Err("expected at least one arm in `$match`".to_owned());
     }
+    Ok(arms)
+}
 
-    let def = struct_def(&names);
-    let def2 = struct_def2(&fragments);
-    let errors = errors.into_iter().map(|err| err.to_compile_error());
-
-    quote!({
-        #(#errors;)*
-        #[allow(unreachable_code)]
-        if false {
-            unreachable!();
-            #def;
-            #def2;
-            (#input_raw);
+/// The position of the `}` that closes the section, the `{` is already consumed.
+fn closing_brace(inp: &str) -> Option<usize> {
+    let mut depth = 0;
+    inp.find(|x| {
+        match x {
+            '{' => depth += 1,
+            '}' if depth == 0 => return true,
+            '}' => depth -= 1,
+            _ => {}
         }
-        (#template, &[#(#params),*])
+        false
     })
-    .into()
 }
 
-fn struct_def(names: &[String]) -> ItemStruct {
-    let idents = names.iter().map(|x| Ident::new_raw(x, Span::call_site()));
-    let generics = names
-        .iter()
-        .map(|x| Ident::new_raw(&format!("_{x}"), Span::call_site()));
-    let generics2 = generics.clone();
+/// Whether the query has a `?` placeholder of another driver, like `WHERE id = ?`.
+///
+/// A `?` is a placeholder after a `=`, `(`, `,`, `<` or `>`, or before a `,`, `)`, `;` or
+/// the end of the query, outside of quotes and comments, so the jsonb operators like
+/// `data ? 'key'` and `data ?| keys` are not placeholders.
+fn has_qmark_placeholder(inp: &str) -> bool {
+    let mut quote = None;
+    for (pos, x) in inp.char_indices() {
+        match (quote, x) {
+            (Some(q), _) if x == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(x),
+            (None, '-') if inp[pos..].starts_with("--") => quote = Some('\n'),
+            (None, '?') => {
+                let before = inp[..pos].trim_end().chars().next_back();
+                let after = inp[pos + 1..].trim_start().chars().next();
+                if before.is_some_and(|x| "=(,<>".contains(x))
+                    || after.is_none_or(|x| ",);".contains(x))
+                {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+    false
+}
 
-    parse_quote!(struct Args<#(#generics),*> {
-        #(#idents: #generics2,)*
-    })
+/// Caps the number of rows of a `SELECT` statement at `max`, for the `max_rows` option.
+///
+/// A literal `LIMIT` above the maximum is an error, a `LIMIT` with a named argument is capped
+/// with `LEAST` at runtime, and a `LIMIT` is appended when the statement doesn't have one.
+/// Only the `LIMIT` outside of parentheses is checked, so the subqueries are left as they are.
+fn cap_rows(inp: &str, max: u64) -> Result<String, String> {
+    let word_char = |x: char| x.is_alphanumeric() || x == '_' || x == '$';
+    let mut quote = None;
+    let mut depth = 0usize;
+    let mut limit = None;
+    for (pos, x) in inp.char_indices() {
+        match (quote, x) {
+            (Some(q), _) if x == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(x),
+            (None, '-') if inp[pos..].starts_with("--") => quote = Some('\n'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, _) if depth == 0 && !inp[..pos].ends_with(word_char) => {
+                let len = inp[pos..]
+                    .find(|x| !word_char(x))
+                    .unwrap_or(inp.len() - pos);
+                match inp[pos..pos + len].to_ascii_uppercase().as_str() {
+                    "LIMIT" => limit = Some(pos + len),
+                    "FETCH" => {
+                        return Err(
+                            "the `max_rows` option requires `LIMIT` instead of `FETCH`".to_owned()
+                        )
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let Some(end) = limit else {
+        let sql = inp.trim_end().trim_end_matches(';').trim_end();
+        // a line comment at the end would comment out the limit
+        let separator = match sql.lines().next_back().unwrap_or_default().contains("--") {
+            true => '\n',
+            false => ' ',
+        };
+        return Ok(format!("{sql}{separator}LIMIT {max}"));
+    };
+    let rest = &inp[end..];
+    let value = rest.trim_start();
+    let start = end + rest.len() - value.len();
+    let len = value.find(|x| !word_char(x)).unwrap_or(value.len());
+    let value = &value[..len];
+    if let Ok(rows) = value.parse::<u64>() {
+        return match rows > max {
+            true => Err(format!(
+                "the `LIMIT {rows}` is above the maximum of {max} rows of the `max_rows` option"
+            )),
+            false => Ok(inp.to_owned()),
+        };
+    }
+    match value.strip_prefix('$') {
+        // the cast keeps the type of the parameter, which is inferred from the limit
+        Some(name) if !name.is_empty() => Ok(format!(
+            "{}LEAST({value}, {max}::bigint){}",
+            &inp[..start],
+            &inp[start + len..]
+        )),
+        _ => Err(
+            "the `max_rows` option requires a number or a named argument after `LIMIT`".to_owned(),
+        ),
+    }
 }
 
-fn struct_def2(fragments: &[String]) -> ItemStruct {
-    let fragment_idents = fragments
+/// The columns of a `$[...]` group, which starts after the `$[`, and the length of the group
+/// until the closing `]`.
+///
+/// The columns need to be known at compile time for the `name` macro, so optional columns and
+/// a fragment at the end of the group are an error.
+fn fixed_columns(group: &str, name: &str) -> Result<(Vec<String>, usize), String> {
+    let mut columns = vec![String::new()];
+    let mut len = 0;
+    loop {
+        let Some(x) = group[len..].chars().next() else {
+            return Err("expected closing `]`".to_owned());
+        };
+        match x {
+            ']' => break,
+            ',' => {
+                columns.push(String::new());
+                len += 1;
+            }
+            '"' => {
+                let quoted = quoted_len(&group[len..]);
+                columns
+                    .last_mut()
+                    .unwrap()
+                    .push_str(&group[len..len + quoted]);
+                len += quoted;
+            }
+            '-' if group[len..].starts_with("--") => {
+                len += group[len..].find('\n').unwrap_or(group.len() - len);
+            }
+            x => {
+                columns.last_mut().unwrap().push(x);
+                len += x.len_utf8();
+            }
+        }
+    }
+    let mut columns: Vec<_> = columns.iter().map(|x| x.trim().to_owned()).collect();
+    // a trailing comma is allowed, like in rust lists
+    if columns.len() > 1 && columns.last().unwrap().is_empty() {
+        columns.pop();
+    }
+    if columns
         .iter()
-        .map(|x| Ident::new_raw(x, Span::call_site()));
+        .any(|x| x.ends_with('?') || x.starts_with('$'))
+    {
+        return Err(format!(
+            "the columns of `{name}` need to be known at compile time, \
+            which optional columns and fragments aren't"
+        ));
+    }
+    Ok((columns, len))
+}
 
-    parse_quote!(struct Sql {
-        #(#fragment_idents: ::pg_named_args::Fragment,)*
+/// Adds the `ON CONFLICT` clause of [upsert_args!] to the insert, which updates the columns of
+/// the first `$[...]` group that are not part of the conflict target `keys`.
+fn upsert(inp: &str, keys: &[String]) -> Result<String, String> {
+    let Some(start) = inp.find("$[") else {
+        return Err("expected a `$[...]` group with the columns of the insert".to_owned());
+    };
+    let (columns, _) = fixed_columns(&inp[start + 2..], "upsert_args!")?;
+
+    let mut targets = vec![];
+    let mut updates = vec![];
+    for column in &columns {
+        if !keys.contains(&column_arg(column)) {
+            updates.push(format!("{column} = excluded.{column}"));
+        }
+    }
+    for key in keys {
+        let Some(column) = columns.iter().find(|x| column_arg(x) == *key) else {
+            return Err(format!(
+                "the conflict column `{key}` is not a column of the group"
+            ));
+        };
+        targets.push(column.as_str());
+    }
+    let action = match updates.is_empty() {
+        true => "DO NOTHING".to_owned(),
+        false => format!("DO UPDATE SET {}", updates.join(", ")),
+    };
+    let clause = format!("ON CONFLICT ({}) {action}", targets.join(", "));
+
+    // the clause goes before the `RETURNING` outside of parentheses
+    let word_char = |x: char| x.is_alphanumeric() || x == '_' || x == '$';
+    let mut quote = None;
+    let mut depth = 0usize;
+    for (pos, x) in inp.char_indices() {
+        match (quote, x) {
+            (Some(q), _) if x == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(x),
+            (None, '-') if inp[pos..].starts_with("--") => quote = Some('\n'),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, _) if depth == 0 && !inp[..pos].ends_with(word_char) => {
+                let len = inp[pos..]
+                    .find(|x| !word_char(x))
+                    .unwrap_or(inp.len() - pos);
+                if inp[pos..pos + len].eq_ignore_ascii_case("RETURNING") {
+                    return Ok(format!("{}{clause} {}", &inp[..pos], &inp[pos..]));
+                }
+            }
+            _ => {}
+        }
+    }
+    let sql = inp.trim_end().trim_end_matches(';').trim_end();
+    // a line comment at the end would comment out the clause
+    let separator = match sql.lines().next_back().unwrap_or_default().contains("--") {
+        true => '\n',
+        false => ' ',
+    };
+    Ok(format!("{sql}{separator}{clause}"))
+}
+
+/// Whether the query uses a modifier, like `$name~contains`.
+fn has_modifier(inp: &str) -> bool {
+    inp.match_indices('$').any(|(pos, _)| {
+        let rest = &inp[pos + 1..];
+        let len = rest.find(|x: char| !x.is_alphanumeric() && x != '_');
+        modifier(&rest[len.unwrap_or(rest.len())..]).is_some()
     })
 }
 
+/// Replaces the positional arguments in the rewritten query with `?` placeholders.
+///
+/// Returns the query and the names for every placeholder, an argument that is used more than
+/// once is repeated.
+fn qmark(template: &str, names: &[String]) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut occurrences = vec![];
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        let len = rest
+            .find(|x: char| !x.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let idx: usize = rest[..len].parse().expect("a positional argument");
+        occurrences.push(names[idx - 1].clone());
+        out.push('?');
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    (out, occurrences)
+}
+
+/// The prefix of the names of the item fields in the body of a `$for` loop.
+const FOR_ITEM: &str = "__item_";
+
+/// The position between the columns and the values of [values_args!].
+const VALUES_MARKER: char = '\u{2}';
+
+/// The position of the `$for` loop in the query, which is replaced by the repeated body.
+const FOR_MARKER: char = '\u{1}';
+
+/// The parts of a query with a `$for(item in $items, separator) { ... }` loop.
+struct ForLoop {
+    /// The name of the item in the body.
+    item: String,
+    /// The named argument with the items.
+    items: String,
+    /// The separator between the repetitions of the body.
+    separator: String,
+    /// The query with the loop replaced by [FOR_MARKER].
+    outer: String,
+    /// The body with `$item.field` replaced by `$__item_field`.
+    body: String,
+}
+
+/// A parameter in the body of a `$for` loop.
+enum BodyParam {
+    /// The index of a named argument of the query.
+    Outer(usize),
+    /// The index of a field of the item.
+    Field(usize),
+}
+
+/// Finds the `$for` loop in the query.
+fn for_loop(inp: &str) -> Result<Option<ForLoop>, String> {
+    const START: &str = "$for(";
+    let syntax = "expected `$for(item in $items) { ... }`";
+
+    let Some(pos) = inp.find(START) else {
+        return Ok(None);
+    };
+    let rest = &inp[pos + START.len()..];
+    let ident_len = |x: &str| x.find(|x: char| !x.is_alphanumeric() && x != '_');
+
+    let rest = rest.trim_start();
+    let len = ident_len(rest).unwrap_or(rest.len());
+    let item = &rest[..len];
+    let Some(rest) = rest[len..].trim_start().strip_prefix("in") else {
+        return Err(syntax.to_owned());
+    };
+    let Some(rest) = rest.trim_start().strip_prefix('$') else {
+        return Err(syntax.to_owned());
+    };
+    let len = ident_len(rest).unwrap_or(rest.len());
+    let items = &rest[..len];
+    let Some(close) = rest.find(')') else {
+        return Err(syntax.to_owned());
+    };
+    let separator = match rest[len..close].trim_start().strip_prefix(',') {
+        Some(separator) => separator.trim(),
+        None if rest[len..close].trim().is_empty() => ",",
+        None => return Err(syntax.to_owned()),
+    };
+    let Some(body) = rest[close + 1..].trim_start().strip_prefix('{') else {
+        return Err(syntax.to_owned());
+    };
+    if item.is_empty() || items.is_empty() || separator.is_empty() {
+        return Err(syntax.to_owned());
+    }
+
+    let Some(end) = closing_brace(body) else {
+        return Err("expected closing `}` of the `$for` loop".to_owned());
+    };
+    let after = &body[end + 1..];
+    let body = &body[..end];
+    if body.contains(START) || after.contains(START) {
+        return Err("only a single `$for` loop is supported in a query".to_owned());
+    }
+
+    // the references to the item are renamed, so they are separate from the named arguments
+    let mut renamed = String::new();
+    let mut rest = body;
+    while let Some(pos) = rest.find('$') {
+        renamed.push_str(&rest[..=pos]);
+        rest = &rest[pos + 1..];
+        let len = ident_len(rest).unwrap_or(rest.len());
+        if &rest[..len] != item {
+            continue;
+        }
+        renamed.push_str(FOR_ITEM);
+        rest = &rest[len..];
+        if let Some(field) = rest.strip_prefix('.') {
+            let len = ident_len(field).unwrap_or(field.len());
+            renamed.push_str(&field[..len]);
+            rest = &field[len..];
+        }
+    }
+    renamed.push_str(rest);
+
+    Ok(Some(ForLoop {
+        item: item.to_owned(),
+        items: items.to_owned(),
+        separator: separator.to_owned(),
+        outer: format!("{}{FOR_MARKER}{after}", &inp[..pos]),
+        body: renamed,
+    }))
+}
+
+/// Replaces the positional arguments in the rewritten body of a `$for` loop with `{pN}`
+/// placeholders, which are numbered for every repetition.
+fn body_placeholders(template: &str) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[..=pos]);
+        rest = &rest[pos + 1..];
+        let len = rest
+            .find(|x: char| !x.is_ascii_digit())
+            .unwrap_or(rest.len());
+        out.push_str(&format!("{{p{}}}", &rest[..len]));
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Rewrites the query with [pg_named_args_core::rewrite], reporting the errors at the query.
 fn rewrite_query(
     inp: LitStr,
     names: &mut Vec<String>,
     errors: &mut Vec<syn::Error>,
-    fragments: &mut Vec<String>,
+    fragments: &mut Vec<(String, Slot)>,
+    defaults: &mut Vec<(String, String)>,
+    columns: &mut Vec<Column>,
 ) -> LitStr {
-    let span = inp.span();
-    let mut push_err = |message: &str| errors.push(syn::Error::new(span, message));
-
-    let mut inp = &*inp.value().replace("{", "{{").replace("}", "}}");
+    let rewrite = pg_named_args_core::rewrite(&inp.value(), names);
+    errors.extend(
+        rewrite
+            .errors
+            .iter()
+            .map(|x| syn::Error::new(inp.span(), x)),
+    );
+    fragments.extend(rewrite.fragments);
+    columns.extend(rewrite.columns);
+    for (name, default) in rewrite.defaults {
+        match defaults.iter().find(|(x, _)| *x == name) {
+            Some((_, existing)) if *existing != default => {
+                let message = format!("conflicting defaults for the fragment `{name}`");
+                errors.push(syn::Error::new(inp.span(), message));
+            }
+            Some(_) => {}
+            None => defaults.push((name, default)),
+        }
+    }
+    LitStr::new(&rewrite.template, inp.span())
+}
 
-    let mut template = String::new();
-    let mut batch = None::<String>;
+/// Strips the common leading indentation and the surrounding blank lines.
+///
+/// Like `indoc`, the first line is not taken into account when computing the
+/// indentation, because it directly follows the opening quote.
+fn dedent(inp: &str) -> String {
+    let is_blank = |line: &str| line.trim().is_empty();
+    let indent = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
 
-    let mut get_idx = |ident: &str| {
-        if let Some(idx) = names.iter().position(|x| x == ident) {
-            idx
-        } else {
-            names.push(ident.to_owned());
-            names.len() - 1
-        }
+    let lines: Vec<_> = inp.lines().collect();
+    let Some(first) = lines.iter().position(|line| !is_blank(line)) else {
+        return String::new();
     };
+    let last = lines.iter().rposition(|line| !is_blank(line)).unwrap();
 
-    fn ident_char(x: char) -> bool {
-        x.is_alphanumeric() || x == '_'
-    }
+    let common = lines[first..=last]
+        .iter()
+        .enumerate()
+        .filter(|(i, line)| (first > 0 || *i > 0) && !is_blank(line))
+        .map(|(_, line)| indent(line))
+        .min()
+        .unwrap_or(0);
 
-    loop {
-        let Some(dollar_pos) = inp.find('$') else {
-            template.push_str(inp);
-            break;
-        };
+    lines[first..=last]
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if is_blank(line) {
+                ""
+            } else if first == 0 && i == 0 {
+                line
+            } else {
+                &line[common..]
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-        template.push_str(&inp[..dollar_pos]);
-        inp = &inp[dollar_pos + 1..];
+/// Removes comments and collapses all whitespace into single spaces.
+///
+/// String constants and quoted identifiers are copied verbatim.
+fn minify(inp: &str) -> String {
+    let mut out = String::new();
+    let mut chars = inp.chars().peekable();
+    let mut space = false;
+    let mut prev = None::<char>;
 
-        let mut is_fragment = false;
-        // braces have been pre-escaped
-        if inp.get(..2) == Some("{{") {
-            is_fragment = true;
-            inp = &inp[2..];
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            space = true;
+            continue;
+        }
+        if c == '-' && chars.peek() == Some(&'-') {
+            chars.find(|&c| c == '\n');
+            space = true;
+            continue;
+        }
+        if c == '/' && chars.peek() == Some(&'*') {
+            chars.next();
+            // block comments can be nested in PostgreSQL
+            let mut depth = 1;
+            while depth > 0 {
+                match chars.next() {
+                    Some('/') if chars.peek() == Some(&'*') => {
+                        chars.next();
+                        depth += 1;
+                    }
+                    Some('*') if chars.peek() == Some(&'/') => {
+                        chars.next();
+                        depth -= 1;
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            space = true;
+            continue;
         }
 
-        let ident_len = inp.find(|x: char| !ident_char(x)).unwrap_or(inp.len());
-        let ident = &inp[..ident_len];
-        inp = &inp[ident_len..];
+        if space && !out.is_empty() {
+            out.push(' ');
+        }
+        space = false;
+        out.push(c);
 
-        if ident.is_empty() {
-            if is_fragment {
-                push_err("expected an identifer after `{`");
-                return LitStr::new(&template, span);
+        if c == '\'' || c == '"' {
+            // `E'...'` strings support backslash escapes
+            let escapes = c == '\''
+                && matches!(prev, Some('e' | 'E'))
+                && !out[..out.len() - 2].ends_with(|x: char| x.is_alphanumeric() || x == '_');
+            while let Some(x) = chars.next() {
+                out.push(x);
+                if escapes && x == '\\' {
+                    out.extend(chars.next());
+                } else if x == c {
+                    // a doubled quote is an escaped quote
+                    if chars.peek() != Some(&c) {
+                        break;
+                    }
+                    out.extend(chars.next());
+                }
             }
+        }
+        prev = Some(c);
+    }
+    out
+}
 
-            let Some("[") = inp.get(..1) else {
-                push_err("expected identifier or `[` after `$`");
-                return LitStr::new(&template, span);
-            };
-            inp = &inp[1..];
+/// The words of the query outside of strings, quoted identifiers and comments, in upper case,
+/// together with their depth in parentheses. The named arguments and the words after a `.`,
+/// like the `update` column of `t.update`, are skipped.
+fn keywords(inp: &str) -> Vec<(String, usize)> {
+    let sql: Vec<char> = minify(inp).chars().collect();
+    let word_char = |x: &char| x.is_alphanumeric() || *x == '_';
+
+    let mut out = vec![];
+    let mut depth = 0usize;
+    let mut idx = 0;
+    while let Some(&c) = sql.get(idx) {
+        idx += 1;
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '\'' | '"' => {
+                // `E'...'` strings support backslash escapes
+                let escapes = c == '\''
+                    && out
+                        .last()
+                        .is_some_and(|(word, _): &(String, usize)| word == "E")
+                    && idx >= 2
+                    && matches!(sql[idx - 2], 'e' | 'E');
+                while let Some(&x) = sql.get(idx) {
+                    idx += 1;
+                    if escapes && x == '\\' {
+                        idx += 1;
+                    } else if x == c {
+                        // a doubled quote is an escaped quote
+                        if sql.get(idx) != Some(&c) {
+                            break;
+                        }
+                        idx += 1;
+                    }
+                }
+            }
+            '$' => {
+                while sql.get(idx).is_some_and(word_char) {
+                    idx += 1;
+                }
+            }
+            c if word_char(&c) => {
+                let start = idx - 1;
+                while sql.get(idx).is_some_and(word_char) {
+                    idx += 1;
+                }
+                if start > 0 && sql[start - 1] == '.' {
+                    continue;
+                }
+                let word: String = sql[start..idx].iter().collect();
+                out.push((word.to_uppercase(), depth));
+            }
+            _ => {}
+        }
+    }
+    out
+}
 
-            let until = inp
-                .find(|x: char| !ident_char(x) && !x.is_ascii_whitespace() && x != ',' && x != '.')
-                .unwrap_or(inp.len());
-            let columns = &inp[..until];
-            inp = &inp[until..];
+/// The kind of statement, like `SELECT` or `INSERT`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum StatementKind {
+    Select,
+    Insert,
+    Update,
+    Delete,
+    Other,
+}
 
-            let Some("]") = inp.get(..1) else {
-                push_err("expected closing `]`");
-                return LitStr::new(&template, span);
-            };
-            inp = &inp[1..];
+/// Classifies the statement by its first keyword, or the main statement of a `WITH` query.
+fn statement_kind(keywords: &[(String, usize)]) -> StatementKind {
+    let kind = |word: &str| match word {
+        "SELECT" | "VALUES" | "TABLE" => Some(StatementKind::Select),
+        "INSERT" => Some(StatementKind::Insert),
+        "UPDATE" => Some(StatementKind::Update),
+        "DELETE" => Some(StatementKind::Delete),
+        _ => None,
+    };
 
-            if columns == ".." {
-                let Some(columns) = batch.take() else {
-                    push_err("parameter group is used, but not defined");
-                    continue;
-                };
+    match keywords.first() {
+        Some((word, _)) if word == "WITH" => keywords
+            .iter()
+            .filter(|(_, depth)| *depth == 0)
+            .find_map(|(word, _)| kind(word))
+            .unwrap_or(StatementKind::Other),
+        Some((word, _)) => kind(word).unwrap_or(StatementKind::Other),
+        None => StatementKind::Other,
+    }
+}
 
-                template.push_str(&columns);
-            } else {
-                let mut out = vec![];
-                for column in columns.split(',') {
-                    let ident = column.trim();
-                    if ident.is_empty() {
-                        push_err(
-                            "expected identifier between all of `$[`, every `,` and final `]`",
-                        );
-                        continue;
-                    }
+/// Checks that the query only reads data, so it can run on a read-only replica.
+fn check_read_only(keywords: &[(String, usize)]) -> Result<(), String> {
+    if statement_kind(keywords) != StatementKind::Select {
+        return Err("the `readonly` option requires a `SELECT` statement".to_owned());
+    }
+    for (idx, (word, depth)) in keywords.iter().enumerate() {
+        let rest: Vec<_> = keywords[idx + 1..]
+            .iter()
+            .take(3)
+            .map(|(x, _)| &**x)
+            .collect();
+        let write = match (&**word, &rest[..]) {
+            // data modifying `WITH` queries, the statement directly after `AS (`
+            ("INSERT" | "UPDATE" | "DELETE" | "MERGE", _) => idx
+                .checked_sub(1)
+                .map(|x| &keywords[x])
+                .filter(|(x, d)| matches!(&**x, "AS" | "MATERIALIZED") && d + 1 == *depth)
+                .map(|_| &**word),
+            // locking clauses
+            ("FOR", [x @ ("UPDATE" | "SHARE"), ..])
+            | ("FOR", ["NO", "KEY", x @ "UPDATE", ..] | ["KEY", x @ "SHARE", ..]) => Some(*x),
+            // `SELECT INTO`, after the select list
+            ("INTO", _) => keywords[..idx]
+                .iter()
+                .rev()
+                .take_while(|(_, d)| d >= depth)
+                .any(|(x, d)| x == "SELECT" && d == depth)
+                .then_some("INTO"),
+            _ => None,
+        };
+        if let Some(word) = write {
+            return Err(format!(
+                "the `readonly` option does not allow `{word}` in the query"
+            ));
+        }
+    }
+    Ok(())
+}
 
-                    let idx = get_idx(ident);
-                    out.push(format!("${}", idx + 1));
-                }
+/// Checks that an `INSERT` statement uses a group for the columns and for the values.
+fn check_insert_groups(inp: &str) -> Result<(), String> {
+    fn word_position(inp: &str, word: &str) -> Option<usize> {
+        inp.match_indices(word).map(|(pos, _)| pos).find(|&pos| {
+            let word_char = |x: char| x.is_alphanumeric() || x == '_';
+            !inp[..pos].ends_with(word_char) && !inp[pos + word.len()..].starts_with(word_char)
+        })
+    }
+
+    let sql = minify(inp);
+    let Some(pos) = word_position(&sql.to_ascii_uppercase(), "INSERT INTO") else {
+        return Ok(());
+    };
+    let rest = sql[pos + "INSERT INTO".len()..].trim_start();
+    // the table, which can be quoted or qualified, and its alias
+    let mut len = 0;
+    while let Some(x) = rest[len..].chars().next() {
+        match x {
+            '"' => len += quoted_len(&rest[len..]),
+            x if x.is_whitespace() || x == '(' => break,
+            x => len += x.len_utf8(),
+        }
+    }
+    let mut rest = rest[len..].trim_start();
+    if rest.get(..3).is_some_and(|x| x.eq_ignore_ascii_case("AS ")) {
+        let alias = rest[3..].trim_start();
+        let len = alias.find([' ', '(']).unwrap_or(alias.len());
+        rest = alias[len..].trim_start();
+    }
+
+    let upper = rest.to_ascii_uppercase();
+    if upper.starts_with("DEFAULT VALUES") {
+        return Ok(());
+    }
+    let columns = rest.strip_prefix('(').map(str::trim_start);
+    if !columns.is_some_and(|x| x.starts_with("$[")) {
+        return Err("the columns of an `INSERT` statement should be a `$[...]` group".to_owned());
+    }
+    if let Some(pos) = word_position(&upper, "VALUES") {
+        if !rest[pos..].contains("$[..]") {
+            return Err(
+                "the values of an `INSERT` statement should be the `$[..]` group".to_owned(),
+            );
+        }
+    }
+    Ok(())
+}
 
-                if batch.replace(out.join(", ")).is_some() {
-                    push_err("previous parameter group is not used");
+/// Appends a comment in the [sqlcommenter](https://google.github.io/sqlcommenter/spec/)
+/// format, placed before the trailing `;` if there is one.
+fn sqlcommenter(inp: &str, mut tags: Vec<(String, String)>) -> String {
+    fn encode(inp: &str) -> String {
+        // the encoded output doesn't contain braces, so it is safe to use in the template
+        inp.bytes()
+            .map(|x| match x {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    char::from(x).to_string()
                 }
+                _ => format!("%{x:02X}"),
+            })
+            .collect()
+    }
+
+    tags.sort();
+    let comment = tags
+        .iter()
+        .map(|(key, value)| format!("{}='{}'", encode(key), encode(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let inp = inp.trim_end();
+    match inp.strip_suffix(';') {
+        Some(inp) => format!("{} /*{comment}*/;", inp.trim_end()),
+        None => format!("{inp} /*{comment}*/"),
+    }
+}
 
-                template.push_str(columns);
+/// The template with every slot marked as `${name}`, and the names of the slots, for the
+/// manifest. The sql of the slots is only known at runtime.
+fn mark_slots(template: &str, fragments: &[(String, Slot)]) -> (String, Vec<String>) {
+    let mut slots = fragments.iter().map(|(name, slot)| match slot {
+        Slot::Columns => "columns".to_owned(),
+        Slot::Values => "values".to_owned(),
+        _ if name.starts_with(WHERE_CLAUSE) => "where".to_owned(),
+        _ => name.clone(),
+    });
+    let mut names = vec![];
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(x) = rest.chars().next() {
+        match rest.get(..2) {
+            Some("{{") => out.push('{'),
+            Some("}}") => out.push('}'),
+            Some("{}") => {
+                let name = slots.next().unwrap_or_default();
+                out.push_str(&format!("${{{name}}}"));
+                names.push(name);
             }
-        } else if is_fragment {
-            // braces have been pre-escaped
-            if inp.get(..2) == Some("}}") {
-                inp = &inp[2..];
-            } else {
-                push_err("fragment should end with `}`")
+            _ => {
+                out.push(x);
+                rest = &rest[x.len_utf8()..];
+                continue;
             }
-            fragments.push(ident.to_owned());
-            template.push_str("{}");
-        } else {
-            let idx = get_idx(ident);
-            template.push_str(&format!("${}", idx + 1));
         }
+        rest = &rest[2..];
     }
+    (out, names)
+}
 
-    if batch.is_some() {
-        push_err("last parameter group is not used");
+/// A JSON string of the manifest.
+fn json_string(inp: &str) -> String {
+    let mut out = String::from('"');
+    for x in inp.chars() {
+        match x {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            x if x.is_control() => out.push_str(&format!("\\u{:04x}", u32::from(x))),
+            x => out.push(x),
+        }
     }
+    out.push('"');
+    out
+}
+
+/// The entry of a query in the manifest, as a JSON object.
+fn manifest_entry(
+    name: Option<&str>,
+    sql: &str,
+    slots: &[String],
+    names: &[String],
+    call_site: &str,
+    fingerprint: u64,
+) -> String {
+    let slots = slots.iter().map(|x| json_string(x)).collect::<Vec<_>>();
+    let params = names.iter().map(|x| json_string(x)).collect::<Vec<_>>();
+    format!(
+        r#"{{"name":{},"sql":{},"fragments":[{}],"params":[{}],"call_site":{},"fingerprint":"{:016x}"}}"#,
+        name.map_or("null".to_owned(), json_string),
+        json_string(sql),
+        slots.join(","),
+        params.join(","),
+        json_string(call_site),
+        fingerprint,
+    )
+}
+
+/// Writes the entry to the manifest directory, in a file per query and source file.
+///
+/// The line is not part of the name of the file, so a query that moves keeps its file. The
+/// first query of a source file in a compilation removes the files of the source file, so the
+/// queries that are removed from it don't keep their files. The directory is `.pg_named_args`
+/// in the crate, which cargo tracks, unlike an environment variable.
+fn write_manifest(file: &str, fingerprint: u64, entry: &str) -> std::io::Result<()> {
+    /// The source files of which the files were removed in this compilation.
+    static CLEARED: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(vec![]);
 
-    LitStr::new(&template, span)
+    let crate_dir = std::env::var_os("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let dir = std::path::Path::new(&crate_dir).join(".pg_named_args");
+    std::fs::create_dir_all(&dir)?;
+    let prefix = format!("query-{:016x}-", self::fingerprint(file));
+    let mut cleared = CLEARED.lock().unwrap_or_else(|err| err.into_inner());
+    if !cleared.iter().any(|x| x == file) {
+        cleared.push(file.to_owned());
+        for other in std::fs::read_dir(&dir)? {
+            let path = other?.path();
+            let other = path.file_name().unwrap_or_default().to_string_lossy();
+            if other.starts_with(&prefix) {
+                // the file can be removed by a concurrent build
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+    }
+    std::fs::write(dir.join(format!("{prefix}{fingerprint:016x}.json")), entry)
 }
 
 struct RawStruct {
@@ -316,15 +2603,162 @@ impl Parse for RawStruct {
     }
 }
 
+#[derive(Default)]
+struct Options {
+    dedent: bool,
+    minify: bool,
+    readonly: bool,
+    /// Whether an `INSERT` statement needs the `$[...]` and `$[..]` groups.
+    strict_insert: bool,
+    /// Whether `?` placeholders are used instead of `$1`, for other databases.
+    qmark: bool,
+    /// The user provided tags of the sqlcommenter comment, if enabled.
+    comment: Option<Vec<(String, String)>>,
+    /// The name of the prepared statement, if enabled.
+    prepare: Option<Ident>,
+    /// Whether the `Query` carries the template with the named arguments.
+    template: Option<Ident>,
+    /// Whether the arguments that are not in `Args` bind the local variables with their names.
+    capture: bool,
+    /// The buffer of the caller that the parameters are written to, if enabled.
+    buffer: Option<Expr>,
+    /// Whether the parameters are returned as an array with the number of parameters as length.
+    array: Option<Ident>,
+    /// The maximum number of rows of a `SELECT` statement, if enabled.
+    max_rows: Option<u64>,
+    /// The length above which the lists of `$list~in` are unnested, if given.
+    unnest_above: Option<usize>,
+    /// The columns of the conflict target of `upsert_args!`.
+    conflict: Option<(Ident, Vec<String>)>,
+}
+
+impl Parse for Options {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let mut options = Options::default();
+        let mut seen = vec![];
+        while input.peek(syn::Ident) {
+            let name: Ident = input.parse()?;
+            let key = name.to_string();
+            if seen.contains(&key) {
+                return Err(syn::Error::new_spanned(
+                    &name,
+                    format!("duplicate option `{name}`"),
+                ));
+            }
+
+            match &*key {
+                "dedent" => options.dedent = true,
+                "minify" => options.minify = true,
+                "readonly" => options.readonly = true,
+                "strict_insert" => options.strict_insert = true,
+                "qmark" => options.qmark = true,
+                "template" => options.template = Some(name.clone()),
+                "capture" => options.capture = true,
+                "array" => options.array = Some(name.clone()),
+                "comment" => {
+                    let mut tags = vec![];
+                    if input.peek(Paren) {
+                        let inner;
+                        parenthesized!(inner in input);
+                        let pairs = inner.parse_terminated(
+                            |input| {
+                                let key = input.call(Ident::parse_any)?;
+                                input.parse::<Token![=]>()?;
+                                let value = input.parse::<LitStr>()?;
+                                Ok((key, value))
+                            },
+                            Token![,],
+                        )?;
+                        for (key, value) in pairs {
+                            if key.unraw() == "file" {
+                                return Err(syn::Error::new_spanned(
+                                    key,
+                                    "the `file` tag is added automatically",
+                                ));
+                            }
+                            tags.push((key.unraw().to_string(), value.value()));
+                        }
+                    }
+                    options.comment = Some(tags);
+                }
+                "prepare" => {
+                    let inner;
+                    parenthesized!(inner in input);
+                    options.prepare = Some(inner.parse()?);
+                }
+                "buffer" => {
+                    let inner;
+                    parenthesized!(inner in input);
+                    options.buffer = Some(inner.parse()?);
+                }
+                "max_rows" => {
+                    let inner;
+                    parenthesized!(inner in input);
+                    options.max_rows = Some(inner.parse::<syn::LitInt>()?.base10_parse()?);
+                }
+                "unnest_above" => {
+                    let inner;
+                    parenthesized!(inner in input);
+                    options.unnest_above = Some(inner.parse::<syn::LitInt>()?.base10_parse()?);
+                }
+                "conflict" => {
+                    let inner;
+                    parenthesized!(inner in input);
+                    let columns = inner.parse_terminated(Ident::parse_any, Token![,])?;
+                    let columns = columns.iter().map(|x| x.unraw().to_string()).collect();
+                    options.conflict = Some((name.clone(), columns));
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        &name,
+                        format!("unknown option `{name}`"),
+                    ))
+                }
+            }
+            seen.push(key);
+            input.parse::<Token![,]>()?;
+        }
+        Ok(options)
+    }
+}
+
 struct Format {
-    template: LitStr,
+    options: Options,
+    /// The input without the options, used to let rustc check the structs.
+    raw: TokenStream,
+    /// Whether multiple templates are given between brackets.
+    batch: bool,
+    templates: Vec<LitStr>,
     args: Option<(Token![,], Punctuated<RawStruct, Token![,]>)>,
 }
 
 impl Parse for Format {
     fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let options = input.parse()?;
+        let raw = input.fork().parse()?;
+        let batch = input.peek(Bracket);
+        let templates = if batch {
+            let inner;
+            bracketed!(inner in input);
+            let templates = inner.parse_terminated(<LitStr as Parse>::parse, Token![,])?;
+            templates.into_iter().collect()
+        } else {
+            let mut template: LitStr = input.parse()?;
+            // adjacent literals are concatenated, to split long queries
+            while input.peek(Token![,]) && input.peek2(LitStr) {
+                input.parse::<Token![,]>()?;
+                let next: LitStr = input.parse()?;
+                let span = template.span().join(next.span());
+                let value = template.value() + &next.value();
+                template = LitStr::new(&value, span.unwrap_or_else(|| template.span()));
+            }
+            vec![template]
+        };
         Ok(Format {
-            template: input.parse()?,
+            options,
+            raw,
+            batch,
+            templates,
             args: input
                 .parse::<Option<Token![,]>>()?
                 .map(|comma| {
@@ -338,22 +2772,196 @@ impl Parse for Format {
 
 #[proc_macro]
 pub fn fragment(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let input_raw = TokenStream::from(input.clone());
-
-    let lit = parse_macro_input!(input as LitStr);
+    let input = parse_macro_input!(input as FragmentInput);
+    let lit = &input.template;
     let mut errors = None;
-    let inp = lit.value();
-    if inp.contains('$') {
+    if lit.value().contains('$') {
         errors = Some(
             syn::Error::new(lit.span(), "Fragment is not allowed to contain `$`")
                 .into_compile_error(),
         );
     }
-    let res = quote!({
+    // the call site is recorded with the `provenance` feature of the runtime crate
+    let origin = quote!(::std::concat!(
+        ::std::file!(),
+        ":",
+        ::std::line!(),
+        " (",
+        ::std::module_path!(),
+        ")"
+    ));
+    if input.args.is_empty() {
+        return quote!({
+            #errors
+            ::pg_named_args::Fragment::with_origin(
+                ::pg_named_args::Fragment::new_unchecked(
+                    #lit,
+                    ::pg_named_args::__private::LITERAL,
+                ),
+                #origin,
+            )
+        })
+        .into();
+    }
+
+    // the names are replaced by `{}` and the fragments are passed in order, so the sql only
+    // comes from the literal and the fragments
+    let (template, names) = match interpolation(&lit.value()) {
+        Ok(res) => res,
+        Err(message) => {
+            return syn::Error::new(lit.span(), message)
+                .into_compile_error()
+                .into()
+        }
+    };
+    let mut fragments = vec![];
+    for name in &names {
+        match input.args.iter().find(|(arg, _)| arg == name) {
+            Some((_, expr)) => fragments.push(quote_spanned!(expr.span()=> &#expr)),
+            None => {
+                let message = format!("the fragment `{name}` is not given after the template");
+                return syn::Error::new(lit.span(), message)
+                    .into_compile_error()
+                    .into();
+            }
+        }
+    }
+    if let Some((arg, _)) = input
+        .args
+        .iter()
+        .find(|(arg, _)| !names.contains(&arg.to_string()))
+    {
+        let message = format!("the fragment `{arg}` is not used in the template");
+        return syn::Error::new(arg.span(), message)
+            .into_compile_error()
+            .into();
+    }
+    quote!({
         #errors
-        ::pg_named_args::Fragment::new_unchecked(#input_raw)
-    });
-    res.into()
+        ::pg_named_args::Fragment::with_origin(
+            ::pg_named_args::Fragment::interpolated(
+                #template,
+                &[#(#fragments),*],
+                ::pg_named_args::__private::LITERAL,
+            ),
+            #origin,
+        )
+    })
+    .into()
+}
+
+/// Replaces the `{name}` of a [fragment!] template with `{}`, returning the template and the
+/// names in order. The `{{` and `}}` escapes are kept.
+fn interpolation(inp: &str) -> Result<(String, Vec<String>), String> {
+    let mut template = String::new();
+    let mut names = vec![];
+    let mut rest = inp;
+    while let Some(pos) = rest.find(['{', '}']) {
+        template.push_str(&rest[..pos]);
+        let brace = &rest[pos..pos + 1];
+        rest = &rest[pos + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            template.push_str(&brace.repeat(2));
+            rest = after;
+            continue;
+        }
+        if brace == "}" {
+            return Err("unmatched `}` in the template, use `}}` for a literal brace".to_owned());
+        }
+        let len = rest
+            .find('}')
+            .ok_or("unmatched `{` in the template, use `{{` for a literal brace")?;
+        let name = &rest[..len];
+        if !name.starts_with(|x: char| x.is_alphabetic() || x == '_')
+            || !name.chars().all(|x| x.is_alphanumeric() || x == '_')
+        {
+            return Err(format!("expected the name of a fragment in `{{{name}}}`"));
+        }
+        template.push_str("{}");
+        names.push(name.to_owned());
+        rest = &rest[len + 1..];
+    }
+    template.push_str(rest);
+    Ok((template, names))
+}
+
+/// The input of [fragment!], the template and the fragments interpolated into the template.
+struct FragmentInput {
+    template: LitStr,
+    args: Vec<(Ident, Expr)>,
+}
+
+impl Parse for FragmentInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let template = input.parse()?;
+        let mut args = vec![];
+        while input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            // `name` is short for `name = name`, like in `format!`
+            let name: Ident = input.parse()?;
+            let expr = match input.parse::<Option<Token![=]>>()? {
+                Some(_) => input.parse()?,
+                None => parse_quote!(#name),
+            };
+            args.push((name, expr));
+        }
+        Ok(Self { template, args })
+    }
+}
+
+/// Runs an async test with a transaction, which is rolled back afterwards.
+///
+/// The test function takes the transaction as its only argument. The connection is made to
+/// the database in the `DATABASE_URL` environment variable, without TLS.
+///
+/// ```ignore
+/// #[pg_test]
+/// async fn insert_fred(txn: &tokio_postgres::Transaction<'_>) {
+///     let name = "Fred";
+///     let (query, params) = query!("INSERT INTO flintstone(name) VALUES($name)", Args { name });
+///     txn.execute_named(&query, params).await.unwrap();
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn pg_test(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let mut test = parse_macro_input!(item as ItemFn);
+    if !attr.is_empty() {
+        let attr = TokenStream::from(attr);
+        return syn::Error::new_spanned(attr, "`pg_test` has no options")
+            .to_compile_error()
+            .into();
+    }
+    if test.sig.asyncness.is_none() {
+        return syn::Error::new_spanned(test.sig.fn_token, "`pg_test` requires an async function")
+            .to_compile_error()
+            .into();
+    }
+    if test.sig.inputs.len() != 1 {
+        return syn::Error::new_spanned(
+            &test.sig.inputs,
+            "`pg_test` requires the transaction as the only argument",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    // the attributes like `#[ignore]` belong to the test itself.
+    let attrs = std::mem::take(&mut test.attrs);
+    let vis = &test.vis;
+    let name = &test.sig.ident;
+    let output = &test.sig.output;
+    quote!(
+        #(#attrs)*
+        #[test]
+        #vis fn #name() #output {
+            #test
+
+            ::pg_named_args::__private::pg_test(|txn| ::std::boxed::Box::pin(#name(txn)))
+        }
+    )
+    .into()
 }
 
 #[cfg(test)]
@@ -365,7 +2973,14 @@ mod tests {
         let mut names = vec![];
         let mut fragments = vec![];
         let inp = LitStr::new(format, Span::call_site());
-        let res = rewrite_query(inp, &mut names, &mut errors, &mut fragments);
+        let res = rewrite_query(
+            inp,
+            &mut names,
+            &mut errors,
+            &mut fragments,
+            &mut vec![],
+            &mut vec![],
+        );
         if errors.is_empty() {
             Ok(res.value())
         } else {
@@ -384,6 +2999,10 @@ VALUES(true, $[..]);
 INSERT INTO fred_flintstone(a, b, c)
 VALUES(true, $b, $c);
             ",
+            r"
+INSERT INTO fred_flintstone(a, $[b, c,])
+VALUES(true, $[..]);
+            ",
         ];
 
         for format in tests {
@@ -396,6 +3015,273 @@ VALUES(true, $1, $2);
         }
     }
 
+    #[test]
+    fn rewrite_query_should_strip_comments_in_groups() {
+        let format = r"
+INSERT INTO fred_flintstone($[
+    a, -- legacy, drop in v3
+    -- b,
+    c, -- ]
+])
+VALUES($[..]);";
+        let expected = "\nINSERT INTO fred_flintstone(\n    a, \n    \n    c \n)\nVALUES($1, $2);";
+        assert_eq!(rewrite_query_wrapper(format).unwrap(), expected);
+    }
+
+    #[test]
+    fn rewrite_query_should_support_quoted_identifiers_in_groups() {
+        let format =
+            r#"INSERT INTO orders($[id, "Order Total", "Ship.To ""Name""", "a,b"]) VALUES($[..])"#;
+        let expected = r#"INSERT INTO orders(id, "Order Total", "Ship.To ""Name""", "a,b") VALUES($1, $2, $3, $4)"#;
+        let mut errors = vec![];
+        let mut names = vec![];
+        let inp = LitStr::new(format, Span::call_site());
+        let res = rewrite_query(
+            inp,
+            &mut names,
+            &mut errors,
+            &mut vec![],
+            &mut vec![],
+            &mut vec![],
+        );
+        assert!(errors.is_empty());
+        assert_eq!(res.value(), expected);
+        assert_eq!(names, ["id", "order_total", "ship_to_name", "a_b"]);
+    }
+
+    #[test]
+    fn rewrite_query_should_expand_like_modifiers() {
+        let escaped = r"replace(replace(replace($1, '\', '\\'), '%', '\%'), '_', '\_')";
+        let tests = [
+            ("$a~contains", format!("('%' || {escaped} || '%')")),
+            ("$a~starts_with", format!("({escaped} || '%')")),
+            ("$a~ends_with)", format!("('%' || {escaped}))")),
+            ("$a ~ b", "$1 ~ b".to_owned()),
+            ("$a~'^b'", "$1~'^b'".to_owned()),
+            // any other word is a regex match
+            ("$a~x", "$1~x".to_owned()),
+            ("$a~like", "$1~like".to_owned()),
+            ("$a~containsx", "$1~containsx".to_owned()),
+        ];
+        for (format, expected) in tests {
+            assert_eq!(rewrite_query_wrapper(format).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn rewrite_query_should_expand_text_search_modifiers() {
+        let tests = [
+            ("$a~websearch", "websearch_to_tsquery($1)"),
+            ("$a~plain($b)", "plainto_tsquery($2::regconfig, $1)"),
+            (
+                "ts_rank(d, $a~phrase($b)) @@ $a~phrase($b)",
+                "ts_rank(d, phraseto_tsquery($2::regconfig, $1)) @@ phraseto_tsquery($2::regconfig, $1)",
+            ),
+            ("$a~websearch ($b)", "websearch_to_tsquery($1) ($2)"),
+        ];
+        for (format, expected) in tests {
+            assert_eq!(rewrite_query_wrapper(format).unwrap(), expected);
+        }
+
+        let errors = rewrite_query_wrapper("$a~contains($b)").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "the `contains` modifier doesn't take a text search configuration"
+        );
+        let errors = rewrite_query_wrapper("$a~plain($)").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "expected a named argument in `~modifier($config)`"
+        );
+    }
+
+    #[test]
+    fn variants_should_expand_every_combination() {
+        let (choices, expanded) =
+            variants("SELECT * FROM t WHERE a = $a $when(b) { AND b } $when(c) {OR '{}'}").unwrap();
+        let args: Vec<_> = choices.iter().map(|x| &*x.arg).collect();
+        assert_eq!(args, ["b", "c"]);
+        assert_eq!(
+            expanded,
+            [
+                "SELECT * FROM t WHERE a = $a  ",
+                "SELECT * FROM t WHERE a = $a  AND b  ",
+                "SELECT * FROM t WHERE a = $a  OR '{}'",
+                "SELECT * FROM t WHERE a = $a  AND b  OR '{}'",
+            ]
+        );
+
+        let (choices, expanded) = variants(
+            "SELECT $match(m) { M::A => { a }, M::B(_) => {b} } $when(c) { c } $when(c) {d}",
+        )
+        .unwrap();
+        assert_eq!(choices[0].patterns, ["M::A", "M::B(_)"]);
+        assert_eq!(choices[1].patterns, ["false", "true"]);
+        assert_eq!(
+            expanded,
+            [
+                "SELECT  a   ",
+                "SELECT b  ",
+                "SELECT  a   c  d",
+                "SELECT b  c  d",
+            ]
+        );
+
+        let (choices, expanded) = variants("SELECT $a").unwrap();
+        assert!(choices.is_empty());
+        assert_eq!(expanded, ["SELECT $a"]);
+
+        let tests = [
+            ("$when(a) AND b", "expected `$when(flag) { ... }`"),
+            ("$when(a) { AND b", "expected closing `}` of the `$when` section"),
+            (
+                "$when(a) { $match(b) { c } }",
+                "`$when` and `$match` sections can't be nested",
+            ),
+            (
+                "$when(a){} $when(b){} $when(c){} $when(d){} $when(e){}",
+                "a query can have at most 16 combinations of `$when` and `$match` arms",
+            ),
+            ("$match(a) a", "expected `$match(arg) { Pattern => { ... } }`"),
+            ("$match(a) {}", "expected at least one arm in `$match`"),
+            ("$match(a) { M::A => a }", "expected `Pattern => { ... }` in `$match`"),
+            (
+                "$match(a) { Fast => {} }",
+                "expected the path of a variant, like `Mode::Fast`, a single name matches any value",
+            ),
+            ("$match(a) { + => {} }", "expected a pattern, found `+`"),
+        ];
+        for (inp, expected) in tests {
+            assert_eq!(variants(inp).err().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn for_loop_should_rename_the_item() {
+        let parts = for_loop("VALUES $for(x in $xs) { ($x, $x.a, $xs, $y) } RETURNING *")
+            .unwrap()
+            .unwrap();
+        assert_eq!((&*parts.item, &*parts.items), ("x", "xs"));
+        assert_eq!(parts.separator, ",");
+        assert_eq!(parts.outer, "VALUES \u{1} RETURNING *");
+        assert_eq!(parts.body, " ($__item_, $__item_a, $xs, $y) ");
+
+        let parts = for_loop("$for(x in $xs, UNION ALL) {SELECT $x}")
+            .unwrap()
+            .unwrap();
+        assert_eq!(parts.separator, "UNION ALL");
+        assert!(for_loop("SELECT $a").unwrap().is_none());
+
+        let tests = [
+            ("$for(x $xs) {}", "expected `$for(item in $items) { ... }`"),
+            (
+                "$for(x in xs) {}",
+                "expected `$for(item in $items) { ... }`",
+            ),
+            ("$for(x in $xs)", "expected `$for(item in $items) { ... }`"),
+            (
+                "$for(x in $xs) { $x",
+                "expected closing `}` of the `$for` loop",
+            ),
+            (
+                "$for(x in $xs) {} $for(y in $ys) {}",
+                "only a single `$for` loop is supported in a query",
+            ),
+        ];
+        for (inp, expected) in tests {
+            assert_eq!(for_loop(inp).err().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn qmark_should_repeat_the_arguments() {
+        let names = ["a".to_owned(), "b".to_owned()];
+        let (template, occurrences) = qmark("SELECT $1, $2 WHERE $1 = x", &names);
+        assert_eq!(template, "SELECT ?, ? WHERE ? = x");
+        assert_eq!(occurrences, ["a", "b", "a"]);
+
+        assert!(has_modifier("x LIKE $a~contains"));
+        assert!(!has_modifier("x ~ $a AND $b ~ 'c'"));
+        assert!(!has_modifier("$a~x"));
+    }
+
+    #[test]
+    fn check_read_only_should_reject_writes() {
+        let tests = [
+            ("SELECT * FROM t WHERE a = $update", Ok(())),
+            ("(SELECT 1) UNION (SELECT 2)", Ok(())),
+            ("WITH x AS (SELECT 1) SELECT * FROM x -- DELETE", Ok(())),
+            (
+                r#"SELECT E'\'DELETE', 'INSERT''s', /* INTO */ "update""#,
+                Ok(()),
+            ),
+            (
+                "UPDATE t SET a = 1",
+                Err("the `readonly` option requires a `SELECT` statement"),
+            ),
+            (
+                "WITH x AS (DELETE FROM t RETURNING *) SELECT * FROM x",
+                Err("the `readonly` option does not allow `DELETE` in the query"),
+            ),
+            (
+                "SELECT * FROM t FOR UPDATE SKIP LOCKED",
+                Err("the `readonly` option does not allow `UPDATE` in the query"),
+            ),
+            (
+                "SELECT * INTO t2 FROM t",
+                Err("the `readonly` option does not allow `INTO` in the query"),
+            ),
+            // unreserved keywords can be column names
+            ("SELECT share FROM holdings", Ok(())),
+            ("SELECT t.update, t.into FROM t", Ok(())),
+            ("SELECT update, delete, insert FROM t AS merge", Ok(())),
+            ("SELECT substring(a FROM 1 FOR 2) FROM t", Ok(())),
+            (
+                "SELECT * FROM t FOR NO KEY UPDATE",
+                Err("the `readonly` option does not allow `UPDATE` in the query"),
+            ),
+            (
+                "SELECT * FROM (SELECT * FROM t FOR KEY SHARE) x",
+                Err("the `readonly` option does not allow `SHARE` in the query"),
+            ),
+            (
+                "WITH x AS MATERIALIZED (INSERT INTO t DEFAULT VALUES RETURNING *) TABLE x",
+                Err("the `readonly` option does not allow `INSERT` in the query"),
+            ),
+        ];
+        for (sql, expected) in tests {
+            assert_eq!(
+                check_read_only(&keywords(sql)),
+                expected.map_err(str::to_owned),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn rewrite_query_should_keep_names_after_errors() {
+        let tests = [
+            ("SELECT $[a, b WHERE c = $c AND $s.t", vec!["a", "b", "c"]),
+            ("SELECT $a, $ + $b", vec!["a", "b"]),
+            ("SELECT $a~plain($) $b", vec!["a", "b"]),
+        ];
+        for (format, expected) in tests {
+            let mut errors = vec![];
+            let mut names = vec![];
+            let inp = LitStr::new(format, Span::call_site());
+            rewrite_query(
+                inp,
+                &mut names,
+                &mut errors,
+                &mut vec![],
+                &mut vec![],
+                &mut vec![],
+            );
+            assert_eq!(errors.len(), 1);
+            assert_eq!(names, expected);
+        }
+    }
+
     #[test]
     fn rewrite_query_should_error_on_unused() {
         let tests = [
@@ -463,7 +3349,7 @@ INSERT INTO some_table (
             (
                 r"
 INSERT INTO some_table (
-    $[one, two,]
+    $[one, , two]
 ) VALUES (
     $[..]
 );
@@ -479,4 +3365,261 @@ INSERT INTO some_table (
             assert_eq!(error_msgs[0], err);
         }
     }
+
+    #[test]
+    fn usage_should_show_the_line_of_the_argument() {
+        let inp = "SELECT *\n    FROM    cities\n    WHERE location = $location\n";
+        let pos = inp.find('$').unwrap();
+        assert_eq!(usage(inp, pos), "used in: WHERE location = $location");
+        assert_eq!(usage("$a, $b", 4), "used in: $a, $b");
+    }
+
+    #[test]
+    fn manifest_entry_should_be_json() {
+        let names = ["id".to_owned(), "na\"me".to_owned()];
+        let sql = "SELECT *\nFROM t WHERE id = $1 AND name = $2";
+        let entry = manifest_entry(None, sql, &[], &names, "src/main.rs:3", 10);
+        let expected = r#"{"name":null,"sql":"SELECT *\nFROM t WHERE id = $1 AND name = $2","fragments":[],"params":["id","na\"me"],"call_site":"src/main.rs:3","fingerprint":"000000000000000a"}"#;
+        assert_eq!(entry, expected);
+        let entry = manifest_entry(Some("get_user"), "SELECT 1", &[], &[], "src/main.rs:3", 10);
+        assert!(entry.starts_with(r#"{"name":"get_user","sql":"SELECT 1","fragments":[],"#));
+    }
+
+    #[test]
+    fn slots_should_be_marked_in_the_manifest() {
+        let fragments = [
+            ("schema".to_owned(), Slot::Schema),
+            (format!("{WHERE_CLAUSE}0"), Slot::Fragment),
+            ("order_by".to_owned(), Slot::OrderBy),
+        ];
+        let (sql, slots) = mark_slots("SELECT '{{}}' FROM {}.t {} ORDER BY {}", &fragments);
+        assert_eq!(
+            sql,
+            "SELECT '{}' FROM ${schema}.t ${where} ORDER BY ${order_by}"
+        );
+        assert_eq!(slots, ["schema", "where", "order_by"]);
+    }
+
+    #[test]
+    fn check_insert_groups_should_require_groups() {
+        let ok = [
+            "INSERT INTO t($[a, b]) VALUES($[..])",
+            "insert into \"my table\" AS x ( $[a] ) values ($[..]) RETURNING id",
+            "INSERT INTO s.t($[a, b]) SELECT $a, $b",
+            "INSERT INTO t DEFAULT VALUES",
+            "WITH x AS (SELECT 1) INSERT INTO t($[a]) VALUES($[..])",
+        ];
+        for sql in ok {
+            assert_eq!(check_insert_groups(sql), Ok(()), "{sql}");
+        }
+
+        let columns = "the columns of an `INSERT` statement should be a `$[...]` group";
+        let values = "the values of an `INSERT` statement should be the `$[..]` group";
+        let err = [
+            ("INSERT INTO t(a, b) VALUES($a, $b)", columns),
+            ("INSERT INTO t VALUES($a, $b)", columns),
+            ("INSERT INTO t SELECT * FROM u", columns),
+            ("INSERT INTO t($[a, b]) VALUES($a, $b)", values),
+        ];
+        for (sql, message) in err {
+            assert_eq!(check_insert_groups(sql), Err(message.to_owned()), "{sql}");
+        }
+    }
+
+    #[test]
+    fn where_clauses_should_keep_the_conditions_without_fragments() {
+        let mut clauses = vec![];
+        let res = expand_where_clauses(
+            "SELECT 1 $where{a = 'x AND y' AND ${b} and (c AND d) AND ${e: where}} LIMIT 1",
+            &mut clauses,
+        );
+        assert_eq!(
+            res.unwrap(),
+            "SELECT 1 WHERE (a = 'x AND y') AND ((c AND d))${__where_0} LIMIT 1"
+        );
+        assert!(clauses[0].has_conditions);
+        assert_eq!(
+            clauses[0].fragments,
+            [
+                ("b".to_owned(), Slot::Fragment),
+                ("e".to_owned(), Slot::Where)
+            ]
+        );
+
+        let res = expand_where_clauses("$where{${a}} $where{a AND AND b}", &mut clauses);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn qmark_placeholders_should_be_detected() {
+        let tests = [
+            ("SELECT * FROM t WHERE id = ?", true),
+            ("SELECT * FROM t WHERE id IN (?, ?)", true),
+            ("SELECT * FROM t LIMIT ?", true),
+            ("SELECT * FROM t WHERE data ? 'key'", false),
+            ("SELECT * FROM t WHERE data ?| array['a']", false),
+            ("SELECT '?', \"a?\" FROM t -- id = ?\n", false),
+        ];
+        for (inp, expected) in tests {
+            assert_eq!(has_qmark_placeholder(inp), expected, "{inp}");
+        }
+    }
+
+    #[test]
+    fn upserts_should_update_the_other_columns() {
+        let keys = ["location".to_owned(), "order_total".to_owned()];
+        let tests = [
+            (
+                "INSERT INTO t ($[location, \"Order Total\", report,]) VALUES ($[..]);",
+                Ok("INSERT INTO t ($[location, \"Order Total\", report,]) VALUES ($[..]) \
+                    ON CONFLICT (location, \"Order Total\") DO UPDATE SET report = excluded.report"),
+            ),
+            (
+                "INSERT INTO t ($[\n  location, -- the key\n  \"Order Total\"\n]) VALUES ($[..]) returning id",
+                Ok("INSERT INTO t ($[\n  location, -- the key\n  \"Order Total\"\n]) VALUES ($[..]) \
+                    ON CONFLICT (location, \"Order Total\") DO NOTHING returning id"),
+            ),
+            (
+                "INSERT INTO t ($[location, order_total, report?]) VALUES ($[..])",
+                Err("the columns of `upsert_args!` need to be known at compile time, \
+                    which optional columns and fragments aren't"),
+            ),
+            (
+                "INSERT INTO t ($[location, report]) VALUES ($[..])",
+                Err("the conflict column `order_total` is not a column of the group"),
+            ),
+            (
+                "INSERT INTO t (location) VALUES ($location)",
+                Err("expected a `$[...]` group with the columns of the insert"),
+            ),
+        ];
+        for (inp, expected) in tests {
+            let expected = expected.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(upsert(inp, &keys), expected, "{inp}");
+        }
+    }
+
+    #[test]
+    fn rows_should_be_capped() {
+        let tests = [
+            ("SELECT * FROM t;", Ok("SELECT * FROM t LIMIT 100")),
+            (
+                "SELECT * FROM t -- all\n",
+                Ok("SELECT * FROM t -- all\nLIMIT 100"),
+            ),
+            (
+                "SELECT * FROM t -- all",
+                Ok("SELECT * FROM t -- all\nLIMIT 100"),
+            ),
+            ("SELECT * FROM t limit 10", Ok("SELECT * FROM t limit 10")),
+            (
+                "SELECT * FROM t LIMIT $limit OFFSET $offset",
+                Ok("SELECT * FROM t LIMIT LEAST($limit, 100::bigint) OFFSET $offset"),
+            ),
+            (
+                "SELECT * FROM (SELECT * FROM t LIMIT 1000) t WHERE a = 'LIMIT'",
+                Ok("SELECT * FROM (SELECT * FROM t LIMIT 1000) t WHERE a = 'LIMIT' LIMIT 100"),
+            ),
+            (
+                "SELECT * FROM t LIMIT 1000",
+                Err("the `LIMIT 1000` is above the maximum of 100 rows of the `max_rows` option"),
+            ),
+            (
+                "SELECT * FROM t LIMIT ALL",
+                Err("the `max_rows` option requires a number or a named argument after `LIMIT`"),
+            ),
+            (
+                "SELECT * FROM t FETCH FIRST 10 ROWS ONLY",
+                Err("the `max_rows` option requires `LIMIT` instead of `FETCH`"),
+            ),
+        ];
+        for (inp, expected) in tests {
+            let expected = expected.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(cap_rows(inp, 100), expected, "{inp}");
+        }
+    }
+
+    #[test]
+    fn json_objects_should_pair_the_keys_and_the_arguments() {
+        assert_eq!(
+            json_objects("SELECT $json{name, email,} FROM t WHERE id = $id"),
+            Ok(
+                "SELECT jsonb_build_object('name', $name, 'email', $email) FROM t WHERE id = $id"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            json_objects("SELECT $json{}"),
+            Err("expected names in `$json{...}`, found ``".to_owned())
+        );
+        assert_eq!(
+            json_objects("SELECT $json{a b}"),
+            Err("expected names in `$json{...}`, found `a b`".to_owned())
+        );
+        assert!(json_objects("SELECT $json{a").is_err());
+    }
+
+    #[test]
+    fn dedent_should_strip_common_indentation() {
+        let tests = [
+            (
+                "
+            SELECT *
+            FROM fred_flintstone
+                WHERE a = $a
+            ",
+                "SELECT *\nFROM fred_flintstone\n    WHERE a = $a",
+            ),
+            (
+                "SELECT *
+            FROM fred_flintstone
+
+            WHERE a = $a",
+                "SELECT *\nFROM fred_flintstone\n\nWHERE a = $a",
+            ),
+            ("   \n  \n", ""),
+        ];
+
+        for (format, expected) in tests {
+            assert_eq!(dedent(format), expected);
+        }
+    }
+
+    #[test]
+    fn minify_should_remove_comments_and_whitespace() {
+        let tests = [
+            (
+                "
+            SELECT a, -- the first column
+                b /* the /* nested */ second column */
+            FROM fred_flintstone
+            ",
+                "SELECT a, b FROM fred_flintstone",
+            ),
+            (
+                "SELECT 'a  -- b', \"c  /* d */\" FROM x",
+                "SELECT 'a  -- b', \"c  /* d */\" FROM x",
+            ),
+            (
+                "SELECT 'it''s  ',  E'it\\'s  ', \"a\"\"  b\"",
+                "SELECT 'it''s  ', E'it\\'s  ', \"a\"\"  b\"",
+            ),
+            ("SELECT a/**/FROM b", "SELECT a FROM b"),
+        ];
+
+        for (format, expected) in tests {
+            assert_eq!(minify(format), expected);
+        }
+    }
+
+    #[test]
+    fn sqlcommenter_should_append_sorted_and_encoded_tags() {
+        let tags = vec![
+            ("file".to_owned(), "src/orders.rs:123".to_owned()),
+            ("app".to_owned(), "check out's".to_owned()),
+        ];
+        let expected = "SELECT 1 /*app='check%20out%27s',file='src%2Forders.rs%3A123'*/";
+        assert_eq!(sqlcommenter("SELECT 1", tags.clone()), expected);
+        assert_eq!(sqlcommenter("SELECT 1 ;\n", tags), format!("{expected};"));
+    }
 }
@@ -0,0 +1,96 @@
+use std::fmt;
+
+#[cfg(feature = "tokio-postgres")]
+use postgres_types::ToSql;
+
+use crate::fingerprint;
+#[cfg(feature = "tokio-postgres")]
+use crate::Query;
+
+/// The key of an advisory lock, hashed from a name at compile time.
+///
+/// A single name is hashed into the `bigint` key space, a pair of names into the two `int`
+/// keys of `pg_advisory_lock(int, int)`, which is useful for a lock per class of jobs.
+/// The names are kept for logging, the locks themselves are taken with the advisory lock
+/// helpers of `ExecutorExt` of the `tokio-postgres` feature.
+///
+/// ```
+/// # use pg_named_args::AdvisoryLock;
+/// const REPORTS: AdvisoryLock = AdvisoryLock::new("weather_reports");
+/// const IMPORT: AdvisoryLock = AdvisoryLock::pair("import", "weather_reports");
+/// assert_eq!(REPORTS.to_string(), "weather_reports");
+/// assert_eq!(IMPORT.to_string(), "import/weather_reports");
+/// assert_ne!(REPORTS, AdvisoryLock::new("weather_stations"));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AdvisoryLock {
+    class: Option<&'static str>,
+    name: &'static str,
+    key: Key,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Single(i64),
+    Pair(i32, i32),
+}
+
+impl AdvisoryLock {
+    /// The lock with the `bigint` key hashed from the name.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            class: None,
+            name,
+            key: Key::Single(fingerprint(name) as i64),
+        }
+    }
+
+    /// The lock with the two `int` keys hashed from the class and the name.
+    pub const fn pair(class: &'static str, name: &'static str) -> Self {
+        const fn fold(hash: u64) -> i32 {
+            (hash ^ (hash >> 32)) as i32
+        }
+
+        Self {
+            class: Some(class),
+            name,
+            key: Key::Pair(fold(fingerprint(class)), fold(fingerprint(name))),
+        }
+    }
+
+    /// The class of a lock created with [AdvisoryLock::pair].
+    pub fn class(&self) -> Option<&'static str> {
+        self.class
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+#[cfg(feature = "tokio-postgres")]
+impl AdvisoryLock {
+    /// The call of the advisory lock function with the keys, like `pg_advisory_lock`.
+    pub(crate) fn call(&self, function: &str) -> Query {
+        match self.key {
+            Key::Single(_) => Query::from_sql(format!("SELECT {function}($1)")),
+            Key::Pair(..) => Query::from_sql(format!("SELECT {function}($1, $2)")),
+        }
+    }
+
+    pub(crate) fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        match &self.key {
+            Key::Single(key) => vec![key],
+            Key::Pair(class, key) => vec![class, key],
+        }
+    }
+}
+
+impl fmt::Display for AdvisoryLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(class) = self.class {
+            write!(f, "{class}/")?;
+        }
+        f.write_str(self.name)
+    }
+}
@@ -0,0 +1,81 @@
+/// Asserts that two queries are equal, ignoring differences in whitespace and case.
+///
+/// Quoted strings and identifiers are compared exactly. The arguments can be anything that
+/// implements `AsRef<str>`, like the output of [query_args!](crate::query_args!) or a
+/// [Query](crate::Query). On failure, the normalized queries are shown with a marker at the
+/// first difference.
+///
+/// ```
+/// # use pg_named_args::{assert_query_eq, query_args};
+/// let name = "Fred";
+/// let (query, _) = query_args!(
+///     r"
+///     SELECT *
+///     FROM flintstone
+///     WHERE name = $name
+///     ",
+///     Args { name }
+/// );
+/// assert_query_eq!(query, "select * from flintstone where name = $1");
+/// ```
+#[macro_export]
+macro_rules! assert_query_eq {
+    ($left:expr, $right:expr $(,)?) => {
+        $crate::__private::assert_query_eq(
+            ::std::convert::AsRef::<str>::as_ref(&$left),
+            ::std::convert::AsRef::<str>::as_ref(&$right),
+        )
+    };
+}
+
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_query_eq(left: &str, right: &str) {
+    let (left, right) = (normalize(left), normalize(right));
+    if left == right {
+        return;
+    }
+
+    let diff = left
+        .chars()
+        .zip(right.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    panic!(
+        "assertion `left == right` failed (normalized queries)\n  left: {left}\n right: {right}\n{:>width$}^ first difference",
+        "",
+        width = diff + 8,
+    );
+}
+
+/// Collapses whitespace, removes it around punctuation and lowercases everything outside of
+/// quoted strings and identifiers.
+fn normalize(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut space = false;
+    let mut chars = sql.trim().chars();
+    while let Some(x) = chars.next() {
+        if x.is_whitespace() {
+            space = true;
+            continue;
+        }
+        let punctuation = matches!(x, '(' | ')' | ',' | ';');
+        if space && !punctuation && !out.ends_with(['(', ',']) {
+            out.push(' ');
+        }
+        space = false;
+
+        if x == '\'' || x == '"' {
+            out.push(x);
+            for y in chars.by_ref() {
+                out.push(y);
+                if y == x {
+                    break;
+                }
+            }
+        } else {
+            out.extend(x.to_lowercase());
+        }
+    }
+    out
+}
@@ -0,0 +1,181 @@
+//! Lists the queries in the manifest that is written by the `manifest` feature, and optionally
+//! explains them against a database, as a report for review.
+
+use std::{env, fs, io, path::Path, path::PathBuf, process::ExitCode};
+
+use serde::Deserialize;
+use tokio_postgres::{NoTls, SimpleQueryMessage};
+
+const USAGE: &str = "\
+usage: pg_named_args [--explain] [DIR]
+
+Lists the queries in the manifest directory, which is .pg_named_args by default. With --explain, the generic plan of every query is added,
+from the database in DATABASE_URL, which needs PostgreSQL 16 or later. Queries with
+fragments are not explained, as the fragments are only known at runtime.";
+
+/// A query in the manifest.
+#[derive(Deserialize)]
+struct Entry {
+    name: Option<String>,
+    sql: String,
+    /// The names of the fragments, which are marked as `${name}` in the sql.
+    #[serde(default)]
+    fragments: Vec<String>,
+    params: Vec<String>,
+    call_site: String,
+    fingerprint: String,
+}
+
+fn main() -> ExitCode {
+    let mut explain = false;
+    let mut dir = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--explain" => explain = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ if dir.is_none() && !arg.starts_with('-') => dir = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("{USAGE}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let dir = dir.unwrap_or_else(|| PathBuf::from(".pg_named_args"));
+
+    let entries = match read_entries(&dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            eprintln!("failed to read the manifest in {}: {err}", dir.display());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let statements: Vec<_> = entries.iter().map(statement).collect();
+    let mut plans = vec![];
+    if explain {
+        let explained: Vec<_> = statements
+            .iter()
+            .filter_map(|x| x.as_ref().ok().copied())
+            .collect();
+        // the entries that can't be explained don't need a database
+        if !explained.is_empty() {
+            match explain_all(&explained) {
+                Ok(res) => plans = res,
+                Err(err) => {
+                    eprintln!("failed to connect to DATABASE_URL: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    }
+    let mut plans = plans.into_iter();
+
+    let mut failed = false;
+    for (idx, entry) in entries.iter().enumerate() {
+        println!("-- {} ({})", entry.call_site, entry.fingerprint);
+        if let Some(name) = &entry.name {
+            println!("-- prepared as {name}");
+        }
+        for (idx, param) in entry.params.iter().enumerate() {
+            println!("-- ${} {param}", idx + 1);
+        }
+        println!("{};", entry.sql.trim_end().trim_end_matches(';'));
+        match &statements[idx] {
+            Err(reason) if explain => println!("-- not explained: {reason}"),
+            Err(_) => {}
+            Ok(_) => match plans.next() {
+                Some(Ok(plan)) => plan.iter().for_each(|line| println!("--   {line}")),
+                Some(Err(err)) => {
+                    failed = true;
+                    println!("-- failed to explain: {err}");
+                }
+                None => {}
+            },
+        }
+        println!();
+    }
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
+
+/// Reads the entries of the manifest, ordered by their call site.
+fn read_entries(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries = vec![];
+    for file in fs::read_dir(dir)? {
+        let path = file?.path();
+        if path.extension().is_none_or(|x| x != "json") {
+            continue;
+        }
+        let entry: Entry = serde_json::from_slice(&fs::read(&path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        entries.push(entry);
+    }
+    // the lines are compared as numbers
+    entries.sort_by_cached_key(|entry| match entry.call_site.rsplit_once(':') {
+        Some((file, line)) => (file.to_owned(), line.parse().unwrap_or(0)),
+        None => (entry.call_site.clone(), 0),
+    });
+    Ok(entries)
+}
+
+/// The statement of the entry that is explained, or the reason it can't be explained.
+fn statement(entry: &Entry) -> Result<&str, String> {
+    if !entry.fragments.is_empty() {
+        let names: Vec<_> = entry
+            .fragments
+            .iter()
+            .map(|x| format!("${{{x}}}"))
+            .collect();
+        return Err(format!(
+            "the fragments {} are only known at runtime",
+            names.join(", ")
+        ));
+    }
+    // a manifest can still contain the `PREPARE name AS` of the `prepare` option
+    let prepared = entry.sql.strip_prefix("PREPARE ");
+    match prepared.and_then(|x| x.split_once(" AS ")) {
+        Some((_, statement)) => Ok(statement),
+        None => Ok(&entry.sql),
+    }
+}
+
+/// The generic plan of every statement, or the error of the database.
+fn explain_all(statements: &[&str]) -> Result<Vec<Result<Vec<String>, String>>, String> {
+    let url = env::var("DATABASE_URL").map_err(|err| err.to_string())?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| err.to_string())?;
+    runtime.block_on(async {
+        let (client, connection) = tokio_postgres::connect(&url, NoTls)
+            .await
+            .map_err(|err| err.to_string())?;
+        tokio::spawn(connection);
+
+        let mut plans = vec![];
+        for statement in statements {
+            // the generic plan doesn't need the values of the parameters
+            let sql = format!("EXPLAIN (GENERIC_PLAN) {statement}");
+            let plan = client
+                .simple_query(&sql)
+                .await
+                .map_err(|err| match err.as_db_error() {
+                    Some(err) => err.message().to_owned(),
+                    None => err.to_string(),
+                });
+            plans.push(plan.map(|messages| {
+                let rows = messages.iter().filter_map(|message| match message {
+                    SimpleQueryMessage::Row(row) => row.get(0).map(str::to_owned),
+                    _ => None,
+                });
+                rows.collect()
+            }));
+        }
+        Ok(plans)
+    })
+}
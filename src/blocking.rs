@@ -0,0 +1,111 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Wake, Waker},
+};
+
+use postgres::{GenericClient, Row};
+use postgres_types::ToSql;
+use tokio_postgres::Error;
+
+use crate::{client::instrument, Executor, Query};
+
+/// An [Executor] for the clients and transactions of the blocking `postgres` crate, so code
+/// that is generic over the executor can be used without an async runtime.
+///
+/// The executions block the current thread, so the futures are ready when they are polled for
+/// the first time. The futures can be run with [block_on]. The client is behind a
+/// mutex, because the blocking clients need `&mut self` to execute a query.
+///
+/// ```no_run
+/// # use pg_named_args::{block_on, query, Blocking, Executor};
+/// async fn count<E: Executor>(client: &E) -> Result<u64, E::Error> {
+///     let location = "sweden";
+///     let (query, params) = query!(
+///         "DELETE FROM weather_reports WHERE location = $location",
+///         Args { location }
+///     );
+///     client.execute_named(&query, params).await
+/// }
+///
+/// # fn example() -> Result<(), postgres::Error> {
+/// let client = postgres::Client::connect("host=localhost", postgres::NoTls)?;
+/// let client = Blocking::new(client);
+/// let deleted = block_on(count(&client))?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Blocking<C> {
+    inner: Mutex<C>,
+}
+
+impl<C> Blocking<C> {
+    /// Wraps the client or transaction.
+    pub fn new(client: C) -> Self {
+        Self {
+            inner: Mutex::new(client),
+        }
+    }
+
+    /// The wrapped client or transaction, to commit a transaction for example.
+    pub fn into_inner(self) -> C {
+        self.inner
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<C: GenericClient + Send> Executor for Blocking<C> {
+    type Row = Row;
+    type Error = Error;
+
+    async fn query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, Error> {
+        let execution = async {
+            let mut client = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+            client.query(query.sql(), params)
+        };
+        instrument(query, execution, |rows| rows.len() as u64).await
+    }
+
+    async fn execute_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, Error> {
+        let execution = async {
+            let mut client = self.inner.lock().unwrap_or_else(|err| err.into_inner());
+            client.execute(query.sql(), params)
+        };
+        instrument(query, execution, |rows| *rows).await
+    }
+}
+
+/// Runs the future on the current thread until it is ready, for the futures of [Blocking].
+///
+/// The futures of [Blocking] are ready right away, other futures park the thread until they
+/// are woken.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(std::thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+/// The conditions of a `$where{...}` clause that are added at runtime, from the fragments that
+/// are not empty.
+///
+/// The conditions are added with `AND` to the conditions that are always included, or start
+/// the `WHERE` clause when there are none of those.
+#[doc(hidden)]
+pub fn conditions(has_conditions: bool, fragments: &[&str]) -> String {
+    let mut sql = String::new();
+    for fragment in fragments.iter().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        match has_conditions || !sql.is_empty() {
+            true => sql.push_str(" AND "),
+            false => sql.push_str("WHERE "),
+        }
+        sql.push_str(&format!("({fragment})"));
+    }
+    sql
+}
@@ -0,0 +1,934 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use futures_util::{stream, SinkExt, Stream, TryStreamExt};
+use tokio_postgres::{
+    binary_copy::{BinaryCopyInWriter, BinaryCopyOutStream},
+    types::{ToSql, Type},
+    Client, CopyOutStream, Error, GenericClient, Portal, Row, RowStream, SimpleQueryMessage,
+    Transaction,
+};
+
+#[cfg(feature = "serde")]
+use crate::explain::Explain;
+use crate::{
+    AdvisoryLock, CsvCopy, CsvRow, Executor, Identifier, Query, Settings, StagingTable,
+    StatementKind,
+};
+#[cfg(feature = "serde")]
+use tokio_postgres::types::Json;
+
+/// Execution helpers for the clients and transactions of `tokio-postgres`, in addition to the
+/// methods of [Executor] and [ExecutorExt].
+///
+/// The helpers execute a [Query] created by the [query!](crate::query!) macro.
+/// With the `tracing` feature enabled, every execution is wrapped in a span.
+/// With the `metrics` feature enabled, the number of executions, the errors and the
+/// duration are recorded per query fingerprint.
+///
+/// ```no_run
+/// # use pg_named_args::{query, Executor};
+/// # async fn example(client: &tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+/// let location = "sweden";
+/// let (query, params) = query!(
+///     "SELECT report FROM weather_reports WHERE location = $location",
+///     Args { location }
+/// );
+/// let rows = client.query_named(&query, params).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait ClientExt {
+    /// Executes the statements using the simple query protocol, with the arguments inlined
+    /// as escaped literals, see [Query::inline].
+    fn simple_query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Vec<SimpleQueryMessage>, crate::Error>> + Send;
+
+    /// Streams the rows of the query as they are received, using `query_raw`.
+    ///
+    /// Unlike [ExecutorExt::query_cursor_named], all rows are sent by the server at once and the
+    /// client doesn't need to be in a transaction. The rows are not buffered by the client.
+    ///
+    /// ```no_run
+    /// # use futures_util::TryStreamExt;
+    /// # use pg_named_args::{query, ClientExt};
+    /// # async fn example(client: &tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+    /// let (query, params) = query!("SELECT * FROM weather_reports", Args {});
+    /// let mut rows = std::pin::pin!(client.query_stream_named(&query, params).await?);
+    /// while let Some(row) = rows.try_next().await? {
+    ///     // export the row
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn query_stream_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<RowStream, Error>> + Send;
+
+    /// Exports the rows of the query with `COPY (...) TO STDOUT (FORMAT BINARY)`, returning
+    /// the raw bytes of the binary copy format.
+    ///
+    /// `COPY` doesn't accept bind parameters, so the arguments are inlined as escaped literals,
+    /// see [Query::inline].
+    fn copy_out_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<CopyOutStream, crate::Error>> + Send;
+
+    /// Exports the rows of the query like [ClientExt::copy_out_named], decoding the rows
+    /// with the given types of the columns.
+    ///
+    /// ```no_run
+    /// # use futures_util::TryStreamExt;
+    /// # use pg_named_args::{query, ClientExt};
+    /// # use tokio_postgres::types::Type;
+    /// # async fn example(client: &tokio_postgres::Client) -> Result<(), pg_named_args::Error> {
+    /// let location = "sweden";
+    /// let (query, params) = query!(
+    ///     "SELECT id, report FROM weather_reports WHERE location = $location",
+    ///     Args { location }
+    /// );
+    /// let rows = client
+    ///     .copy_out_rows_named(&query, params, &[Type::INT8, Type::TEXT])
+    ///     .await?;
+    /// let mut rows = std::pin::pin!(rows);
+    /// while let Some(row) = rows.try_next().await? {
+    ///     let report: &str = row.get(1);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn copy_out_rows_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+        types: &[Type],
+    ) -> impl Future<Output = Result<BinaryCopyOutStream, crate::Error>> + Send;
+
+    /// Loads the rows with the `COPY ... FROM STDIN (FORMAT CSV)` statement, returning the
+    /// number of rows loaded.
+    ///
+    /// The rows are serialized as CSV and sent in chunks, see [CsvCopy].
+    ///
+    /// ```no_run
+    /// # use pg_named_args::{ClientExt, CsvCopy, CsvRecord, CsvRow, Identifier};
+    /// struct Report {
+    ///     location: String,
+    ///     temperature: f64,
+    /// }
+    ///
+    /// impl CsvRow for Report {
+    ///     fn write_csv(&self, record: &mut CsvRecord<'_>) {
+    ///         record.field(&self.location).field(self.temperature);
+    ///     }
+    /// }
+    ///
+    /// # async fn example(client: &tokio_postgres::Client, reports: Vec<Report>) -> Result<(), tokio_postgres::Error> {
+    /// let copy = CsvCopy::new(
+    ///     Identifier::new("weather_reports").unwrap(),
+    ///     vec![Identifier::new("location").unwrap(), Identifier::new("temperature").unwrap()],
+    /// );
+    /// let count = client.copy_in_csv(&copy, &reports).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn copy_in_csv<I>(
+        &self,
+        copy: &CsvCopy,
+        rows: I,
+    ) -> impl Future<Output = Result<u64, Error>> + Send
+    where
+        I: IntoIterator + Send,
+        I::IntoIter: Send,
+        I::Item: CsvRow + Send;
+}
+
+/// Execution helpers for every [Executor] of `tokio-postgres` rows, in addition to its methods.
+///
+/// The helpers only need [Executor::query_named] and [Executor::execute_named], so they work
+/// with the clients and transactions of `tokio-postgres`, the `Blocking` executor and the
+/// `Router`, `Timed` and `Hooked` executors wrapping them, and with code that is generic over
+/// `E: ExecutorExt`.
+pub trait ExecutorExt: Executor<Row = Row, Error = Error> {
+    /// Streams the rows of the query using a server-side cursor, which fetches the rows in
+    /// batches of `batch_size` rows.
+    ///
+    /// The client needs to be in a transaction, the cursor is closed after the last batch.
+    /// A [Router](crate::Router) sends the statements of the cursor to the primary.
+    ///
+    /// ```no_run
+    /// # use futures_util::TryStreamExt;
+    /// # use pg_named_args::{query, ExecutorExt};
+    /// # async fn example(client: &mut tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+    /// let txn = client.transaction().await?;
+    /// let (query, params) = query!("SELECT * FROM weather_reports", Args {});
+    /// let mut rows = std::pin::pin!(txn.query_cursor_named(&query, params, 1000));
+    /// while let Some(row) = rows.try_next().await? {
+    ///     // export the row
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn query_cursor_named<'a>(
+        &'a self,
+        query: &'a Query,
+        params: &'a [&'a (dyn ToSql + Sync)],
+        batch_size: u32,
+    ) -> impl Stream<Item = Result<Row, Error>> + Send + 'a;
+
+    /// Returns whether the query returns any rows, using `SELECT EXISTS(...)`.
+    fn exists_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Executes the query, returning the single row, or [Error::NotFound](crate::Error::NotFound)
+    /// and [Error::TooManyRows](crate::Error::TooManyRows) when it returns no or more rows.
+    ///
+    /// Like `query_one` of `tokio-postgres`, every row of the query is received before the
+    /// number of rows is checked, so the query shouldn't return many rows.
+    ///
+    /// ```no_run
+    /// # use pg_named_args::{query, Error, ExecutorExt};
+    /// # async fn example(client: &tokio_postgres::Client) -> Result<(), Error> {
+    /// let id = 42;
+    /// let (query, params) = query!("SELECT report FROM weather_reports WHERE id = $id", Args { id });
+    /// match client.query_one_named(&query, params).await {
+    ///     Ok(row) => println!("{}", row.get::<_, &str>(0)),
+    ///     Err(Error::NotFound) => println!("no report {id}"),
+    ///     Err(err) => return Err(err),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn query_one_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Row, crate::Error>> + Send;
+
+    /// Executes the query, returning the row if there is one, or
+    /// [Error::TooManyRows](crate::Error::TooManyRows) when it returns more rows.
+    fn query_opt_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Option<Row>, crate::Error>> + Send;
+
+    /// Waits for the session level advisory lock, see [AdvisoryLock].
+    ///
+    /// ```no_run
+    /// # use pg_named_args::{AdvisoryLock, ExecutorExt};
+    /// # async fn example(client: &tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+    /// const IMPORT: AdvisoryLock = AdvisoryLock::new("weather_import");
+    ///
+    /// if client.try_advisory_lock(&IMPORT).await? {
+    ///     // import the reports
+    ///     client.advisory_unlock(&IMPORT).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn advisory_lock(&self, lock: &AdvisoryLock) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Takes the session level advisory lock if it is available, returning whether it is taken.
+    fn try_advisory_lock(
+        &self,
+        lock: &AdvisoryLock,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Releases the session level advisory lock, returning whether it was held.
+    fn advisory_unlock(
+        &self,
+        lock: &AdvisoryLock,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Applies the settings to the current transaction, see [Settings].
+    ///
+    /// Outside of a transaction the settings have no effect.
+    fn set_local(&self, settings: &Settings) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Starts listening for notifications on the channel.
+    ///
+    /// The notifications are delivered through the `Connection` of the client.
+    fn listen(&self, channel: &Identifier) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Stops listening for notifications on the channel.
+    fn unlisten(&self, channel: &Identifier) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Sends a notification with the payload on the channel using `pg_notify`.
+    fn notify(
+        &self,
+        channel: &Identifier,
+        payload: &str,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Sends a notification with the payload serialized as JSON on the channel.
+    #[cfg(feature = "serde")]
+    fn notify_json<T: serde::Serialize + std::fmt::Debug + Sync>(
+        &self,
+        channel: &Identifier,
+        payload: &T,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Returns the plan of the query, executing the query as well when `analyze` is set.
+    #[cfg(feature = "serde")]
+    fn explain_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+        analyze: bool,
+    ) -> impl Future<Output = Result<Explain, Error>> + Send;
+}
+
+/// Execution helpers for the transactions of `tokio-postgres`, for the features that need
+/// a transaction.
+pub trait TransactionExt {
+    /// Binds the query to an unnamed portal, from which the rows can be fetched gradually
+    /// with [NamedPortal::fetch], without a server-side cursor.
+    ///
+    /// ```no_run
+    /// # use pg_named_args::{query, TransactionExt};
+    /// # async fn example(client: &mut tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+    /// let txn = client.transaction().await?;
+    /// let (query, params) = query!("SELECT * FROM weather_reports", Args {});
+    /// let portal = txn.query_portal_named(&query, params).await?;
+    /// loop {
+    ///     let rows = portal.fetch(100).await?;
+    ///     if rows.is_empty() {
+    ///         break;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn query_portal_named<'t>(
+        &'t self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<NamedPortal<'t>, Error>> + Send;
+
+    /// Upserts the rows through a temporary table, returning the number of rows affected by
+    /// the merge statement.
+    ///
+    /// The staging table is created, loaded with the rows using binary `COPY`, merged with the
+    /// query and dropped again, see [StagingTable]. The values of a row are in the order of the
+    /// columns of the table.
+    ///
+    /// ```no_run
+    /// # use pg_named_args::{query, Identifier, StagingTable, TransactionExt};
+    /// # use tokio_postgres::types::{ToSql, Type};
+    /// # struct Report { location: String, temperature: f64 }
+    /// # async fn example(client: &mut tokio_postgres::Client, reports: Vec<Report>) -> Result<(), tokio_postgres::Error> {
+    /// let staging = StagingTable::new(Identifier::new("staged_reports").unwrap())
+    ///     .column(Identifier::new("location").unwrap(), Type::TEXT)
+    ///     .column(Identifier::new("temperature").unwrap(), Type::FLOAT8);
+    /// let rows = reports
+    ///     .iter()
+    ///     .map(|r| [&r.location as &(dyn ToSql + Sync), &r.temperature]);
+    /// let source = "station";
+    /// let (merge, params) = query!(
+    ///     r"
+    ///     INSERT INTO weather_reports(location, temperature, source)
+    ///     SELECT location, temperature, $source FROM staged_reports
+    ///     ON CONFLICT (location) DO UPDATE SET temperature = excluded.temperature
+    ///     ",
+    ///     Args { source }
+    /// );
+    ///
+    /// let txn = client.transaction().await?;
+    /// let count = txn.copy_merge_named(&staging, rows, &merge, params).await?;
+    /// txn.commit().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn copy_merge_named<'a, I>(
+        &self,
+        staging: &StagingTable,
+        rows: I,
+        merge: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<u64, Error>> + Send
+    where
+        I: IntoIterator + Send,
+        I::IntoIter: Send,
+        I::Item: AsRef<[&'a (dyn ToSql + Sync)]> + Send;
+
+    /// Executes the query with a `statement_timeout`, so a slow query is canceled by the server
+    /// instead of holding on to the connection.
+    ///
+    /// The timeout is set with `set_config` for the transaction and restored to the previous
+    /// value after the query, so it doesn't apply to the other statements of the transaction.
+    /// A query that takes longer fails with the `query_canceled` (`57014`) error, which aborts
+    /// the transaction.
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use pg_named_args::{query, transaction, TransactionExt};
+    /// # async fn example(client: &mut tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+    /// let location = "sweden";
+    /// let (query, params) = query!(
+    ///     "SELECT avg(temperature) FROM weather_reports WHERE location = $location",
+    ///     Args { location }
+    /// );
+    /// let rows = transaction!(client, |txn| {
+    ///     txn.query_timeout_named(&query, params, Duration::from_secs(5)).await
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn query_timeout_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Vec<Row>, Error>> + Send;
+}
+
+impl TransactionExt for Transaction<'_> {
+    async fn query_portal_named<'t>(
+        &'t self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<NamedPortal<'t>, Error> {
+        let portal = instrument(query, self.bind(query.sql(), params), |_| 0).await?;
+        Ok(NamedPortal {
+            txn: self,
+            portal,
+            query: query.clone(),
+        })
+    }
+
+    async fn copy_merge_named<'a, I>(
+        &self,
+        staging: &StagingTable,
+        rows: I,
+        merge: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, Error>
+    where
+        I: IntoIterator + Send,
+        I::IntoIter: Send,
+        I::Item: AsRef<[&'a (dyn ToSql + Sync)]> + Send,
+    {
+        self.batch_execute(&staging.create_sql()).await?;
+        let sink = self.copy_in(&staging.copy_sql()).await?;
+        let types = staging.types();
+        let mut writer = pin!(BinaryCopyInWriter::new(sink, &types));
+        for row in rows {
+            writer.as_mut().write(row.as_ref()).await?;
+        }
+        writer.finish().await?;
+        let count = self.execute_named(merge, params).await?;
+        self.batch_execute(&staging.drop_sql()).await?;
+        Ok(count)
+    }
+
+    async fn query_timeout_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+        timeout: Duration,
+    ) -> Result<Vec<Row>, Error> {
+        // a timeout of zero disables the timeout
+        let timeout = format!("{}ms", timeout.as_millis().max(1));
+        let previous: String = self
+            .query_one(
+                "SELECT current_setting('statement_timeout'), set_config('statement_timeout', $1, true)",
+                &[&timeout],
+            )
+            .await?
+            .try_get(0)?;
+        let rows = self.query_named(query, params).await?;
+        self.execute(
+            "SELECT set_config('statement_timeout', $1, true)",
+            &[&previous],
+        )
+        .await?;
+        Ok(rows)
+    }
+}
+
+/// A query bound to a portal, see [TransactionExt::query_portal_named].
+pub struct NamedPortal<'t> {
+    txn: &'t Transaction<'t>,
+    portal: Portal,
+    query: Query,
+}
+
+impl NamedPortal<'_> {
+    /// Fetches at most `max_rows` rows, an empty result means that all rows are fetched.
+    pub async fn fetch(&self, max_rows: i32) -> Result<Vec<Row>, Error> {
+        let execution = self.txn.query_portal(&self.portal, max_rows);
+        instrument(&self.query, execution, |rows| rows.len() as u64).await
+    }
+
+    /// The query that is bound to the portal.
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+}
+
+/// Executes the queries concurrently, returning the rows of every query as a tuple.
+///
+/// The queries are pipelined by `tokio-postgres` over the single connection of the client,
+/// so the round trips are not waited for one by one.
+/// The first error is returned, which does not cancel the queries that are already sent.
+///
+/// ```no_run
+/// # use pg_named_args::{pipeline, query};
+/// # async fn example(client: &tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+/// let location = "sweden";
+/// let (reports, stations) = pipeline!(
+///     client,
+///     query!("SELECT report FROM weather_reports WHERE location = $location", Args { location }),
+///     query!("SELECT name FROM weather_stations WHERE location = $location", Args { location }),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! pipeline {
+    ($client:expr, $($query:expr),+ $(,)?) => {{
+        use $crate::Executor as _;
+        let client = &$client;
+        $crate::__private::try_join!($(async {
+            let (query, params) = $query;
+            client.query_named(&query, params).await
+        }),+)
+    }};
+}
+
+/// Runs the body in a transaction, which is committed when the body returns `Ok` and
+/// rolled back when it returns `Err`.
+///
+/// The macro returns a future, the transaction is started on the client when it is awaited.
+/// A client that is already in a transaction starts a savepoint instead.
+/// Without a return type, the error type of the body is `tokio_postgres::Error`.
+/// With a return type, the error type needs to implement `From<tokio_postgres::Error>`.
+///
+/// ```no_run
+/// # use pg_named_args::{query, transaction, Executor};
+/// # async fn example(client: &mut tokio_postgres::Client) -> Result<(), Box<dyn std::error::Error>> {
+/// let name = "Fred";
+/// let town = "Bedrock";
+/// transaction!(client, |txn| {
+///     let (query, params) = query!("INSERT INTO flintstone(name) VALUES($name)", Args { name });
+///     txn.execute_named(&query, params).await?;
+///     let (query, params) = query!(
+///         "UPDATE town SET residents = residents + 1 WHERE name = $town",
+///         Args { town }
+///     );
+///     txn.execute_named(&query, params).await?;
+///     Ok(())
+/// })
+/// .await?;
+///
+/// let count = transaction!(client, |txn| -> Result<i64, Box<dyn std::error::Error>> {
+///     let (query, params) = query!("SELECT count(*) FROM flintstone", Args {});
+///     Ok(txn.query_named(&query, params).await?[0].try_get(0)?)
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! transaction {
+    ($client:expr, |$txn:ident| -> $ret:ty $body:block) => {
+        async {
+            let $txn = $client.transaction().await?;
+            let res: $ret = async { $body }.await;
+            match res {
+                Ok(value) => {
+                    $txn.commit().await?;
+                    Ok(value)
+                }
+                Err(err) => {
+                    // the error of the body is more relevant than the error of the rollback
+                    let _ = $txn.rollback().await;
+                    Err(err)
+                }
+            }
+        }
+    };
+    ($client:expr, |$txn:ident| $body:block) => {
+        $crate::transaction!(
+            $client,
+            |$txn| -> ::std::result::Result<_, $crate::__private::tokio_postgres::Error> $body
+        )
+    };
+}
+
+/// Implements [Executor] for the clients and transactions that are, or dereference to, a
+/// `GenericClient` of `tokio-postgres`.
+macro_rules! impl_executor {
+    ($($ty:ty => $client:ty),* $(,)?) => {$(
+        impl Executor for $ty {
+            type Row = Row;
+            type Error = Error;
+
+            async fn query_named(
+                &self,
+                query: &Query,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<Vec<Row>, Error> {
+                let client: &$client = self;
+                instrument(query, client.query(query.sql(), params), |rows| {
+                    rows.len() as u64
+                })
+                .await
+            }
+
+            async fn execute_named(
+                &self,
+                query: &Query,
+                params: &[&(dyn ToSql + Sync)],
+            ) -> Result<u64, Error> {
+                let client: &$client = self;
+                instrument(query, client.execute(query.sql(), params), |rows| *rows).await
+            }
+        }
+    )*};
+}
+
+impl_executor!(Client => Client, Transaction<'_> => Transaction<'_>);
+#[cfg(feature = "deadpool")]
+impl_executor!(
+    deadpool_postgres::Object => Client,
+    deadpool_postgres::Transaction<'_> => Transaction<'_>,
+);
+
+impl<C> ClientExt for C
+where
+    C: GenericClient + Executor<Row = Row, Error = Error> + Sync,
+{
+    async fn simple_query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<SimpleQueryMessage>, crate::Error> {
+        let sql = query.inline(params)?;
+        let rows = |messages: &Vec<SimpleQueryMessage>| {
+            let mut rows = messages.iter().rev().filter_map(|message| match message {
+                SimpleQueryMessage::CommandComplete(rows) => Some(*rows),
+                _ => None,
+            });
+            rows.next().unwrap_or(0)
+        };
+        Ok(instrument(query, self.simple_query(&sql), rows).await?)
+    }
+
+    async fn query_stream_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<RowStream, Error> {
+        let execution = self.query_raw(query.sql(), params.iter().copied());
+        instrument(query, execution, |_| 0).await
+    }
+
+    async fn copy_out_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<CopyOutStream, crate::Error> {
+        let query = query.copy_out();
+        let sql = query.inline(params)?;
+        Ok(instrument(&query, self.client().copy_out(&sql), |_| 0).await?)
+    }
+
+    async fn copy_out_rows_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+        types: &[Type],
+    ) -> Result<BinaryCopyOutStream, crate::Error> {
+        let stream = self.copy_out_named(query, params).await?;
+        Ok(BinaryCopyOutStream::new(stream, types))
+    }
+
+    async fn copy_in_csv<I>(&self, copy: &CsvCopy, rows: I) -> Result<u64, Error>
+    where
+        I: IntoIterator + Send,
+        I::IntoIter: Send,
+        I::Item: CsvRow + Send,
+    {
+        const CHUNK_SIZE: usize = 64 * 1024;
+
+        let sink = self.client().copy_in::<_, Bytes>(&copy.sql()).await?;
+        let mut sink = pin!(sink);
+        let mut chunk = String::new();
+        copy.write_header(&mut chunk);
+        for row in rows {
+            copy.write_row(&mut chunk, &row);
+            if chunk.len() >= CHUNK_SIZE {
+                sink.send(Bytes::from(std::mem::take(&mut chunk))).await?;
+            }
+        }
+        if !chunk.is_empty() {
+            sink.send(Bytes::from(chunk)).await?;
+        }
+        sink.as_mut().finish().await
+    }
+}
+
+impl<E> ExecutorExt for E
+where
+    E: Executor<Row = Row, Error = Error> + Sync,
+{
+    fn query_cursor_named<'a>(
+        &'a self,
+        query: &'a Query,
+        params: &'a [&'a (dyn ToSql + Sync)],
+        batch_size: u32,
+    ) -> impl Stream<Item = Result<Row, Error>> + Send + 'a {
+        static CURSORS: AtomicU64 = AtomicU64::new(0);
+        // `FETCH 0` returns the current row instead of an empty batch
+        let batch_size = batch_size.max(1);
+        let cursor = format!("pg_named_args_{}", CURSORS.fetch_add(1, Ordering::Relaxed));
+
+        // `None` is the state before the cursor is declared.
+        let batches = stream::try_unfold(None, move |declared: Option<bool>| {
+            let cursor = cursor.clone();
+            async move {
+                match declared {
+                    None => {
+                        let prefix = format!("DECLARE {cursor} NO SCROLL CURSOR FOR ");
+                        let mut declare = query.wrapped(&prefix, "");
+                        // the `FETCH` and `CLOSE` are `Other` as well, so a `Router` sends all
+                        // statements of the cursor to the primary
+                        declare.kind = StatementKind::Other;
+                        self.execute_named(&declare, params).await?;
+                    }
+                    Some(true) => return Ok(None),
+                    Some(false) => {}
+                }
+
+                let fetch = Query::from_sql(format!("FETCH {batch_size} FROM {cursor}"));
+                let rows = self.query_named(&fetch, &[]).await?;
+                let done = rows.len() < batch_size as usize;
+                if done {
+                    let close = Query::from_sql(format!("CLOSE {cursor}"));
+                    self.execute_named(&close, &[]).await?;
+                }
+                Ok(Some((rows, Some(done))))
+            }
+        });
+        batches
+            .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    async fn exists_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<bool, Error> {
+        let query = query.exists();
+        self.query_named(&query, params).await?[0].try_get(0)
+    }
+
+    async fn query_one_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Row, crate::Error> {
+        self.query_opt_named(query, params)
+            .await?
+            .ok_or(crate::Error::NotFound)
+    }
+
+    async fn query_opt_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Option<Row>, crate::Error> {
+        let mut rows = self.query_named(query, params).await?.into_iter();
+        let row = rows.next();
+        if rows.next().is_some() {
+            return Err(crate::Error::TooManyRows);
+        }
+        Ok(row)
+    }
+
+    async fn advisory_lock(&self, lock: &AdvisoryLock) -> Result<(), Error> {
+        let query = lock.call("pg_advisory_lock");
+        self.query_named(&query, &lock.params()).await?;
+        Ok(())
+    }
+
+    async fn try_advisory_lock(&self, lock: &AdvisoryLock) -> Result<bool, Error> {
+        let query = lock.call("pg_try_advisory_lock");
+        self.query_named(&query, &lock.params()).await?[0].try_get(0)
+    }
+
+    async fn advisory_unlock(&self, lock: &AdvisoryLock) -> Result<bool, Error> {
+        let query = lock.call("pg_advisory_unlock");
+        self.query_named(&query, &lock.params()).await?[0].try_get(0)
+    }
+
+    async fn set_local(&self, settings: &Settings) -> Result<(), Error> {
+        if settings.is_empty() {
+            return Ok(());
+        }
+
+        let mut calls = vec![];
+        let mut params: Vec<&(dyn ToSql + Sync)> = vec![];
+        for (name, value) in &settings.settings {
+            calls.push(format!(
+                "set_config(${}, ${}, true)",
+                params.len() + 1,
+                params.len() + 2
+            ));
+            params.extend([name as &(dyn ToSql + Sync), value as _]);
+        }
+        let sql = format!("SELECT {}", calls.join(", "));
+        let query = Query::from_sql(sql);
+
+        self.query_named(&query, &params).await?;
+        Ok(())
+    }
+
+    async fn listen(&self, channel: &Identifier) -> Result<(), Error> {
+        let query = Query::from_sql(format!("LISTEN {channel}"));
+        self.execute_named(&query, &[]).await?;
+        Ok(())
+    }
+
+    async fn unlisten(&self, channel: &Identifier) -> Result<(), Error> {
+        let query = Query::from_sql(format!("UNLISTEN {channel}"));
+        self.execute_named(&query, &[]).await?;
+        Ok(())
+    }
+
+    async fn notify(&self, channel: &Identifier, payload: &str) -> Result<(), Error> {
+        let query = Query::from_sql("SELECT pg_notify($1, $2)");
+        self.query_named(&query, &[&channel.as_str(), &payload])
+            .await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    async fn notify_json<T: serde::Serialize + std::fmt::Debug + Sync>(
+        &self,
+        channel: &Identifier,
+        payload: &T,
+    ) -> Result<(), Error> {
+        let query = Query::from_sql("SELECT pg_notify($1, $2::json::text)");
+        self.query_named(&query, &[&channel.as_str(), &Json(payload)])
+            .await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    async fn explain_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+        analyze: bool,
+    ) -> Result<Explain, Error> {
+        let query = match analyze {
+            true => query.explain_analyze(),
+            false => query.explain(),
+        };
+        let Json([explain]) = self.query_named(&query, params).await?[0].try_get(0)?;
+        Ok(explain)
+    }
+}
+
+/// Runs the execution of a query, adding the instrumentation of the enabled features.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) async fn instrument<T>(
+    query: &Query,
+    execution: impl Future<Output = Result<T, Error>>,
+    rows: impl FnOnce(&T) -> u64,
+) -> Result<T, Error> {
+    // The field names follow the OpenTelemetry semantic conventions.
+    #[cfg(all(feature = "tracing", not(feature = "otel")))]
+    let span = tracing::info_span!(
+        "query",
+        otel.kind = "client",
+        otel.status_code = tracing::field::Empty,
+        db.system = "postgresql",
+        db.statement = query.sql(),
+        db.parameters = ?query.names(),
+        db.response.returned_rows = tracing::field::Empty,
+        duration_ms = tracing::field::Empty,
+        error.message = tracing::field::Empty,
+        pg_named_args.fragments = tracing::field::Empty,
+    );
+    #[cfg(feature = "otel")]
+    let span = {
+        let attributes = crate::otel::attributes();
+        let span = tracing::info_span!(
+            "query",
+            otel.kind = "client",
+            otel.status_code = tracing::field::Empty,
+            db.system = "postgresql",
+            db.operation = &*crate::otel::operation(query),
+            db.statement = &*attributes.statement(query.sql()),
+            db.parameters = ?query.names(),
+            db.name = attributes.database_name(),
+            server.address = attributes.server_address(),
+            server.port = attributes.server_port(),
+            db.response.returned_rows = tracing::field::Empty,
+            duration_ms = tracing::field::Empty,
+            error.message = tracing::field::Empty,
+            pg_named_args.fragments = tracing::field::Empty,
+        );
+        span
+    };
+    // the call sites of the fragments, with the `provenance` feature
+    #[cfg(feature = "tracing")]
+    if !query.fragment_origins().is_empty() {
+        span.record(
+            "pg_named_args.fragments",
+            tracing::field::debug(query.fragment_origins()),
+        );
+    }
+    #[cfg(feature = "tracing")]
+    let execution = tracing::Instrument::instrument(execution, span.clone());
+
+    let start = Instant::now();
+    let res = execution.await;
+    let elapsed = start.elapsed();
+
+    #[cfg(feature = "tracing")]
+    {
+        span.record("duration_ms", elapsed.as_secs_f64() * 1000.0);
+        match &res {
+            Ok(res) => span.record("db.response.returned_rows", rows(res)),
+            Err(err) => span
+                .record("otel.status_code", "ERROR")
+                .record("error.message", tracing::field::display(err)),
+        };
+    }
+
+    #[cfg(feature = "metrics")]
+    {
+        let fingerprint = format!("{:016x}", query.fingerprint());
+        metrics::counter!("pg_named_args_queries_total", "fingerprint" => fingerprint.clone())
+            .increment(1);
+        metrics::histogram!("pg_named_args_query_duration_seconds", "fingerprint" => fingerprint.clone())
+            .record(elapsed);
+        if res.is_err() {
+            metrics::counter!("pg_named_args_query_errors_total", "fingerprint" => fingerprint)
+                .increment(1);
+        }
+    }
+
+    res
+}
@@ -0,0 +1,29 @@
+/// The columns of a group with optional columns, like `$[id, name?]`, that are written.
+#[doc(hidden)]
+pub fn columns(columns: &[(&str, bool)]) -> String {
+    let written: Vec<_> = columns
+        .iter()
+        .filter(|(_, written)| *written)
+        .map(|(column, _)| *column)
+        .collect();
+    written.join(", ")
+}
+
+/// The placeholders of the written columns of [columns]. The optional columns don't have a
+/// parameter yet and get the parameters after the `params` other parameters.
+#[doc(hidden)]
+pub fn values(params: usize, values: &[(Option<usize>, bool)]) -> String {
+    let mut next = params;
+    let written: Vec<_> = values
+        .iter()
+        .filter(|(_, written)| *written)
+        .map(|(param, _)| {
+            let param = param.unwrap_or_else(|| {
+                next += 1;
+                next
+            });
+            format!("${param}")
+        })
+        .collect();
+    written.join(", ")
+}
@@ -0,0 +1,235 @@
+use std::fmt::{self, Write};
+
+use crate::{Identifier, SchemaName};
+
+/// A `COPY table (columns) FROM STDIN (FORMAT CSV)` statement, for bulk loading rows.
+///
+/// The names are validated identifiers and the options are quoted as literals, so the
+/// statement can't be used for injection. The rows are serialized by [CsvCopy::write_rows],
+/// or loaded with `ClientExt::copy_in_csv` of the `tokio-postgres` feature.
+///
+/// ```
+/// # use pg_named_args::{CsvCopy, CsvRecord, CsvRow, Identifier};
+/// struct Report {
+///     location: &'static str,
+///     temperature: Option<f64>,
+/// }
+///
+/// impl CsvRow for Report {
+///     fn write_csv(&self, record: &mut CsvRecord<'_>) {
+///         record.field(self.location).option(self.temperature);
+///     }
+/// }
+///
+/// let copy = CsvCopy::new(
+///     Identifier::new("weather_reports").unwrap(),
+///     vec![Identifier::new("location").unwrap(), Identifier::new("temperature").unwrap()],
+/// );
+/// assert_eq!(
+///     copy.sql(),
+///     r#"COPY "weather_reports" ("location", "temperature") FROM STDIN (FORMAT CSV, DELIMITER ',', NULL '')"#
+/// );
+///
+/// let mut csv = String::new();
+/// copy.write_rows(&mut csv, [
+///     Report { location: "sweden, north", temperature: Some(-3.5) },
+///     Report { location: "", temperature: None },
+/// ]);
+/// assert_eq!(csv, "\"sweden, north\",-3.5\n\"\",\n");
+/// ```
+#[derive(Clone, Debug)]
+pub struct CsvCopy {
+    schema: Option<SchemaName>,
+    table: Identifier,
+    columns: Vec<Identifier>,
+    delimiter: char,
+    null: String,
+    header: bool,
+}
+
+impl CsvCopy {
+    /// Creates the statement for the columns of the table, separated by commas and with an
+    /// empty unquoted field as `NULL`.
+    pub fn new(table: Identifier, columns: Vec<Identifier>) -> Self {
+        Self {
+            schema: None,
+            table,
+            columns,
+            delimiter: ',',
+            null: String::new(),
+            header: false,
+        }
+    }
+
+    /// Qualifies the table with the schema.
+    pub fn schema(mut self, schema: SchemaName) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Sets the character separating the fields.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the delimiter is not ASCII, a quote, a line break or a character of the
+    /// null string.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        assert!(
+            delimiter.is_ascii()
+                && !matches!(delimiter, '"' | '\n' | '\r')
+                && !self.null.contains(delimiter),
+            "the delimiter {delimiter:?} can't be used in CSV"
+        );
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Sets the string that represents `NULL`, an empty string by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the string contains a line break or the delimiter.
+    pub fn null(mut self, null: impl Into<String>) -> Self {
+        let null = null.into();
+        assert!(
+            !null.contains(['\n', '\r', self.delimiter]),
+            "the null string {null:?} can't be used in CSV"
+        );
+        self.null = null;
+        self
+    }
+
+    /// Writes a line with the names of the columns before the rows, which is skipped by
+    /// the server.
+    pub fn header(mut self, header: bool) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// The `COPY` statement.
+    pub fn sql(&self) -> String {
+        let mut sql = String::from("COPY ");
+        if let Some(schema) = &self.schema {
+            write!(sql, "{schema}.").unwrap();
+        }
+        let columns = self.columns.iter().map(Identifier::to_string);
+        write!(
+            sql,
+            "{} ({}) FROM STDIN (FORMAT CSV, DELIMITER {}, NULL {}",
+            self.table,
+            columns.collect::<Vec<_>>().join(", "),
+            literal(&self.delimiter.to_string()),
+            literal(&self.null),
+        )
+        .unwrap();
+        if self.header {
+            sql.push_str(", HEADER true");
+        }
+        sql.push(')');
+        sql
+    }
+
+    /// Serializes the rows as CSV, preceded by the header if it is enabled.
+    pub fn write_rows<R: CsvRow>(&self, out: &mut String, rows: impl IntoIterator<Item = R>) {
+        self.write_header(out);
+        for row in rows {
+            self.write_row(out, &row);
+        }
+    }
+
+    pub(crate) fn write_header(&self, out: &mut String) {
+        if self.header {
+            let mut record = self.record(out);
+            for column in &self.columns {
+                record.field(column.as_str());
+            }
+            out.push('\n');
+        }
+    }
+
+    pub(crate) fn write_row(&self, out: &mut String, row: &impl CsvRow) {
+        row.write_csv(&mut self.record(out));
+        out.push('\n');
+    }
+
+    fn record<'a>(&'a self, out: &'a mut String) -> CsvRecord<'a> {
+        CsvRecord {
+            out,
+            delimiter: self.delimiter,
+            null: &self.null,
+            first: true,
+        }
+    }
+}
+
+/// Quotes the value as a string literal.
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// A row that can be loaded with a [CsvCopy].
+///
+/// The fields are written in the order of the columns of the statement.
+pub trait CsvRow {
+    /// Writes the fields of the row.
+    fn write_csv(&self, record: &mut CsvRecord<'_>);
+}
+
+impl<R: CsvRow + ?Sized> CsvRow for &R {
+    fn write_csv(&self, record: &mut CsvRecord<'_>) {
+        (**self).write_csv(record);
+    }
+}
+
+/// The fields of a single row of a [CsvCopy], see [CsvRow].
+pub struct CsvRecord<'a> {
+    out: &'a mut String,
+    delimiter: char,
+    null: &'a str,
+    first: bool,
+}
+
+impl CsvRecord<'_> {
+    /// Writes the value using its `Display` implementation, quoted when needed.
+    pub fn field(&mut self, value: impl fmt::Display) -> &mut Self {
+        self.separate();
+        let value = value.to_string();
+        // a quoted value is never the null string, and `\.` alone marks the end of the data
+        let quote = value == self.null
+            || value == "\\."
+            || value.contains(['"', '\n', '\r', self.delimiter]);
+        match quote {
+            true => write!(self.out, "\"{}\"", value.replace('"', "\"\"")).unwrap(),
+            false => self.out.push_str(&value),
+        }
+        self
+    }
+
+    /// Writes `NULL`.
+    pub fn null(&mut self) -> &mut Self {
+        self.separate();
+        self.out.push_str(self.null);
+        self
+    }
+
+    /// Writes the value, or `NULL` for `None`.
+    pub fn option(&mut self, value: Option<impl fmt::Display>) -> &mut Self {
+        match value {
+            Some(value) => self.field(value),
+            None => self.null(),
+        }
+    }
+
+    fn separate(&mut self) {
+        if !self.first {
+            self.out.push(self.delimiter);
+        }
+        self.first = false;
+    }
+}
+
+impl fmt::Debug for CsvRecord<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CsvRecord").finish_non_exhaustive()
+    }
+}
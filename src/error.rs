@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::InlineError;
+
+/// The error returned by the execution helpers that can fail before reaching the database.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An error returned by `tokio-postgres`.
+    Db(tokio_postgres::Error),
+    /// An argument can't be inlined as a literal.
+    Inline(InlineError),
+    /// The statements of the migration with this version changed after it was applied,
+    /// see [Migrations](crate::Migrations).
+    MigrationChanged(i64),
+    /// The query returned no rows, while it should return one.
+    NotFound,
+    /// The query returned more than one row, while it should return at most one.
+    TooManyRows,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Db(err) => err.fmt(f),
+            Error::Inline(err) => err.fmt(f),
+            Error::MigrationChanged(version) => {
+                write!(f, "the migration {version} changed after it was applied")
+            }
+            Error::NotFound => f.write_str("the query returned no rows"),
+            Error::TooManyRows => f.write_str("the query returned more than one row"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Db(err) => Some(err),
+            Error::Inline(err) => Some(err),
+            Error::MigrationChanged(_) | Error::NotFound | Error::TooManyRows => None,
+        }
+    }
+}
+
+impl From<tokio_postgres::Error> for Error {
+    fn from(err: tokio_postgres::Error) -> Self {
+        Error::Db(err)
+    }
+}
+
+impl From<InlineError> for Error {
+    fn from(err: InlineError) -> Self {
+        Error::Inline(err)
+    }
+}
@@ -0,0 +1,43 @@
+use std::future::Future;
+
+use postgres_types::ToSql;
+
+use crate::Query;
+
+/// Executes a [Query] created by the [query!](crate::query!) macro.
+///
+/// With the `tokio-postgres` feature, this is implemented for the clients and transactions of
+/// `tokio-postgres`. Code that is generic over the executor can be tested without a database
+/// using the `MockClient` of the `test-support` feature.
+///
+/// ```no_run
+/// # use pg_named_args::{query, Executor};
+/// async fn count<E: Executor>(client: &E) -> Result<u64, E::Error> {
+///     let location = "sweden";
+///     let (query, params) = query!(
+///         "DELETE FROM weather_reports WHERE location = $location",
+///         Args { location }
+///     );
+///     client.execute_named(&query, params).await
+/// }
+/// ```
+pub trait Executor {
+    /// The type of the rows returned by the queries.
+    type Row;
+    /// The error returned when an execution fails.
+    type Error;
+
+    /// Executes the query, returning the resulting rows.
+    fn query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<Vec<Self::Row>, Self::Error>> + Send;
+
+    /// Executes the statement, returning the number of rows modified.
+    fn execute_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> impl Future<Output = Result<u64, Self::Error>> + Send;
+}
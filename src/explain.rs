@@ -0,0 +1,157 @@
+//! Typed output of `EXPLAIN (FORMAT JSON)`, see [Query::explain](crate::Query::explain), and snapshot tests of the plans.
+
+use std::fmt::Write;
+#[cfg(all(feature = "test-support", feature = "tokio-postgres"))]
+use std::path::Path;
+
+use serde::Deserialize;
+#[cfg(all(feature = "test-support", feature = "tokio-postgres"))]
+use tokio_postgres::types::ToSql;
+
+#[cfg(all(feature = "test-support", feature = "tokio-postgres"))]
+use crate::{ExecutorExt, Query};
+
+/// The output of `EXPLAIN (FORMAT JSON)` for a single statement.
+///
+/// PostgreSQL returns a JSON array containing one of these, which can be parsed with
+/// [Explain::from_json] or read from the row as `postgres_types::Json<Vec<Explain>>`.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Explain {
+    #[serde(rename = "Plan")]
+    pub plan: Plan,
+    /// The planning time in milliseconds, only available with `ANALYZE`.
+    #[serde(rename = "Planning Time")]
+    pub planning_time: Option<f64>,
+    /// The execution time in milliseconds, only available with `ANALYZE`.
+    #[serde(rename = "Execution Time")]
+    pub execution_time: Option<f64>,
+}
+
+impl Explain {
+    /// Parses the JSON array returned by `EXPLAIN (FORMAT JSON)`.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let [explain]: [Explain; 1] = serde_json::from_str(json)?;
+        Ok(explain)
+    }
+
+    /// The shape of the plan, see [Plan::shape].
+    pub fn shape(&self) -> String {
+        self.plan.shape()
+    }
+}
+
+/// A node of the query plan.
+///
+/// The actual values are only available with `ANALYZE`.
+#[derive(Clone, Debug, Deserialize)]
+#[non_exhaustive]
+pub struct Plan {
+    #[serde(rename = "Node Type")]
+    pub node_type: String,
+    #[serde(rename = "Relation Name")]
+    pub relation_name: Option<String>,
+    #[serde(rename = "Alias")]
+    pub alias: Option<String>,
+    #[serde(rename = "Index Name")]
+    pub index_name: Option<String>,
+    #[serde(rename = "Startup Cost")]
+    pub startup_cost: f64,
+    #[serde(rename = "Total Cost")]
+    pub total_cost: f64,
+    #[serde(rename = "Plan Rows")]
+    pub plan_rows: f64,
+    #[serde(rename = "Plan Width")]
+    pub plan_width: u64,
+    #[serde(rename = "Actual Startup Time")]
+    pub actual_startup_time: Option<f64>,
+    #[serde(rename = "Actual Total Time")]
+    pub actual_total_time: Option<f64>,
+    #[serde(rename = "Actual Rows")]
+    pub actual_rows: Option<f64>,
+    #[serde(rename = "Actual Loops")]
+    pub actual_loops: Option<f64>,
+    #[serde(rename = "Plans", default)]
+    pub plans: Vec<Plan>,
+}
+
+impl Plan {
+    /// The shape of the plan, with a line for every node with its type, the relation and the
+    /// index, indented by the depth of the node.
+    ///
+    /// The costs, the row estimates and the timings are left out, because they change with the
+    /// statistics of the data, so the shape only changes when the plan does.
+    ///
+    /// ```
+    /// # use pg_named_args::explain::Explain;
+    /// let json = r#"[{"Plan": {
+    ///     "Node Type": "Index Scan", "Relation Name": "reports", "Index Name": "reports_pkey",
+    ///     "Startup Cost": 0.15, "Total Cost": 8.17, "Plan Rows": 1, "Plan Width": 36
+    /// }}]"#;
+    /// let explain = Explain::from_json(json).unwrap();
+    /// assert_eq!(explain.shape(), "Index Scan on reports using reports_pkey\n");
+    /// ```
+    pub fn shape(&self) -> String {
+        let mut shape = String::new();
+        self.write_shape(&mut shape, 0);
+        shape
+    }
+
+    fn write_shape(&self, shape: &mut String, depth: usize) {
+        write!(shape, "{:width$}{}", "", self.node_type, width = depth * 2).unwrap();
+        if let Some(relation) = &self.relation_name {
+            write!(shape, " on {relation}").unwrap();
+        }
+        if let Some(index) = &self.index_name {
+            write!(shape, " using {index}").unwrap();
+        }
+        shape.push('\n');
+        for plan in &self.plans {
+            plan.write_shape(shape, depth + 1);
+        }
+    }
+}
+
+/// Asserts that the shape of the plan of the query matches the snapshot in the file, so a
+/// change of the plan, like a query that no longer uses an index, fails the tests.
+///
+/// The plan comes from `EXPLAIN (FORMAT JSON)` without executing the query, and is compared
+/// with [Plan::shape]. The snapshot is written when the file doesn't exist yet, or when the
+/// `PG_NAMED_ARGS_UPDATE_PLANS` environment variable is set, to accept the changed plans.
+/// The plans depend on the schema, the indexes and the statistics of the database, so the
+/// tests should run against a database with representative data.
+///
+/// ```ignore
+/// let (query, params) = query!("SELECT * FROM reports WHERE id = $id", Args { id });
+/// assert_plan_snapshot(&client, &query, params, "tests/plans/report_by_id.txt").await;
+/// ```
+#[cfg(all(feature = "test-support", feature = "tokio-postgres"))]
+pub async fn assert_plan_snapshot(
+    client: &impl ExecutorExt,
+    query: &Query,
+    params: &[&(dyn ToSql + Sync)],
+    path: impl AsRef<Path>,
+) {
+    let path = path.as_ref();
+    let explain = client.explain_named(query, params, false).await;
+    let shape = explain.expect("failed to explain the query").shape();
+
+    let update = std::env::var_os("PG_NAMED_ARGS_UPDATE_PLANS").is_some();
+    if update || !path.exists() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).expect("failed to create the snapshot directory");
+        }
+        std::fs::write(path, shape).expect("failed to write the plan snapshot");
+        return;
+    }
+
+    let snapshot = std::fs::read_to_string(path).expect("failed to read the plan snapshot");
+    if snapshot != shape {
+        panic!(
+            "the plan of the query at {} doesn't match {}\nquery: {}\n\nexpected:\n{snapshot}\nactual:\n{shape}\nset PG_NAMED_ARGS_UPDATE_PLANS to accept the new plan",
+            query.location(),
+            path.display(),
+            query.sql(),
+        );
+    }
+}
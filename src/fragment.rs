@@ -0,0 +1,107 @@
+use std::fmt;
+
+/// The characters that are allowed outside of quoted identifiers in a checked fragment.
+const ALLOWED: &[char] = &['_', '.', ',', '(', ')', '=', '<', '>', '!', '*', '+'];
+
+/// Checks that the sql only contains identifiers, qualified and quoted names, numbers, commas,
+/// parentheses and comparisons, so it can't contain literals, comments, parameters or other
+/// statements.
+pub(crate) fn check(sql: &str) -> Result<(), InvalidFragment> {
+    let invalid = |reason| Err(InvalidFragment::new(sql.to_owned(), reason));
+    let mut depth = 0usize;
+    let mut chars = sql.chars();
+    while let Some(x) = chars.next() {
+        match x {
+            '"' => loop {
+                match chars.next() {
+                    // a quote is escaped by doubling it
+                    Some('"') if chars.clone().next() == Some('"') => {
+                        chars.next();
+                    }
+                    Some('"') => break,
+                    Some(_) => {}
+                    None => return invalid("it contains an unclosed quoted identifier".into()),
+                }
+            },
+            '(' => depth += 1,
+            ')' => match depth.checked_sub(1) {
+                Some(x) => depth = x,
+                None => return invalid("its parentheses are not balanced".into()),
+            },
+            x if x.is_alphanumeric() || x.is_whitespace() || ALLOWED.contains(&x) => {}
+            x => return invalid(format!("it contains `{x}`")),
+        }
+    }
+    if depth > 0 {
+        return invalid("its parentheses are not balanced".into());
+    }
+    Ok(())
+}
+
+/// Creates a [Fragment](crate::Fragment) from a constant string, like a snippet that is shared
+/// between queries or crates.
+///
+/// The constant is validated at compile time with the same rules as the literals of
+/// [fragment!](crate::fragment!), so it can't contain `$`. The path needs to refer to a
+/// `const` of type `&'static str`, a `static` or a runtime value is not accepted.
+///
+/// ```
+/// # use pg_named_args::{const_fragment, query_args};
+/// mod snippets {
+///     pub const RECENT: &str = "ORDER BY time DESC LIMIT 10";
+/// }
+///
+/// let location = "sweden";
+/// let (query, params) = query_args!(
+///     "SELECT report FROM weather_reports WHERE location = $location ${recent}",
+///     Args { location },
+///     Sql { recent: const_fragment!(snippets::RECENT) }
+/// );
+/// assert_eq!(
+///     query,
+///     "SELECT report FROM weather_reports WHERE location = $1 ORDER BY time DESC LIMIT 10"
+/// );
+/// ```
+#[macro_export]
+macro_rules! const_fragment {
+    ($path:path) => {{
+        const SQL: &str = $path;
+        const _: () = ::std::assert!(
+            $crate::Fragment::is_valid_literal(SQL),
+            "Fragment is not allowed to contain `$`"
+        );
+        $crate::Fragment::with_origin(
+            $crate::Fragment::new_unchecked(SQL, $crate::__private::LITERAL),
+            ::std::concat!(
+                ::std::file!(),
+                ":",
+                ::std::line!(),
+                " (",
+                ::std::module_path!(),
+                ")"
+            ),
+        )
+    }};
+}
+
+/// The error returned when a fragment is not valid, see
+/// [Fragment::new_checked](crate::Fragment::new_checked).
+#[derive(Debug)]
+pub struct InvalidFragment {
+    sql: String,
+    reason: String,
+}
+
+impl InvalidFragment {
+    fn new(sql: String, reason: String) -> Self {
+        Self { sql, reason }
+    }
+}
+
+impl fmt::Display for InvalidFragment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fragment {:?}: {}", self.sql, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidFragment {}
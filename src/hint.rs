@@ -0,0 +1,141 @@
+use std::fmt;
+
+use crate::Query;
+#[cfg(not(pg_named_args_literal_sql))]
+use crate::{Identifier, InvalidIdentifier};
+
+/// A planner hint for the `pg_hint_plan` extension, which is added to a query with
+/// [Query::with_hints].
+///
+/// The names of the tables and indexes are validated like an [Identifier](crate::Identifier) and are always
+/// quoted, so a hint can't end the hint comment or inject SQL. The names are runtime strings,
+/// so the hints can't be created with `--cfg pg_named_args_literal_sql`.
+///
+/// ```
+/// # use pg_named_args::{query, Hint};
+/// let id = 1;
+/// let (query, _) = query!(
+///     "SELECT * FROM orders o JOIN customers c ON c.id = o.customer_id WHERE o.id = $id",
+///     Args { id }
+/// );
+/// let hints = [
+///     Hint::index_scan("o", "orders_pkey").unwrap(),
+///     Hint::leading(&["o", "c"]).unwrap(),
+/// ];
+/// assert_eq!(
+///     query.with_hints(&hints).sql(),
+///     r#"/*+ IndexScan("o" "orders_pkey") Leading("o" "c") */ SELECT * FROM orders o JOIN customers c ON c.id = o.customer_id WHERE o.id = $1"#
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Hint(String);
+
+#[cfg(not(pg_named_args_literal_sql))]
+impl Hint {
+    /// Forces a sequential scan on the table, `SeqScan(table)`.
+    pub fn seq_scan(table: &str) -> Result<Self, InvalidIdentifier> {
+        Self::new("SeqScan", &[table])
+    }
+
+    /// Forces an index scan on the table with the index, `IndexScan(table index)`.
+    pub fn index_scan(table: &str, index: &str) -> Result<Self, InvalidIdentifier> {
+        Self::new("IndexScan", &[table, index])
+    }
+
+    /// Forces an index only scan on the table with the index, `IndexOnlyScan(table index)`.
+    pub fn index_only_scan(table: &str, index: &str) -> Result<Self, InvalidIdentifier> {
+        Self::new("IndexOnlyScan", &[table, index])
+    }
+
+    /// Forbids an index scan on the table, `NoIndexScan(table)`.
+    pub fn no_index_scan(table: &str) -> Result<Self, InvalidIdentifier> {
+        Self::new("NoIndexScan", &[table])
+    }
+
+    /// Forces a nested loop join of the tables, `NestLoop(table table...)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when less than two tables are given.
+    pub fn nest_loop(tables: &[&str]) -> Result<Self, InvalidIdentifier> {
+        Self::join("NestLoop", tables)
+    }
+
+    /// Forces a hash join of the tables, `HashJoin(table table...)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when less than two tables are given.
+    pub fn hash_join(tables: &[&str]) -> Result<Self, InvalidIdentifier> {
+        Self::join("HashJoin", tables)
+    }
+
+    /// Forces a merge join of the tables, `MergeJoin(table table...)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when less than two tables are given.
+    pub fn merge_join(tables: &[&str]) -> Result<Self, InvalidIdentifier> {
+        Self::join("MergeJoin", tables)
+    }
+
+    /// Forces the join order of the tables, `Leading(table table...)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when less than two tables are given.
+    pub fn leading(tables: &[&str]) -> Result<Self, InvalidIdentifier> {
+        Self::join("Leading", tables)
+    }
+
+    /// Sets the estimated number of rows of the join of the tables, `Rows(table table... #rows)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics when less than two tables are given.
+    pub fn rows(tables: &[&str], rows: u64) -> Result<Self, InvalidIdentifier> {
+        let hint = Self::join("Rows", tables)?.0;
+        Ok(Self(format!("{} #{rows})", hint.trim_end_matches(')'))))
+    }
+
+    fn join(name: &str, tables: &[&str]) -> Result<Self, InvalidIdentifier> {
+        assert!(tables.len() >= 2, "`{name}` hint needs at least two tables");
+        Self::new(name, tables)
+    }
+
+    fn new(name: &str, objects: &[&str]) -> Result<Self, InvalidIdentifier> {
+        let mut hint = format!("{name}(");
+        for (idx, object) in objects.iter().enumerate() {
+            if object.contains("*/") || object.contains("/*") {
+                let reason = "it contains a comment delimiter";
+                return Err(InvalidIdentifier::new(object.to_string(), reason));
+            }
+            if idx > 0 {
+                hint.push(' ');
+            }
+            hint.push_str(&Identifier::new(*object)?.to_string());
+        }
+        hint.push(')');
+        Ok(Self(hint))
+    }
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Query {
+    /// The query prefixed with a `/*+ ... */` comment with the hints for `pg_hint_plan`,
+    /// see [Hint]. Without hints, the query is returned unchanged.
+    ///
+    /// The fingerprint and the names are the same as of the original query.
+    pub fn with_hints(&self, hints: &[Hint]) -> Query {
+        if hints.is_empty() {
+            return self.clone();
+        }
+        let hints: Vec<_> = hints.iter().map(|x| x.0.as_str()).collect();
+        self.wrapped(&format!("/*+ {} */ ", hints.join(" ")), "")
+    }
+}
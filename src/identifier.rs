@@ -0,0 +1,196 @@
+use std::fmt;
+
+/// The maximum length of an identifier in bytes, longer identifiers are truncated by PostgreSQL.
+const MAX_LEN: usize = 63;
+
+/// A validated SQL identifier, for the places where bind parameters can't be used,
+/// like channel names of `LISTEN`.
+///
+/// The identifier is always quoted when it is displayed, so it can't be used for injection.
+///
+/// ```
+/// # use pg_named_args::Identifier;
+/// let channel = Identifier::new("order \"events\"").unwrap();
+/// assert_eq!(channel.to_string(), r#""order ""events""""#);
+/// assert!(Identifier::new("").is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Identifier(String);
+
+impl Identifier {
+    /// Validates the identifier, it must be non-empty, at most 63 bytes long and can't contain
+    /// the NUL character.
+    ///
+    /// This is not available with `--cfg pg_named_args_literal_sql`, then identifiers can only
+    /// be created from literals with [identifier!](crate::identifier!).
+    #[cfg(not(pg_named_args_literal_sql))]
+    pub fn new(name: impl Into<String>) -> Result<Self, InvalidIdentifier> {
+        Self::validate(name.into())
+    }
+
+    #[cfg(pg_named_args_literal_sql)]
+    pub(crate) fn new(name: impl Into<String>) -> Result<Self, InvalidIdentifier> {
+        Self::validate(name.into())
+    }
+
+    #[doc(hidden)]
+    /// This is the constructor used by the [identifier!](crate::identifier!) macro, which
+    /// validates the literal at compile time.
+    /// It is not intended to be used manually.
+    pub fn from_literal(name: &'static str, _: crate::__private::Literal) -> Self {
+        Self(name.to_owned())
+    }
+
+    #[doc(hidden)]
+    /// This is used by the [identifier!](crate::identifier!) macro to validate the literal.
+    pub const fn is_valid(name: &str) -> bool {
+        let bytes = name.as_bytes();
+        let mut idx = 0;
+        while idx < bytes.len() {
+            if bytes[idx] == 0 {
+                return false;
+            }
+            idx += 1;
+        }
+        !bytes.is_empty() && bytes.len() <= MAX_LEN
+    }
+
+    fn validate(name: String) -> Result<Self, InvalidIdentifier> {
+        let reason = if name.is_empty() {
+            "it is empty"
+        } else if name.len() > MAX_LEN {
+            "it is longer than 63 bytes"
+        } else if name.contains('\0') {
+            "it contains a NUL character"
+        } else {
+            return Ok(Self(name));
+        };
+        Err(InvalidIdentifier { name, reason })
+    }
+
+    /// The identifier without quotes.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self.0.replace('"', "\"\""))
+    }
+}
+
+/// Creates an [Identifier] from a string literal, which is validated at compile time.
+///
+/// ```
+/// # use pg_named_args::identifier;
+/// let table = identifier!("weather_reports");
+/// assert_eq!(table.to_string(), r#""weather_reports""#);
+/// ```
+#[macro_export]
+macro_rules! identifier {
+    ($name:literal) => {{
+        const NAME: &str = ::std::concat!($name);
+        const _: () = ::std::assert!(
+            $crate::Identifier::is_valid(NAME),
+            "invalid identifier, it must be non-empty, at most 63 bytes long and without NUL"
+        );
+        $crate::Identifier::from_literal(NAME, $crate::__private::LITERAL)
+    }};
+}
+
+/// A validated schema name, which binds the schema of `$schema.table` names in a query at
+/// runtime, for example per tenant.
+///
+/// The schema is given in the `Sql` struct, like the fragments, and is always quoted.
+///
+/// ```
+/// # use pg_named_args::{query, SchemaName};
+/// let schema = SchemaName::allowed("tenant_a", &["tenant_a", "tenant_b"]).unwrap();
+/// let id = 1;
+/// let (query, params) = query!(
+///     "SELECT * FROM $schema.orders WHERE id = $id",
+///     Args { id },
+///     Sql { schema }
+/// );
+/// assert_eq!(query.sql(), r#"SELECT * FROM "tenant_a".orders WHERE id = $1"#);
+/// assert!(SchemaName::allowed("public", &["tenant_a", "tenant_b"]).is_err());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SchemaName(Identifier);
+
+impl SchemaName {
+    /// Validates the schema name, see [Identifier::new].
+    #[cfg(not(pg_named_args_literal_sql))]
+    pub fn new(name: impl Into<String>) -> Result<Self, InvalidIdentifier> {
+        Identifier::new(name).map(Self)
+    }
+
+    /// Validates the schema name and checks that it is one of the allowed names.
+    ///
+    /// With `--cfg pg_named_args_literal_sql`, the allowed names are identifiers, which can
+    /// only be created from literals with [identifier!](crate::identifier!).
+    #[cfg(not(pg_named_args_literal_sql))]
+    pub fn allowed(
+        name: impl Into<String>,
+        allow_list: &[&str],
+    ) -> Result<Self, InvalidIdentifier> {
+        let name = name.into();
+        if !allow_list.contains(&&*name) {
+            let reason = "it is not in the allow-list";
+            return Err(InvalidIdentifier { name, reason });
+        }
+        Identifier::new(name).map(Self)
+    }
+
+    /// Checks that the schema name is one of the allowed names, which are created from
+    /// literals with [identifier!](crate::identifier!).
+    #[cfg(pg_named_args_literal_sql)]
+    pub fn allowed(name: &str, allow_list: &[Identifier]) -> Result<Self, InvalidIdentifier> {
+        match allow_list.iter().find(|x| x.as_str() == name) {
+            Some(allowed) => Ok(Self(allowed.clone())),
+            None => {
+                let reason = "it is not in the allow-list";
+                Err(InvalidIdentifier::new(name.to_owned(), reason))
+            }
+        }
+    }
+
+    /// The schema name without quotes.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    #[doc(hidden)]
+    /// This is used by the [query!](crate::query!) macro to insert the schema.
+    pub fn quoted(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+impl fmt::Display for SchemaName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The error returned when an identifier is not valid, see [Identifier::new].
+#[derive(Debug)]
+pub struct InvalidIdentifier {
+    name: String,
+    reason: &'static str,
+}
+
+impl InvalidIdentifier {
+    pub(crate) fn new(name: String, reason: &'static str) -> Self {
+        Self { name, reason }
+    }
+}
+
+impl fmt::Display for InvalidIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid identifier {:?}: {}", self.name, self.reason)
+    }
+}
+
+impl std::error::Error for InvalidIdentifier {}
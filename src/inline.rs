@@ -0,0 +1,161 @@
+use std::fmt;
+
+use bytes::BytesMut;
+use postgres_protocol::escape::escape_literal;
+use postgres_types::{IsNull, ToSql, Type, WrongType};
+
+use crate::Query;
+
+/// The types that can be inlined, in the order they are tried.
+const TYPES: [Type; 10] = [
+    Type::BOOL,
+    Type::INT2,
+    Type::INT4,
+    Type::INT8,
+    Type::FLOAT4,
+    Type::FLOAT8,
+    Type::TEXT,
+    Type::BYTEA,
+    Type::JSONB,
+    Type::UUID,
+];
+
+impl Query {
+    /// The query with the arguments inlined as escaped literals, for use with the simple
+    /// query protocol.
+    ///
+    /// This is meant for utility statements that don't accept bind parameters, and for
+    /// deployments behind a transaction-pooling PgBouncer. The literals are quoted without
+    /// a type, so PostgreSQL infers the type from the context like it does for parameters.
+    ///
+    /// Only arguments of boolean, integer, floating point, text, bytea, json and uuid types
+    /// are supported.
+    ///
+    /// ```
+    /// # use pg_named_args::query;
+    /// let password = "it's a secret";
+    /// let (query, params) = query!("ALTER ROLE fred PASSWORD $password", Args { password });
+    /// assert_eq!(
+    ///     query.inline(params).unwrap(),
+    ///     "ALTER ROLE fred PASSWORD 'it''s a secret'"
+    /// );
+    /// ```
+    pub fn inline(&self, params: &[&(dyn ToSql + Sync)]) -> Result<String, InlineError> {
+        let sql = self.sql();
+        let mut out = String::new();
+        let mut rest = sql;
+        while let Some(x) = rest.chars().next() {
+            match x {
+                '\'' | '"' => {
+                    // skip string constants and quoted identifiers, a doubled quote is skipped
+                    // as two separate strings
+                    let len = rest[1..].find(x).map_or(rest.len(), |i| i + 2);
+                    out.push_str(&rest[..len]);
+                    rest = &rest[len..];
+                }
+                '$' => {
+                    let digits = rest[1..].find(|x: char| !x.is_ascii_digit());
+                    let digits = digits.unwrap_or(rest.len() - 1);
+                    let idx = rest[1..=digits].parse::<usize>().ok();
+                    match idx.and_then(|idx| params.get(idx.checked_sub(1)?)) {
+                        Some(param) => {
+                            let name = self.name_of(idx.unwrap() - 1).unwrap_or_default();
+                            out.push_str(&literal(*param).ok_or(InlineError { name })?);
+                            rest = &rest[digits + 1..];
+                        }
+                        None => {
+                            out.push('$');
+                            rest = &rest[1..];
+                        }
+                    }
+                }
+                x => {
+                    out.push(x);
+                    rest = &rest[x.len_utf8()..];
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Converts the argument to a literal, by encoding it as the first type it accepts.
+fn literal(param: &(dyn ToSql + Sync)) -> Option<String> {
+    let mut buf = BytesMut::new();
+    for ty in TYPES {
+        buf.clear();
+        // `accepts` can't be called on a trait object, but the checked version reports it
+        let is_null = match param.to_sql_checked(&ty, &mut buf) {
+            Ok(is_null) => is_null,
+            Err(err) if err.is::<WrongType>() => continue,
+            Err(_) => return None,
+        };
+        if let IsNull::Yes = is_null {
+            return Some("NULL".to_owned());
+        }
+
+        let text = match ty {
+            Type::BOOL => (buf[0] != 0).to_string(),
+            Type::INT2 => i16::from_be_bytes(buf[..].try_into().ok()?).to_string(),
+            Type::INT4 => i32::from_be_bytes(buf[..].try_into().ok()?).to_string(),
+            Type::INT8 => i64::from_be_bytes(buf[..].try_into().ok()?).to_string(),
+            Type::FLOAT4 => float(f32::from_be_bytes(buf[..].try_into().ok()?).into()),
+            Type::FLOAT8 => float(f64::from_be_bytes(buf[..].try_into().ok()?)),
+            Type::TEXT => String::from_utf8(buf.to_vec()).ok()?,
+            Type::BYTEA => buf.iter().fold("\\x".to_owned(), |mut out, x| {
+                out.push_str(&format!("{x:02x}"));
+                out
+            }),
+            // the binary format of jsonb is a version byte followed by the text
+            Type::JSONB => String::from_utf8(buf.get(1..)?.to_vec()).ok()?,
+            Type::UUID => {
+                let hex: String = buf.iter().map(|x| format!("{x:02x}")).collect();
+                format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..]
+                )
+            }
+            _ => unreachable!(),
+        };
+        return Some(escape_literal(&text));
+    }
+    None
+}
+
+fn float(x: f64) -> String {
+    match x {
+        f64::INFINITY => "Infinity".to_owned(),
+        f64::NEG_INFINITY => "-Infinity".to_owned(),
+        x if x.is_nan() => "NaN".to_owned(),
+        x => x.to_string(),
+    }
+}
+
+/// The error returned when an argument can't be inlined, see [Query::inline].
+#[derive(Debug)]
+pub struct InlineError {
+    name: &'static str,
+}
+
+impl InlineError {
+    /// The name of the argument that can't be inlined.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl fmt::Display for InlineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the argument `{}` has a type that can't be inlined as a literal",
+            self.name
+        )
+    }
+}
+
+impl std::error::Error for InlineError {}
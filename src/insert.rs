@@ -0,0 +1,120 @@
+use crate::Query;
+
+/// The maximum number of rows in a batch of [insert_batched!](crate::insert_batched!).
+const MAX_ROWS: usize = 1000;
+
+/// The maximum number of parameters of a statement in PostgreSQL.
+const MAX_PARAMS: usize = u16::MAX as usize;
+
+/// Inserts the rows of a slice in multi-row `INSERT ... VALUES` batches in a transaction,
+/// returning the number of inserted rows.
+///
+/// The statement is written with a `$for(item in $items) { ... }` loop over the rows, like
+/// in [query!](crate::query!), so the columns and the values are checked by the macro.
+/// The rows are given as `items = rows` before the query, and `items` is a batch of the rows
+/// in the `Args`. The first batch has a single row, the other batches have as many rows as fit
+/// in a statement, at most 1000 rows and 65535 parameters.
+/// The transaction needs to be a `tokio_postgres::Transaction`, so either all rows are
+/// inserted or none of them.
+///
+/// ```no_run
+/// # use pg_named_args::insert_batched;
+/// # struct Report { location: String, report: String }
+/// # async fn example(client: &mut tokio_postgres::Client, reports: Vec<Report>) -> Result<(), tokio_postgres::Error> {
+/// let txn = client.transaction().await?;
+/// let source = "station";
+/// let count = insert_batched!(
+///     &txn,
+///     batch = &reports,
+///     r"
+///     INSERT INTO weather_reports(location, report, source)
+///     VALUES $for(r in $batch) { ($r.location, $r.report, $source) }
+///     ",
+///     Args { batch, source }
+/// )
+/// .await?;
+/// txn.commit().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! insert_batched {
+    ($txn:expr, $items:ident = $rows:expr, $($query:tt)+) => {
+        async {
+            let txn: &$crate::__private::tokio_postgres::Transaction<'_> = $txn;
+            let mut rest: &[_] = $rows;
+            let mut batch_size = 1;
+            let mut count = 0;
+            while !rest.is_empty() {
+                let $items;
+                ($items, rest) = rest.split_at(::std::cmp::Ord::min(batch_size, rest.len()));
+                let (query, params) = $crate::query!($($query)+);
+                batch_size = $crate::__private::batch_size(&query, params.len(), $items.len());
+                count += $crate::Executor::execute_named(txn, &query, params).await?;
+            }
+            ::std::result::Result::Ok::<u64, $crate::__private::tokio_postgres::Error>(count)
+        }
+    };
+}
+
+/// Inserts a row, returning the generated key of the row, like the `id` of a serial or
+/// identity column.
+///
+/// The statement is written like in [query!](crate::query!), typically with the insert syntax,
+/// and `RETURNING id` is appended to it. Another column is given with `returning = column`
+/// before the query. The client is any executor with the
+/// [ExecutorExt](crate::ExecutorExt) helpers, and the key is returned with the type it's assigned
+/// to. An insert that doesn't insert a row, like with `ON CONFLICT DO NOTHING`, returns
+/// [Error::NotFound](crate::Error::NotFound).
+///
+/// ```no_run
+/// # use pg_named_args::insert_returning;
+/// # async fn example(client: &tokio_postgres::Client) -> Result<(), pg_named_args::Error> {
+/// let location = "sweden";
+/// let report = "sunny";
+/// let id: i64 = insert_returning!(
+///     client,
+///     "INSERT INTO weather_reports ($[location, report]) VALUES ($[..])",
+///     Args { location, report }
+/// )
+/// .await?;
+/// let code: String = insert_returning!(
+///     client,
+///     returning = code,
+///     "INSERT INTO weather_stations ($[location]) VALUES ($[..])",
+///     Args { location }
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! insert_returning {
+    ($client:expr, returning = $column:ident, $($query:tt)+) => {
+        async {
+            let (query, params) = $crate::query!($($query)+);
+            let column = $crate::Identifier::from_literal(
+                ::std::stringify!($column),
+                $crate::__private::LITERAL,
+            );
+            let query = query.returning(&column);
+            let row = $crate::ExecutorExt::query_one_named($client, &query, params).await?;
+            ::std::result::Result::Ok::<_, $crate::Error>(row.try_get(0)?)
+        }
+    };
+    ($client:expr, $($query:tt)+) => {
+        $crate::insert_returning!($client, returning = id, $($query)+)
+    };
+}
+
+/// The number of rows of the next batches, from the parameters of a batch of `rows` rows.
+#[doc(hidden)]
+pub fn batch_size(query: &Query, params: usize, rows: usize) -> usize {
+    // the parameters of the rows are not part of the names
+    let shared = query.names().len();
+    let per_row = (params - shared) / rows;
+    match per_row {
+        0 => MAX_ROWS,
+        _ => ((MAX_PARAMS - shared) / per_row).clamp(1, MAX_ROWS),
+    }
+}
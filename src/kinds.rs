@@ -0,0 +1,76 @@
+use crate::{Fragment, Identifier};
+
+/// The columns of an `ORDER BY` clause, like `created_at DESC, id`, for a
+/// `${name: order_by}` slot.
+///
+/// A fragment of a kind can only be used in a slot of the same kind, so a fragment that is
+/// meant to order the rows can't be used as a condition by mistake.
+///
+/// ```
+/// # use pg_named_args::{fragment, query_args, OrderByFragment};
+/// let order_by = OrderByFragment::new(fragment!("created_at DESC"));
+/// let (query, _) = query_args!(
+///     "SELECT * FROM reports ORDER BY ${order_by: order_by}",
+///     Args {},
+///     Sql { order_by }
+/// );
+/// assert_eq!(query, "SELECT * FROM reports ORDER BY created_at DESC");
+/// ```
+#[derive(Clone, Default)]
+pub struct OrderByFragment(Fragment);
+
+impl OrderByFragment {
+    /// Marks the fragment as this kind.
+    pub fn new(fragment: Fragment) -> Self {
+        Self(fragment)
+    }
+
+    /// The sql of the fragment.
+    pub fn get(&self) -> &str {
+        self.0.get()
+    }
+
+    /// The call site of the fragment, see [Fragment::origin].
+    pub fn origin(&self) -> Option<&'static str> {
+        self.0.origin()
+    }
+}
+
+/// A condition, like `verified AND time > now() - interval '1 day'`, for a
+/// `${name: where}` slot.
+#[derive(Clone, Default)]
+pub struct WhereFragment(Fragment);
+
+impl WhereFragment {
+    /// Marks the fragment as this kind.
+    pub fn new(fragment: Fragment) -> Self {
+        Self(fragment)
+    }
+
+    /// The sql of the fragment.
+    pub fn get(&self) -> &str {
+        self.0.get()
+    }
+
+    /// The call site of the fragment, see [Fragment::origin].
+    pub fn origin(&self) -> Option<&'static str> {
+        self.0.origin()
+    }
+}
+
+/// A quoted identifier, like the name of a column, for a `${name: ident}` slot.
+/// It is created from a validated `Identifier`, so it can't contain other sql.
+#[derive(Clone, Default)]
+pub struct IdentFragment(String);
+
+impl IdentFragment {
+    /// Quotes the identifier.
+    pub fn new(ident: &Identifier) -> Self {
+        Self(ident.to_string())
+    }
+
+    /// The sql of the fragment.
+    pub fn get(&self) -> &str {
+        &self.0
+    }
+}
@@ -5,13 +5,16 @@
 //! arguments.
 //!
 //! # Dependencies
-//! The macro expands to usage of `postgres-types`, so make sure to have it in your dependencies:
+//! The macro expands to usage of `postgres-types`, which is re-exported by this crate,
+//! so only `pg_named_args` is needed in your dependencies:
 //! ```toml
 //! [dependencies]
-//! postgres-types = ...
 //! pg_named_args = ...
 //! ```
 //!
+//! The re-exported `postgres-types` is the same version as used by `tokio-postgres` 0.7.
+//! Upgrading it to an incompatible version is a breaking change of this crate.
+//!
 //! # Query Argument Syntax
 //! The macro uses struct syntax for the named arguments.
 //! The struct name `Args` is required to support rustfmt and rust-analyzer.
@@ -49,9 +52,124 @@
 //! let rows = client.query(query, args).await?;
 //! ```
 //!
+//! Arguments that only differ by case or underscores, like `$userId` and `$user_id`, are an
+//! error, as are fields of `Args` that only differ like that from an argument, because these
+//! are almost always a typo.
+//!
+//! Long queries can be split over multiple adjacent string literals, which are concatenated
+//! like with `concat!`, e.g. `query_args!("SELECT * FROM weather_reports ", "WHERE location =
+//! $location", Args { location })`.
+//!
+//! Small queries can embed the expression of an argument directly with `$(expr)`, which binds
+//! the expression as the next parameter, e.g. `WHERE id = $(user.id)`. The `Args` struct can
+//! be left out when all arguments are expressions. The name of such an argument is the
+//! expression itself, like `user.id`.
+//!
+//! JSON objects can be built from the arguments with `$json{name, email}`, which expands to
+//! `jsonb_build_object('name', $1, 'email', $2)`, so the keys and the arguments can't mismatch.
+//!
+//! Parts of the query that can't be parameters are inserted with `${name}` fragments, which are
+//! given in a `Sql` struct. Fragments are created with `fragment!` from a string literal, or from
+//! a `LockMode` to vary the locking clause, like `FOR UPDATE SKIP LOCKED`, at runtime.
+//! Fragments can be composed from other fragments with `fragment!("ORDER BY {col} {dir}", col, dir)`,
+//! which interpolates the fragments like `format!`, with `{{` and `}}` for literal braces.
+//! Snippets that are shared as a `const` string, possibly from another crate, are made a
+//! fragment with `const_fragment!(path::TO_CONST)`, which validates the constant at compile time.
+//! Sql that is only known at runtime, like a clause from trusted configuration, can be made a
+//! fragment with `Fragment::new_checked`, which only accepts names, numbers and comparisons.
+//! A fragment can have a default in the template, like `${order_by = ORDER BY id}`, so it can
+//! be left out of the `Sql` struct. The `Sql` struct itself can be left out when all fragments
+//! have a default.
+//!
+//! A fragment can be restricted to a kind in the template, like `${order_by: order_by}`,
+//! `${filter: where}` or `${column: ident}`. The fragment in the `Sql` struct then needs to be
+//! an `OrderByFragment`, a `WhereFragment` or an `IdentFragment` respectively, so a fragment
+//! can't be spliced into a slot of another kind. An `IdentFragment` is created from a validated
+//! `Identifier`.
+//!
+//! A `$where{...}` clause leaves out the conditions that are a single fragment, like
+//! `$where{location = $location AND ${filter}}`, when the fragment is empty. The other
+//! conditions are always included, and the `WHERE` is left out when there are no conditions
+//! at all. The conditions are split on the `AND`s outside of parentheses, and every condition
+//! is wrapped in parentheses, so an `OR` in a condition doesn't escape the other conditions.
+//!
+//! Applications that need to guarantee that all sql comes from literals in the source can build
+//! with `RUSTFLAGS="--cfg pg_named_args_literal_sql"`. This removes `Fragment::new_checked`,
+//! `Identifier::new`, `SchemaName::new` and the `Hint` constructors, so the runtime
+//! constructors can't be used anywhere in the binary. Identifiers are then created with
+//! `identifier!("name")`, which validates the literal at compile time, and schema names with
+//! `SchemaName::allowed`, which picks one of such identifiers. Interpolated fragments only
+//! combine the literal of `fragment!` with other fragments. The hidden constructors that the
+//! macros use take a token from the `__private` module, so an audit only needs to search for
+//! `__private` to find code that bypasses the macros.
+//! This is a cfg instead of a feature, because it removes functions, which would break other
+//! crates in the build that enable the features they need.
+//!
+//! # Pattern Matching
+//! The values used in `LIKE` and `ILIKE` patterns need to have the pattern characters escaped.
+//! The `~contains`, `~starts_with` and `~ends_with` modifiers directly after a named argument
+//! expand to a pattern with the escaped value, which is still bound as a parameter.
+//! Any other word after the `~`, like in `$term~x`, is left alone as a regex match.
+//! The `escape_like` function does the same for patterns built in Rust.
+//!
+//! ```
+//! # use pg_named_args::query_args;
+//! #
+//! let term = "50%";
+//! let (query, args) = query_args!(
+//!     r"SELECT * FROM weather_reports WHERE report ILIKE $term~contains",
+//!     Args { term }
+//! );
+//! ```
+//!
+//! # Text Search
+//! The `~websearch`, `~plain` and `~phrase` modifiers convert a named argument to a `tsquery`
+//! with `websearch_to_tsquery`, `plainto_tsquery` and `phraseto_tsquery` respectively.
+//! The text search configuration can be given as a named argument, like `~websearch($config)`,
+//! which is bound as a parameter and cast to `regconfig`. Otherwise the default configuration
+//! of the database is used. A named argument is bound once, so the same search can be used
+//! to filter and to rank the results.
+//!
+//! ```
+//! # use pg_named_args::query_args;
+//! #
+//! let search = r#""rain showers" -snow"#;
+//! let config = "english";
+//! let (query, args) = query_args!(
+//!     r"
+//!     SELECT location, report FROM weather_reports
+//!     WHERE document @@ $search~websearch($config)
+//!     ORDER BY ts_rank(document, $search~websearch($config)) DESC
+//!     ",
+//!     Args { search, config }
+//! );
+//! ```
+//!
+//! # Membership Tests
+//! A list in a slice argument can be tested with `= ANY($ids)`, which the planner estimates
+//! from the elements of the array. For long lists, a join with the unnested list is often a
+//! better plan. The `~any` modifier expands to `ANY($1)` and the `~unnest` modifier to
+//! `ANY(SELECT unnest($1))`, so the form can be chosen per query. The `~in` modifier chooses at
+//! runtime, it uses the unnested form when the list has more than 100 items, or the number
+//! given with the `unnest_above(n)` option.
+//!
+//! ```
+//! # use pg_named_args::query_args;
+//! #
+//! let ids = vec![1, 2, 3];
+//! let (query, args) = query_args!(
+//!     unnest_above(2),
+//!     r"SELECT * FROM weather_reports WHERE id = $ids~in",
+//!     Args { ids: &ids }
+//! );
+//! assert_eq!(query, "SELECT * FROM weather_reports WHERE id = ANY(SELECT unnest($1))");
+//! ```
+//!
 //! # Insert Syntax
 //! For `INSERT`'s a special syntax is supported, which helps to avoid mismatches
-//! between the list of column names and the values:
+//! between the list of column names and the values.
+//! Like in Rust lists, the column list may end with a trailing comma, and line comments
+//! can annotate the columns:
 //!
 //! ```
 //! # use pg_named_args::query_args;
@@ -78,6 +196,468 @@
 //! client.execute(query, args).await?;
 //! ```
 //!
+//! Quoted identifiers like `"Order Total"` are kept as they are in the column list,
+//! the argument uses the snake case version of the name, `order_total`.
+//!
+//! Both groups can end with a fragment, like `$[location, time, ${audit}]` and
+//! `$[.., ${audit_values}]`, for columns that are only known at runtime, like audit columns
+//! that only some deployments have. The fragment is appended with a comma, unless it's empty.
+//!
+//! A column that ends with `?`, like `$[id, name?, email?]`, is optional. Its argument is an
+//! `Option`, and the column and its value are only written when the argument is `Some`, which
+//! fits partial updates like `UPDATE users SET ($[name?, email?]) = ROW($[..])`. The parameters
+//! of the optional columns are numbered after the other parameters and are not part of
+//! `Query::names`. A query can have a single group with optional columns, which is not
+//! supported with `$for` and the `qmark` and `prepare` options.
+//!
+//! This syntax can be enforced with the `strict_insert` option, which projects can add to
+//! every insert, for example in a wrapper macro. An `INSERT` statement without a `$[...]`
+//! group for the columns, or with values that don't use the `$[..]` group, then fails to
+//! compile.
+//!
+//! Upserts are written with `upsert_args!`, which derives the `ON CONFLICT` clause from the
+//! group, so the columns are only listed once. The `conflict(...)` option names the columns of
+//! the conflict target, and the other columns are updated:
+//!
+//! ```
+//! # use pg_named_args::upsert_args;
+//! # let (location, time, report) = ("sweden", "monday", "sunny");
+//! let (query, args) = upsert_args!(
+//!     conflict(location, time),
+//!     r"INSERT INTO weather_reports ($[location, time, report]) VALUES ($[..])",
+//!     Args { location, time, report }
+//! );
+//! assert!(query.ends_with("ON CONFLICT (location, time) DO UPDATE SET report = excluded.report"));
+//! ```
+//!
+//! Statements that are assembled from pieces, like an `INSERT ... SELECT` wrapper or the
+//! template of a dynamic `EXECUTE format(...)`, can use `values_args!`. It returns the columns
+//! of a group and the matching placeholders separately, together with the parameters.
+//!
+//! # Composite Keys
+//! Rows can be selected or deleted by a list of composite keys in a single round trip, by
+//! binding an array per column. The `Transpose` trait turns a slice of tuples into a list per
+//! column, which fits the column group syntax:
+//!
+//! ```
+//! # use pg_named_args::{query_args, Transpose};
+//! #
+//! let keys = vec![("sweden", "monday"), ("netherlands", "tuesday")];
+//! let (location, time) = keys.transpose();
+//! let (query, args) = query_args!(
+//!     r"
+//!     SELECT report FROM weather_reports
+//!     WHERE ($[location, time]) IN (SELECT * FROM UNNEST($[..]))
+//!     ",
+//!     Args { location, time }
+//! );
+//! ```
+//!
+//! # Composite Arrays
+//! Bulk upserts can bind all rows as a single array of a composite type, with a cast like
+//! `$rows::my_type[]` that tells PostgreSQL the type of the parameter.
+//! The `derive` feature enables the derive macros of the re-exported `postgres-types`, which
+//! need `postgres_types` to be in scope.
+//!
+//! ```ignore
+//! use pg_named_args::postgres_types::{self, ToSql};
+//!
+//! #[derive(Debug, ToSql)]
+//! #[postgres(name = "weather_report")]
+//! struct WeatherReport {
+//!     location: String,
+//!     report: String,
+//! }
+//!
+//! let (query, args) = query_args!(
+//!     r"
+//!     INSERT INTO weather_reports(location, report)
+//!     SELECT * FROM UNNEST($reports::weather_report[])
+//!     ON CONFLICT (location) DO UPDATE SET report = excluded.report
+//!     ",
+//!     Args { reports }
+//! );
+//! ```
+//!
+//! # Schema Qualification
+//! Multi-tenant deployments with a schema per tenant can bind the schema at runtime.
+//! A named argument directly followed by a `.` and a table name, like `$schema.orders`,
+//! is a schema slot. The schema is given as a validated `SchemaName` in the `Sql` struct
+//! and is always quoted. The schema names can be restricted to an allow-list.
+//!
+//! ```
+//! # use pg_named_args::{query_args, SchemaName};
+//! #
+//! let schema = SchemaName::allowed("tenant_a", &["tenant_a", "tenant_b"]).unwrap();
+//! let location = "sweden";
+//! let (query, args) = query_args!(
+//!     r"SELECT report FROM $schema.weather_reports WHERE location = $location",
+//!     Args { location },
+//!     Sql { schema }
+//! );
+//! ```
+//!
+//! # Dynamic Sections
+//! A `$when(flag) { ... }` section is only included in the query when the named argument
+//! `flag` is `true` at runtime. The flag is not a parameter of the query, unless it is also used
+//! as `$flag`.
+//!
+//! A `$match(arg) { Pattern => { ... }, ... }` section includes the body of the arm that matches
+//! the named argument `arg` at runtime. The patterns are Rust patterns, like `Mode::Fast`, and
+//! the match is checked for exhaustiveness at compile time. A single name is rejected, because
+//! it would match any value.
+//!
+//! The macro generates a query for every combination of the sections, so the parameters are
+//! numbered correctly for each of them, and each of them has its own fingerprint.
+//! A query can have at most 16 combinations, like four flags, and sections can't be nested.
+//!
+//! ```
+//! # use pg_named_args::query_args;
+//! #
+//! enum Order {
+//!     Newest,
+//!     Location,
+//! }
+//!
+//! let location = "sweden";
+//! let include_unverified = false;
+//! let order = Order::Newest;
+//!
+//! let (query, args) = query_args!(
+//!     r"
+//!     SELECT report FROM weather_reports
+//!     WHERE location = $location
+//!     $when(include_unverified) { OR verified = false }
+//!     ORDER BY $match(order) {
+//!         Order::Newest => { time DESC },
+//!         Order::Location => { location, time DESC },
+//!     }
+//!     ",
+//!     Args { location, include_unverified, order }
+//! );
+//! ```
+//!
+//! A `$for(item in $items) { ... }` loop repeats its body for every item of the slice in the
+//! named argument `items`. The fields of an item are used as `$item.field`, or the item itself
+//! as `$item`, and every repetition gets its own parameters after the other parameters.
+//! The repetitions are separated by a comma, or by the separator given after the items, like
+//! `$for(item in $items, UNION ALL)`. A query can have a single loop, without fragments in its
+//! body. The parameters of the items are not part of `Query::names`.
+//!
+//! ```
+//! # use pg_named_args::query_args;
+//! #
+//! struct Report {
+//!     location: &'static str,
+//!     report: &'static str,
+//! }
+//!
+//! let reports = vec![
+//!     Report { location: "sweden", report: "sunny" },
+//!     Report { location: "norway", report: "rainy" },
+//! ];
+//! let (query, args) = query_args!(
+//!     r"
+//!     INSERT INTO weather_reports(location, report)
+//!     VALUES $for(r in $reports) { ($r.location, $r.report) }
+//!     ",
+//!     Args { reports: &reports }
+//! );
+//! assert_eq!(args.len(), 4);
+//! ```
+//!
+//! # Batches
+//! Workflows with multiple statements that use the same arguments can use `batch_args!`.
+//! It takes a list of statements between brackets and a single `Args` struct, and returns an
+//! array with the query and the arguments of every statement.
+//!
+//! ```
+//! # use pg_named_args::batch_args;
+//! #
+//! let location = "sweden";
+//! let report = "sunny";
+//!
+//! let statements = batch_args!(
+//!     [
+//!         r"INSERT INTO weather_reports(location, report) VALUES($location, $report)",
+//!         r"UPDATE locations SET reports = reports + 1 WHERE name = $location",
+//!     ],
+//!     Args { location, report }
+//! );
+//! ```
+//! ```ignore
+//! for (query, args) in statements {
+//!     client.execute(query, args).await?;
+//! }
+//! ```
+//!
+//! # Query Information
+//! The `query!` macro has the same syntax as `query_args!`, but returns a `Query` instead of
+//! the query string. The `Query` contains information about the query, like a stable
+//! fingerprint that is calculated at compile time.
+//! It also contains the names of the arguments in the order of the parameters, so logging,
+//! auditing and custom binding layers can refer to `$3` by its name.
+//! Errors reported by the database can be rendered against the query with `render_error`,
+//! which shows the named arguments instead of the positional arguments.
+//! For log messages and bug reports, `debug_sql` renders the query with the values of the
+//! arguments substituted and labeled with their names. The result is not meant to be executed.
+//! Sensitive arguments can be wrapped with `redact`, so their values show up as `[REDACTED]`.
+//! Long queries can be formatted with `pretty_sql` for the logs, with the keywords in upper
+//! case and every clause on its own line, while the executed query is left untouched.
+//!
+//! The kind of statement is detected at compile time and available as `Query::kind`,
+//! so reads and writes can be routed, audited or metered differently.
+//! The `Router` executor uses it to send the reads to replicas and the writes to the primary.
+//! The `serde` feature implements `Serialize` for `Query`, with the SQL, the fingerprint, the
+//! names of the parameters, the call site, the kind and the template, so queries can be shipped
+//! to analysis services or stored for replay tooling.
+//!
+//! For performance investigations, `explain` and `explain_analyze` return the query prefixed
+//! with `EXPLAIN (FORMAT JSON)` and `EXPLAIN (ANALYZE, FORMAT JSON)` respectively.
+//! The `serde` feature adds the `explain` module with a typed structure for the resulting plan.
+//! Planner hints for the `pg_hint_plan` extension can be added with `with_hints`,
+//! which prefixes the query with a `/*+ ... */` comment built from validated `Hint`s.
+//!
+//! For external tooling, like SQL linters and review dashboards, the `manifest` feature writes
+//! a JSON file for every query at compile time. It contains the name of the prepared statement,
+//! the SQL, the names of the parameters, the call site and the fingerprint.
+//! The files are written to `.pg_named_args` in the directory of the crate.
+//! There is a file per query and source file, so a query that moves to another line keeps its
+//! file. The files of a source file are replaced when the crate is compiled again, so a changed
+//! or removed query doesn't keep its file. The files of a removed source file, or of a crate
+//! that is no longer built, stay until the directory is removed. As only the compiled queries
+//! are written, a query behind a `cfg`, like a test, is left out when its source file is
+//! compiled without it.
+//! The fragments are only known at runtime, so they are marked as `${name}` in the SQL and
+//! their names are listed in `fragments`.
+//! The `cli` feature adds the `pg_named_args` binary, which prints every query of the manifest
+//! with its parameters, as a report for review. With `--explain`, it adds the plan of every
+//! query from `EXPLAIN (GENERIC_PLAN)` against the database in `DATABASE_URL`, which needs
+//! PostgreSQL 16 or later, e.g. `cargo run --features cli --bin pg_named_args -- --explain`.
+//! Tools that rewrite the queries themselves, like code generators and editors, can use the
+//! `pg_named_args_core` crate, which contains the rewriter of the macros.
+//!
+//! ```
+//! # use pg_named_args::query;
+//! #
+//! let location = "sweden";
+//!
+//! let (query, args) = query!(
+//!     r"SELECT report FROM weather_reports WHERE location = $location",
+//!     Args { location }
+//! );
+//! let label = format!("{:016x}", query.fingerprint());
+//! assert_eq!(query.names(), ["location"]);
+//! assert_eq!(query.index_of("location"), Some(0));
+//! ```
+//! ```ignore
+//! let rows = client.query(query.sql(), args).await?;
+//! ```
+//!
+//! # Execution Helpers
+//! With the `tokio-postgres` feature enabled, the `Executor`, `ExecutorExt` and `ClientExt`
+//! traits add methods to the clients and transactions of `tokio-postgres` to execute a `Query`
+//! directly.
+//!
+//! ```ignore
+//! let rows = client.query_named(&query, args).await?;
+//! ```
+//!
+//! Libraries can be generic over the `Executor`, so their users can pick the client.
+//! The `deadpool` feature implements it for the objects and transactions of `deadpool-postgres`.
+//! The `postgres` feature adds the `Blocking` executor for the clients and transactions of the
+//! blocking `postgres` crate, whose futures are ready right away and can be run with
+//! `block_on`, so no async runtime is needed. The `Router`, `Timed` and `Hooked` executors
+//! and the `MockClient` work with all of them.
+//!
+//! The helpers of `ExecutorExt`, like `query_one_named`, `exists_named`, `set_local` and the
+//! advisory locks, only need the methods of the `Executor`, so they work with every executor of
+//! `tokio-postgres` rows and with code that is generic over `E: ExecutorExt`. The helpers of
+//! `ClientExt`, like `COPY` and `query_stream_named`, need a `tokio-postgres` client.
+//!
+//! The rows of a join can be folded into the parents with their children with `nest`,
+//! grouped by the key of the parent, like the orders with their line items.
+//!
+//! Large results can be streamed with `query_stream_named`, which returns the `RowStream` of
+//! `query_raw`, or with `query_cursor_named`, which declares a server-side cursor for the query
+//! and fetches the rows in batches.
+//!
+//! Consumers that pull the rows gradually can bind the query to a portal in a transaction with
+//! `query_portal_named` of the `TransactionExt` trait, and fetch the rows in pages.
+//!
+//! Many rows are inserted with `insert_batched!`, which repeats a `$for` loop over batches of
+//! the rows, sized to fit the limits of a statement, and executes them in a transaction.
+//! A single row is inserted with `insert_returning!`, which appends `RETURNING id` and returns
+//! the generated key.
+//!
+//! Rows can be exported with `copy_out_named`, which uses `COPY (...) TO STDOUT (FORMAT BINARY)`
+//! with the arguments inlined as escaped literals and returns the raw bytes.
+//! `copy_out_rows_named` decodes the rows of the binary format with the given column types.
+//!
+//! Bulk loading uses `copy_in_csv`, which sends the rows with `COPY ... FROM STDIN (FORMAT CSV)`.
+//! The `CsvCopy` statement takes the table and the columns as validated identifiers and quotes
+//! the options, its `sql` can be used as well to load a CSV file directly.
+//! Bulk upserts can use `copy_merge_named` of the `TransactionExt` trait, which loads the rows
+//! into a temporary `StagingTable` with binary `COPY`, and merges them into the target table
+//! with a statement written with `query!`.
+//!
+//! Existence checks can use `exists_named`, which wraps the query in `SELECT EXISTS(...)` and
+//! returns the boolean directly. Without the feature, `exists_args!` returns the wrapped query.
+//!
+//! For utility statements that don't accept bind parameters, or deployments behind a
+//! transaction-pooling PgBouncer, `simple_query_named` inlines the arguments as escaped
+//! literals and uses the simple query protocol.
+//!
+//! Multi-tenant setups can apply settings like `app.tenant_id` to the current transaction
+//! with `set_local`, which avoids quoting the values by using bind parameters.
+//!
+//! The `transaction!` macro runs a block of executions in a transaction, which is committed
+//! or rolled back depending on the result of the block.
+//! `retry_serializable!` runs the block in a `SERIALIZABLE` transaction instead, which is retried
+//! with a backoff when it fails with a serialization failure or a deadlock.
+//! Independent queries can be executed concurrently with `pipeline!`, which lets
+//! `tokio-postgres` pipeline them over a single connection.
+//!
+//! Jobs can be coordinated with `advisory_lock`, `try_advisory_lock` and `advisory_unlock`.
+//! They take an `AdvisoryLock`, which hashes a name, or a class and a name, into the keys of
+//! the lock at compile time and keeps the names for logging.
+//!
+//! The `listen`, `unlisten` and `notify` helpers take the channel as a validated `Identifier`,
+//! because channel names can't be bind parameters.
+//!
+//! Projects that also use the `sea-query` query builder can enable the `sea-query` feature.
+//! `Query::from_sea_query` builds a statement into a `Query` and its values, so it can be
+//! executed and routed with the same helpers as the queries of the macros.
+//! The other direction is not provided: the parameters of the macros are `ToSql` values,
+//! which can't be read back into `sea_query::Values`.
+//!
+//! Data migrations can be written with `query!` as well. `Migrations` applies them in order,
+//! each in its own transaction, and tracks the applied versions and checksums in a table.
+//!
+//! The `tracing` feature wraps every execution by these helpers in a span, carrying the
+//! statement, the parameter names, the number of rows and the duration.
+//! The field names follow the OpenTelemetry semantic conventions.
+//! The `otel` feature adds the `db.operation` of the statement and the peer attributes
+//! `server.address`, `server.port` and `db.name`, which are installed at startup with
+//! `OtelAttributes`. It also collapses the whitespace of `db.statement`, which can be
+//! truncated to a maximum length.
+//!
+//! The `metrics` feature records the following metrics through the `metrics` facade,
+//! labeled with the hexadecimal `fingerprint` of the query:
+//! - `pg_named_args_queries_total`: the number of executions.
+//! - `pg_named_args_query_errors_total`: the number of failed executions.
+//! - `pg_named_args_query_duration_seconds`: the duration of the executions.
+//!
+//! Without these features, the duration of an execution can be measured with `timed`,
+//! which returns the result together with the elapsed time.
+//! The `Timed` executor wraps any executor and reports the duration of every execution
+//! to an observer, so the latency is captured without changing the call sites.
+//! With the `tracing` feature, `log_slow_queries` is an observer that logs the fingerprint,
+//! the parameter names and the duration of the executions slower than a threshold.
+//!
+//! For custom logging, metrics or auditing, the `Hooked` executor calls the `before_execute`
+//! and `after_execute` hooks of a `Middleware` around every execution, with the query, the
+//! duration and the result. Middlewares are layered by wrapping the executors.
+//!
+//! The `provenance` feature records the call site of every `fragment!`, available as
+//! `Fragment::origin`, and the call sites of the fragments of a query as
+//! `Query::fragment_origins`. Middlewares can log them, and the `tracing` feature adds them to
+//! the span as `pg_named_args.fragments`, so it's clear which variant of a fragment was used
+//! when a composed query misbehaves.
+//!
+//! # Options
+//! Options can be given before the query, separated by commas.
+//! They change how the query is rewritten.
+//!
+//! - `dedent`: strips the common leading indentation and the surrounding blank lines,
+//!   so the query doesn't show up indented in the logs or `pg_stat_activity`.
+//! - `minify`: removes comments and collapses whitespace at compile time,
+//!   which reduces the bytes on the wire and the noise in the server logs.
+//!   Named arguments that only appear in comments are not used.
+//! - `comment` or `comment(key = "value", ..)`: appends a
+//!   [sqlcommenter](https://google.github.io/sqlcommenter/spec/) comment with the given tags and
+//!   the `file` tag containing the call site, so slow queries in `pg_stat_statements` can be
+//!   traced back to the code, e.g. `/*app='checkout',file='src%2Forders.rs%3A123'*/`.
+//! - `readonly`: fails the compilation when the query is not a `SELECT` statement, or when it
+//!   contains data modifying `WITH` queries, `SELECT INTO` or a locking clause. This guarantees
+//!   that queries meant for read-only replicas don't write. Fragments are not checked.
+//! - `strict_insert`: fails the compilation of an `INSERT` statement that doesn't use a `$[...]`
+//!   group for the columns and the `$[..]` group for the values.
+//! - `prepare(name)`: for session-level prepared statements, the macro returns the
+//!   `PREPARE name AS ...` statement and the matching `EXECUTE name($1, ..)` statement
+//!   followed by the arguments, which belong to the `EXECUTE` statement.
+//! - `qmark`: uses `?` placeholders instead of `$1`, for databases like SQLite or MySQL.
+//!   An argument that is used more than once is repeated in the parameters, which are returned
+//!   as a tuple of references for the other drivers, e.g. `(&location, &time, &location)`.
+//!   The Postgres specific syntax, like the modifiers and `$json{...}`, is not supported.
+//!   Without the option, a query with `?` placeholders and no named arguments, like a query
+//!   copied from another driver, fails to compile with an explanation of the named arguments.
+//! - `capture`: the arguments that are not given in `Args` bind the local variables with the
+//!   same name, like the implicit captures of `format!`. The `Args` struct can be left out.
+//! - `template`: the `Query` of `query!` carries the query as it is written, with the named
+//!   arguments, available as `Query::template` for logs and error reports.
+//! - `buffer(expr)`: writes the parameters to a buffer of the caller, like a `Vec` or a
+//!   `SmallVec` given as `&mut params`, and returns them as a slice of the buffer. The buffer is
+//!   cleared first, so a loop that executes the same statement reuses its allocation. The
+//!   arguments need to outlive the buffer, like the fields of the rows of a slice.
+//! - `array`: returns the parameters as an array instead of a slice, like
+//!   `[&(dyn ToSql + Sync); 2]`, so its length is known at compile time and it can be kept on
+//!   the stack or passed to code that is generic over the number of parameters. It is not
+//!   supported with `$for`, optional columns and `$when` or `$match` sections.
+//! - `max_rows(n)`: caps the number of rows of a `SELECT` statement, to protect services from
+//!   unbounded results. A `LIMIT` with a number above the maximum fails to compile, a `LIMIT`
+//!   with a named argument is capped at runtime with `LEAST($limit, n)`, and `LIMIT n` is
+//!   appended to a statement without a `LIMIT`. Fragments are not checked.
+//!
+//! ```
+//! # use pg_named_args::query_args;
+//! #
+//! let location = "sweden";
+//!
+//! let (query, args) = query_args!(
+//!     dedent,
+//!     r"
+//!     SELECT report
+//!     FROM weather_reports
+//!     WHERE location = $location
+//!     ",
+//!     Args { location }
+//! );
+//! assert_eq!(query, "SELECT report\nFROM weather_reports\nWHERE location = $1");
+//! ```
+//!
+//! # Testing
+//! The `test-support` feature contains helpers for the tests of code using this crate.
+//! Code that is generic over the `Executor` can be unit tested with the `MockClient`, which
+//! records the executed queries and returns canned results.
+//! The `assert_query_eq!` macro compares queries while ignoring whitespace and case, so the
+//! tests don't break on formatting-only changes.
+//! The `pg-test` feature adds the `#[pg_test]` attribute for async tests against a database.
+//! The test gets a transaction on the database of `DATABASE_URL`, which is rolled back
+//! afterwards, so the tests don't leave data behind.
+//!
+//! ```ignore
+//! #[pg_test]
+//! async fn insert_report(txn: &tokio_postgres::Transaction<'_>) {
+//!     let location = "sweden";
+//!     let (query, args) = query!(
+//!         "INSERT INTO weather_reports(location) VALUES($location)",
+//!         Args { location }
+//!     );
+//!     txn.execute_named(&query, args).await.unwrap();
+//! }
+//! ```
+//!
+//! For snapshot tests, `Query::snapshot` returns the rewritten query and the names of its
+//! arguments in a stable form, so changes to the queries show up as SQL diffs in code review.
+//! The `Query` also knows the `location` of the macro call, which can name the snapshot.
+//!
+//! With the `serde` and `tokio-postgres` features as well, `explain::assert_plan_snapshot`
+//! compares the shape of the plan of a query with a snapshot file, so a query that no longer
+//! uses an index fails the tests instead of showing up in the latency graphs. The shape leaves
+//! out the costs, the row estimates and the timings. The snapshots are written when they don't
+//! exist, or when the `PG_NAMED_ARGS_UPDATE_PLANS` environment variable is set.
+//!
 //! # IDE Support
 //!
 //! First, the syntax used by this macro is compatible with rustfmt.
@@ -86,29 +666,297 @@
 //! Second, the macro is implemented in a way that is rust-analyzer "friendly".
 //! This means that rust-analyzer knows which arguments are required and can complete them.
 //! Use the code action "Fill struct fields" or ask rust-analyzer to complete a field name.
+//! This keeps working while the query has a syntax error, like an unclosed `$[` group,
+//! because the arguments after the error are still collected.
+//! Go to definition on a field of the arguments struct jumps to where the argument is used in the query.
+//! Hovering an argument shows the line of the query where it is used, like `used in: WHERE id = $id`.
 
 extern crate self as pg_named_args;
 
-pub use pg_named_args_macros::{fragment, query_args};
+use std::borrow::Cow;
+
+mod advisory;
+#[cfg(feature = "test-support")]
+mod assert;
+#[cfg(feature = "postgres")]
+mod blocking;
+mod clause;
+#[cfg(feature = "tokio-postgres")]
+mod client;
+mod columns;
+mod csv;
+#[cfg(feature = "tokio-postgres")]
+mod error;
+mod executor;
+#[cfg(feature = "serde")]
+pub mod explain;
+#[cfg(not(pg_named_args_literal_sql))]
+mod fragment;
+mod hint;
+mod identifier;
+#[cfg(feature = "tokio-postgres")]
+mod inline;
+#[cfg(feature = "tokio-postgres")]
+mod insert;
+mod kinds;
+mod like;
+mod list;
+mod literal;
+mod lock;
+mod middleware;
+#[cfg(feature = "tokio-postgres")]
+mod migrate;
+#[cfg(feature = "test-support")]
+mod mock;
+mod nest;
+mod origin;
+#[cfg(feature = "otel")]
+mod otel;
+mod param;
+#[cfg(feature = "pg-test")]
+mod pg_test;
+mod pretty;
+mod query;
+mod redact;
+#[cfg(feature = "tokio-postgres")]
+mod retry;
+mod router;
+#[cfg(feature = "sea-query")]
+mod sea;
+#[cfg(feature = "tokio-postgres")]
+mod settings;
+#[cfg(feature = "test-support")]
+mod snapshot;
+mod staging;
+mod timing;
+mod transpose;
+
+pub use advisory::AdvisoryLock;
+#[cfg(feature = "postgres")]
+pub use blocking::{block_on, Blocking};
+#[cfg(feature = "tokio-postgres")]
+pub use client::{ClientExt, ExecutorExt, NamedPortal, TransactionExt};
+pub use csv::{CsvCopy, CsvRecord, CsvRow};
+#[cfg(feature = "tokio-postgres")]
+pub use error::Error;
+pub use executor::Executor;
+#[cfg(not(pg_named_args_literal_sql))]
+pub use fragment::InvalidFragment;
+pub use hint::Hint;
+pub use identifier::{Identifier, InvalidIdentifier, SchemaName};
+#[cfg(feature = "tokio-postgres")]
+pub use inline::InlineError;
+pub use kinds::{IdentFragment, OrderByFragment, WhereFragment};
+pub use like::escape_like;
+pub use lock::LockMode;
+pub use middleware::{Hooked, Middleware};
+#[cfg(feature = "tokio-postgres")]
+pub use migrate::Migrations;
+#[cfg(feature = "test-support")]
+pub use mock::{MockClient, MockError};
+pub use nest::nest;
+#[cfg(feature = "otel")]
+pub use otel::OtelAttributes;
+#[cfg(feature = "pg-test")]
+pub use pg_named_args_macros::pg_test;
+pub use pg_named_args_macros::{
+    batch_args, exists_args, fragment, query, query_args, upsert_args, values_args,
+};
+pub use postgres_types;
+pub use query::{fingerprint, Query, StatementKind};
+pub use redact::{redact, Redacted};
+#[cfg(feature = "tokio-postgres")]
+pub use retry::RetryableError;
+pub use router::Router;
+#[cfg(feature = "tokio-postgres")]
+pub use settings::Settings;
+#[cfg(feature = "test-support")]
+pub use snapshot::Snapshot;
+pub use staging::StagingTable;
+#[cfg(feature = "tracing")]
+pub use timing::log_slow_queries;
+pub use timing::{timed, Timed};
+pub use transpose::Transpose;
 
-#[derive(Clone, Copy, Default)]
-pub struct Fragment(&'static str);
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "test-support")]
+    pub use crate::assert::assert_query_eq;
+    pub use crate::clause::conditions;
+    pub use crate::columns::{columns, values};
+    #[cfg(feature = "tokio-postgres")]
+    pub use crate::insert::batch_size;
+    pub use crate::list::list;
+    pub use crate::literal::{Literal, LITERAL};
+    pub use crate::origin::record;
+    pub use crate::param::Param;
+    #[cfg(feature = "pg-test")]
+    pub use crate::pg_test::pg_test;
+    #[cfg(feature = "tokio-postgres")]
+    pub use crate::retry::{backoff, MAX_RETRIES};
+    #[cfg(feature = "tokio-postgres")]
+    pub use futures_util::try_join;
+    #[cfg(feature = "tokio-postgres")]
+    pub use tokio_postgres;
+}
+
+#[derive(Clone, Default)]
+pub struct Fragment {
+    sql: Cow<'static, str>,
+    #[cfg(feature = "provenance")]
+    origin: Option<&'static str>,
+}
 
 impl Fragment {
-    pub fn get(self) -> &'static str {
-        self.0
+    pub fn get(&self) -> &str {
+        &self.sql
+    }
+
+    /// The call site of the [fragment!] macro that created the fragment, like
+    /// `src/orders.rs:12 (shop::orders)`, with the file, the line and the module.
+    ///
+    /// This is only recorded with the `provenance` feature, and is `None` for the fragments
+    /// that are not created by the macro.
+    pub fn origin(&self) -> Option<&'static str> {
+        #[cfg(feature = "provenance")]
+        return self.origin;
+        #[cfg(not(feature = "provenance"))]
+        None
     }
 
     #[doc(hidden)]
     /// This is the constructor used by the [fragment!] macro.
     /// It is not intended to be used manually.
-    pub const fn new_unchecked(sql: &'static str) -> Self {
-        Self(sql)
+    pub const fn new_unchecked(sql: &'static str, _: __private::Literal) -> Self {
+        Self::from_cow(Cow::Borrowed(sql))
+    }
+
+    #[doc(hidden)]
+    /// This is used by the [const_fragment!] macro to validate the constant, with the same
+    /// rules as the literals of the [fragment!] macro.
+    pub const fn is_valid_literal(sql: &str) -> bool {
+        let bytes = sql.as_bytes();
+        let mut idx = 0;
+        while idx < bytes.len() {
+            if bytes[idx] == b'$' {
+                return false;
+            }
+            idx += 1;
+        }
+        true
+    }
+
+    const fn from_cow(sql: Cow<'static, str>) -> Self {
+        Self {
+            sql,
+            #[cfg(feature = "provenance")]
+            origin: None,
+        }
+    }
+
+    #[doc(hidden)]
+    /// This is used by the [fragment!] and [const_fragment!] macros to record their call site.
+    /// It is not intended to be used manually.
+    #[cfg_attr(not(feature = "provenance"), allow(unused_mut))]
+    pub const fn with_origin(mut self, origin: &'static str) -> Self {
+        #[cfg(feature = "provenance")]
+        {
+            self.origin = Some(origin);
+        }
+        #[cfg(not(feature = "provenance"))]
+        let _ = origin;
+        self
+    }
+
+    /// Creates a fragment from sql that is only known at runtime, like a clause from trusted
+    /// configuration that is read at startup.
+    ///
+    /// The sql can only contain identifiers, qualified and quoted names, numbers, commas,
+    /// parentheses and comparisons, so it can't contain literals, comments, parameters or
+    /// other statements.
+    ///
+    /// ```
+    /// # use pg_named_args::Fragment;
+    /// let config: &'static str = "reports.created_at DESC, id";
+    /// assert_eq!(Fragment::new_checked(config).unwrap().get(), config);
+    /// assert!(Fragment::new_checked("id; DROP TABLE reports").is_err());
+    /// assert!(Fragment::new_checked("name = 'admin'").is_err());
+    /// ```
+    ///
+    /// This is not available with `--cfg pg_named_args_literal_sql`.
+    #[cfg(not(pg_named_args_literal_sql))]
+    pub fn new_checked(sql: &'static str) -> Result<Self, InvalidFragment> {
+        fragment::check(sql)?;
+        Ok(Self::from_cow(Cow::Borrowed(sql)))
+    }
+
+    #[doc(hidden)]
+    /// This is the constructor used by the [fragment!] macro for interpolated fragments.
+    /// Every `{}` of the template is replaced by the next fragment, and `{{` and `}}` are
+    /// unescaped, so the sql only comes from the literal and the fragments.
+    /// It is not intended to be used manually.
+    pub fn interpolated(
+        template: &'static str,
+        fragments: &[&Fragment],
+        _: __private::Literal,
+    ) -> Self {
+        let mut sql = String::new();
+        let mut fragments = fragments.iter();
+        let mut rest = template;
+        while let Some(pos) = rest.find(['{', '}']) {
+            sql.push_str(&rest[..pos]);
+            let brace = &rest[pos..pos + 1];
+            rest = &rest[pos + 1..];
+            match rest.strip_prefix('}').filter(|_| brace == "{") {
+                Some(after) => {
+                    let fragment = fragments.next().expect("a fragment for every `{}`");
+                    sql.push_str(fragment.get());
+                    rest = after;
+                }
+                None => {
+                    sql.push_str(brace);
+                    rest = rest.strip_prefix(brace).unwrap_or(rest);
+                }
+            }
+        }
+        sql.push_str(rest);
+        Self::from_cow(Cow::Owned(sql))
+    }
+
+    #[doc(hidden)]
+    /// This is used by the macros for a fragment at the end of a `$[...]` group, which is
+    /// appended with a comma when it isn't empty.
+    /// It is not intended to be used manually.
+    pub fn items(&self) -> Cow<'_, str> {
+        match self.sql.trim() {
+            "" => Cow::Borrowed(""),
+            items => Cow::Owned(format!(", {items}")),
+        }
     }
 }
 
+#[cfg(not(pg_named_args_literal_sql))]
 #[test]
 fn ui() {
     let t = trybuild::TestCases::new();
     t.compile_fail("tests/ui/*.rs");
 }
+
+/// The constructors that take runtime strings, which can't be used with
+/// `RUSTFLAGS="--cfg pg_named_args_literal_sql"`.
+#[cfg(pg_named_args_literal_sql)]
+#[test]
+fn ui_literal_sql() {
+    // trybuild drops `RUSTFLAGS`, the encoded flags replace its own flags instead
+    let flags = [
+        "--cfg",
+        "pg_named_args_literal_sql",
+        "--cfg",
+        "trybuild",
+        "-A",
+        "dead_code",
+    ];
+    std::env::set_var("CARGO_ENCODED_RUSTFLAGS", flags.join("\x1f"));
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui_literal_sql/*.rs");
+}
@@ -0,0 +1,21 @@
+/// Escapes the pattern characters `%` and `_` of a `LIKE` pattern, and the escape character `\`.
+///
+/// This is useful to build patterns in Rust, the `~contains`, `~starts_with` and `~ends_with`
+/// placeholder modifiers escape the value in the query instead.
+///
+/// ```
+/// # use pg_named_args::{escape_like, query_args};
+/// let pattern = format!("{}%", escape_like("100%_"));
+/// assert_eq!(pattern, r"100\%\_%");
+/// let (query, params) = query_args!("SELECT * FROM products WHERE name LIKE $pattern", Args { pattern });
+/// ```
+pub fn escape_like(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for x in value.chars() {
+        if matches!(x, '\\' | '%' | '_') {
+            out.push('\\');
+        }
+        out.push(x);
+    }
+    out
+}
@@ -0,0 +1,12 @@
+/// The list of a `$list~in` membership test, which is the parameter for a short list and a
+/// subquery that unnests the parameter for a list with more than `unnest_above` items.
+///
+/// The planner estimates `= ANY($1)` from the elements of the array, which fits short lists,
+/// while `= ANY(SELECT unnest($1))` is planned as a join, which fits long lists.
+#[doc(hidden)]
+pub fn list<T>(list: &impl AsRef<[T]>, param: &'static str, unnest_above: usize) -> String {
+    match list.as_ref().len() > unnest_above {
+        true => format!("SELECT unnest({param})"),
+        false => param.to_owned(),
+    }
+}
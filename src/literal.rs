@@ -0,0 +1,11 @@
+/// A token that the macros pass to the constructors that take sql or identifiers from a
+/// literal, like `Fragment::new_unchecked` and `Identifier::from_literal`.
+///
+/// The token is only reachable through the hidden `__private` module, so code that calls
+/// these constructors with a runtime string has to name that module, which an audit of a
+/// build with `--cfg pg_named_args_literal_sql` can search for.
+#[derive(Clone, Copy, Debug)]
+pub struct Literal(());
+
+/// The [Literal] token used by the macros.
+pub const LITERAL: Literal = Literal(());
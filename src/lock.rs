@@ -0,0 +1,113 @@
+use crate::Fragment;
+
+/// A row locking clause, like `FOR UPDATE SKIP LOCKED`, which can be used as a fragment.
+///
+/// This allows queries to vary their locking clause at runtime, for example to poll a job
+/// queue without waiting for the jobs that are locked by other workers.
+/// `None` converts to an empty fragment, which doesn't lock.
+///
+/// ```
+/// # use pg_named_args::{query_args, LockMode};
+/// let lock = LockMode::update().skip_locked();
+/// let (query, _) = query_args!(
+///     "SELECT id FROM jobs ORDER BY id LIMIT 1 ${lock}",
+///     Args {},
+///     Sql { lock: lock.into() }
+/// );
+/// assert_eq!(query, "SELECT id FROM jobs ORDER BY id LIMIT 1 FOR UPDATE SKIP LOCKED");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LockMode {
+    strength: Strength,
+    wait: Waiting,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Strength {
+    Update,
+    NoKeyUpdate,
+    Share,
+    KeyShare,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Waiting {
+    Block,
+    NoWait,
+    SkipLocked,
+}
+
+impl LockMode {
+    /// `FOR UPDATE`, which blocks all other locks on the rows.
+    pub const fn update() -> Self {
+        Self::new(Strength::Update)
+    }
+
+    /// `FOR NO KEY UPDATE`, which allows `FOR KEY SHARE` locks on the rows.
+    pub const fn no_key_update() -> Self {
+        Self::new(Strength::NoKeyUpdate)
+    }
+
+    /// `FOR SHARE`, which blocks updates of the rows.
+    pub const fn share() -> Self {
+        Self::new(Strength::Share)
+    }
+
+    /// `FOR KEY SHARE`, which blocks deletes and updates of the keys of the rows.
+    pub const fn key_share() -> Self {
+        Self::new(Strength::KeyShare)
+    }
+
+    /// Adds `NOWAIT`, which returns an error instead of waiting for locked rows.
+    pub const fn nowait(self) -> Self {
+        Self {
+            wait: Waiting::NoWait,
+            ..self
+        }
+    }
+
+    /// Adds `SKIP LOCKED`, which skips the locked rows instead of waiting for them.
+    pub const fn skip_locked(self) -> Self {
+        Self {
+            wait: Waiting::SkipLocked,
+            ..self
+        }
+    }
+
+    /// The locking clause.
+    pub const fn sql(self) -> &'static str {
+        match (self.strength, self.wait) {
+            (Strength::Update, Waiting::Block) => "FOR UPDATE",
+            (Strength::Update, Waiting::NoWait) => "FOR UPDATE NOWAIT",
+            (Strength::Update, Waiting::SkipLocked) => "FOR UPDATE SKIP LOCKED",
+            (Strength::NoKeyUpdate, Waiting::Block) => "FOR NO KEY UPDATE",
+            (Strength::NoKeyUpdate, Waiting::NoWait) => "FOR NO KEY UPDATE NOWAIT",
+            (Strength::NoKeyUpdate, Waiting::SkipLocked) => "FOR NO KEY UPDATE SKIP LOCKED",
+            (Strength::Share, Waiting::Block) => "FOR SHARE",
+            (Strength::Share, Waiting::NoWait) => "FOR SHARE NOWAIT",
+            (Strength::Share, Waiting::SkipLocked) => "FOR SHARE SKIP LOCKED",
+            (Strength::KeyShare, Waiting::Block) => "FOR KEY SHARE",
+            (Strength::KeyShare, Waiting::NoWait) => "FOR KEY SHARE NOWAIT",
+            (Strength::KeyShare, Waiting::SkipLocked) => "FOR KEY SHARE SKIP LOCKED",
+        }
+    }
+
+    const fn new(strength: Strength) -> Self {
+        Self {
+            strength,
+            wait: Waiting::Block,
+        }
+    }
+}
+
+impl From<LockMode> for Fragment {
+    fn from(lock: LockMode) -> Self {
+        Fragment::new_unchecked(lock.sql(), crate::__private::LITERAL)
+    }
+}
+
+impl From<Option<LockMode>> for Fragment {
+    fn from(lock: Option<LockMode>) -> Self {
+        lock.map(Fragment::from).unwrap_or_default()
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use postgres_types::ToSql;
+
+use crate::{Executor, Query};
+
+/// Hooks that are called around every execution of a [Hooked] executor, for logging,
+/// metrics, tracing or auditing.
+///
+/// Both methods do nothing by default. Middlewares are layered by wrapping a [Hooked]
+/// executor in another one, the outer middleware is called first.
+///
+/// ```
+/// # use std::{fmt::Display, time::Duration};
+/// # use pg_named_args::{Middleware, Query};
+/// struct Audit;
+///
+/// impl Middleware for Audit {
+///     fn after_execute(&self, query: &Query, _: Duration, result: Result<u64, &dyn Display>) {
+///         match result {
+///             Ok(rows) => println!("{:016x} changed {rows} rows", query.fingerprint()),
+///             Err(err) => println!("{:016x} failed: {err}", query.fingerprint()),
+///         }
+///     }
+/// }
+/// ```
+pub trait Middleware {
+    /// Called before the query is sent.
+    fn before_execute(&self, query: &Query) {
+        let _ = query;
+    }
+
+    /// Called after the execution with its duration, and the number of rows returned or
+    /// modified, or the error.
+    fn after_execute(
+        &self,
+        query: &Query,
+        elapsed: Duration,
+        result: Result<u64, &dyn fmt::Display>,
+    ) {
+        let _ = (query, elapsed, result);
+    }
+}
+
+impl<M: Middleware + ?Sized> Middleware for std::sync::Arc<M> {
+    fn before_execute(&self, query: &Query) {
+        (**self).before_execute(query);
+    }
+
+    fn after_execute(
+        &self,
+        query: &Query,
+        elapsed: Duration,
+        result: Result<u64, &dyn fmt::Display>,
+    ) {
+        (**self).after_execute(query, elapsed, result);
+    }
+}
+
+/// An [Executor] that calls the [Middleware] around every execution.
+///
+/// ```no_run
+/// # use pg_named_args::{query, Executor, Hooked, Middleware};
+/// # struct Audit;
+/// # impl Middleware for Audit {}
+/// # async fn example<E: Executor + Sync>(client: E) -> Result<(), E::Error>
+/// # where
+/// #     E::Error: std::fmt::Display,
+/// # {
+/// let client = Hooked::new(client, Audit);
+///
+/// let name = "Fred";
+/// let (query, params) = query!("DELETE FROM flintstone WHERE name = $name", Args { name });
+/// client.execute_named(&query, params).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Hooked<E, M> {
+    inner: E,
+    middleware: M,
+}
+
+impl<E, M: Middleware> Hooked<E, M> {
+    /// Wraps the executor, calling the middleware around every execution.
+    pub fn new(inner: E, middleware: M) -> Self {
+        Self { inner, middleware }
+    }
+
+    /// The wrapped executor.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// The middleware called around the executions.
+    pub fn middleware(&self) -> &M {
+        &self.middleware
+    }
+}
+
+impl<E, M> Executor for Hooked<E, M>
+where
+    E: Executor + Sync,
+    E::Error: fmt::Display,
+    M: Middleware + Sync,
+{
+    type Row = E::Row;
+    type Error = E::Error;
+
+    async fn query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<E::Row>, E::Error> {
+        self.middleware.before_execute(query);
+        let start = Instant::now();
+        let res = self.inner.query_named(query, params).await;
+        let result = match &res {
+            Ok(rows) => Ok(rows.len() as u64),
+            Err(err) => Err(err as &dyn fmt::Display),
+        };
+        self.middleware
+            .after_execute(query, start.elapsed(), result);
+        res
+    }
+
+    async fn execute_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, E::Error> {
+        self.middleware.before_execute(query);
+        let start = Instant::now();
+        let res = self.inner.execute_named(query, params).await;
+        let result = match &res {
+            Ok(rows) => Ok(*rows),
+            Err(err) => Err(err as &dyn fmt::Display),
+        };
+        self.middleware
+            .after_execute(query, start.elapsed(), result);
+        res
+    }
+}
@@ -0,0 +1,141 @@
+use postgres_types::ToSql;
+use tokio_postgres::Client;
+
+use crate::{fingerprint, Error, Identifier, InlineError, Query};
+
+/// Data migrations that are applied in order and tracked in a table.
+///
+/// The statements of a migration are written with the [query!](crate::query!) macro, the
+/// arguments are inlined as escaped literals when the migration is added, see [Query::inline].
+/// Every migration is applied in its own transaction, together with its row in the table.
+/// The checksum of the statements is recorded as well, a migration that is changed after it
+/// was applied is reported as [Error::MigrationChanged].
+///
+/// ```no_run
+/// # use pg_named_args::{query, Migrations};
+/// # async fn example(client: &mut tokio_postgres::Client) -> Result<(), pg_named_args::Error> {
+/// let location = "unknown";
+/// let migrations = Migrations::new()
+///     .add(
+///         1,
+///         "fill the missing locations",
+///         [query!(
+///             "UPDATE weather_reports SET location = $location WHERE location IS NULL",
+///             Args { location }
+///         )],
+///     )?
+///     .add(2, "drop the old reports", [query!("DELETE FROM weather_reports_old", Args {})])?;
+///
+/// let applied = migrations.run(client).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Migrations {
+    table: Identifier,
+    migrations: Vec<Migration>,
+}
+
+#[derive(Clone, Debug)]
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: String,
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Self {
+            table: Identifier::new("pg_named_args_migrations").unwrap(),
+            migrations: vec![],
+        }
+    }
+}
+
+impl Migrations {
+    /// Creates the migrations, tracked in the `pg_named_args_migrations` table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the table the applied migrations are tracked in, it is created when it doesn't
+    /// exist yet.
+    pub fn table(mut self, table: Identifier) -> Self {
+        self.table = table;
+        self
+    }
+
+    /// Adds a migration with the statements, which is applied after the earlier migrations.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the version is not higher than the version of the previous migration.
+    pub fn add<'a, P>(
+        mut self,
+        version: i64,
+        name: &'static str,
+        statements: impl IntoIterator<Item = (Query, P)>,
+    ) -> Result<Self, InlineError>
+    where
+        P: AsRef<[&'a (dyn ToSql + Sync)]>,
+    {
+        if let Some(last) = self.migrations.last() {
+            assert!(
+                version > last.version,
+                "the migration {version} should come after {}",
+                last.version
+            );
+        }
+        let statements = statements
+            .into_iter()
+            .map(|(query, params)| query.inline(params.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.migrations.push(Migration {
+            version,
+            name,
+            sql: statements.join(";\n"),
+        });
+        Ok(self)
+    }
+
+    /// Applies the migrations that are not applied yet, returning their versions.
+    ///
+    /// The table is locked while a migration is applied, so concurrent runs apply every
+    /// migration only once.
+    pub async fn run(&self, client: &mut Client) -> Result<Vec<i64>, Error> {
+        let table = &self.table;
+        client
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {table} (
+                    version bigint PRIMARY KEY,
+                    name text NOT NULL,
+                    checksum bigint NOT NULL,
+                    applied_at timestamptz NOT NULL DEFAULT now()
+                )"
+            ))
+            .await?;
+
+        let select = format!("SELECT checksum FROM {table} WHERE version = $1");
+        let insert = format!("INSERT INTO {table} (version, name, checksum) VALUES ($1, $2, $3)");
+        let mut applied = vec![];
+        for migration in &self.migrations {
+            let checksum = fingerprint(&migration.sql) as i64;
+
+            let txn = client.transaction().await?;
+            txn.batch_execute(&format!("LOCK TABLE {table} IN EXCLUSIVE MODE"))
+                .await?;
+            if let Some(row) = txn.query_opt(&select, &[&migration.version]).await? {
+                if row.try_get::<_, i64>(0)? != checksum {
+                    return Err(Error::MigrationChanged(migration.version));
+                }
+                continue;
+            }
+            txn.batch_execute(&migration.sql).await?;
+            txn.execute(&insert, &[&migration.version, &migration.name, &checksum])
+                .await?;
+            txn.commit().await?;
+            applied.push(migration.version);
+        }
+        Ok(applied)
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::VecDeque, fmt, sync::Mutex};
+
+use postgres_types::ToSql;
+
+use crate::{Executor, Query};
+
+/// An [Executor] for unit tests, which records the executed queries and returns canned results.
+///
+/// Every execution takes the next canned result, or an empty result when there is none.
+/// The `execute_named` method returns the number of canned rows.
+///
+/// ```
+/// # use pg_named_args::{query, Executor, MockClient};
+/// # block_on(async {
+/// let client = MockClient::new();
+/// client.push_rows(vec!["Fred"]);
+///
+/// let name = "Fred";
+/// let (query, params) = query!("SELECT name FROM flintstone WHERE name = $name", Args { name });
+/// assert_eq!(client.query_named(&query, params).await.unwrap(), ["Fred"]);
+/// assert_eq!(
+///     client.calls(),
+///     [(
+///         "SELECT name FROM flintstone WHERE name = $1".to_owned(),
+///         vec!["\"Fred\"".to_owned()]
+///     )]
+/// );
+/// # });
+/// # fn block_on(f: impl std::future::Future) {
+/// #     let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+/// #     let mut f = std::pin::pin!(f);
+/// #     assert!(f.as_mut().poll(&mut cx).is_ready());
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MockClient<R = ()> {
+    calls: Mutex<Vec<(String, Vec<String>)>>,
+    results: Mutex<VecDeque<Result<Vec<R>, MockError>>>,
+}
+
+impl<R> Default for MockClient<R> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::default(),
+            results: Mutex::default(),
+        }
+    }
+}
+
+impl<R> MockClient<R> {
+    /// Creates a client without canned results.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the rows as the result of the next execution.
+    pub fn push_rows(&self, rows: Vec<R>) {
+        self.results.lock().unwrap().push_back(Ok(rows));
+    }
+
+    /// Adds an error with the message as the result of the next execution.
+    pub fn push_error(&self, message: impl Into<String>) {
+        let err = MockError(message.into());
+        self.results.lock().unwrap().push_back(Err(err));
+    }
+
+    /// The sql and the debug representation of the parameters of every execution so far.
+    pub fn calls(&self) -> Vec<(String, Vec<String>)> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn execute(&self, query: &Query, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<R>, MockError> {
+        let params = params.iter().map(|param| format!("{param:?}")).collect();
+        self.calls
+            .lock()
+            .unwrap()
+            .push((query.sql().to_owned(), params));
+        self.results
+            .lock()
+            .unwrap()
+            .pop_front()
+            .unwrap_or_else(|| Ok(vec![]))
+    }
+}
+
+impl<R: Send> Executor for MockClient<R> {
+    type Row = R;
+    type Error = MockError;
+
+    async fn query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<R>, MockError> {
+        self.execute(query, params)
+    }
+
+    async fn execute_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, MockError> {
+        Ok(self.execute(query, params)?.len() as u64)
+    }
+}
+
+/// The canned error of a [MockClient].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockError(pub String);
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for MockError {}
@@ -0,0 +1,54 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// Folds the rows of a join into the parents with their children, like the orders with their
+/// line items.
+///
+/// The rows are grouped by the key of the parent, the parent is mapped from the first row with
+/// the key. A row without a child, like a parent without children in a `LEFT JOIN`, maps the
+/// child to `None`. The parents keep the order of their first rows, and the children the
+/// order of the rows, so the rows don't need to be sorted by the key.
+///
+/// ```
+/// # use pg_named_args::nest;
+/// #[derive(Debug, PartialEq)]
+/// struct Order {
+///     id: i32,
+///     items: Vec<&'static str>,
+/// }
+///
+/// // SELECT orders.id, items.name FROM orders LEFT JOIN items ON items.order_id = orders.id
+/// let rows = [(1, Some("anvil")), (2, None), (1, Some("rope"))];
+/// let orders: Vec<Order> = nest(rows, |row| row.0, |row| row.0, |row| row.1)
+///     .into_iter()
+///     .map(|(id, items)| Order { id, items })
+///     .collect();
+/// assert_eq!(
+///     orders,
+///     [
+///         Order { id: 1, items: vec!["anvil", "rope"] },
+///         Order { id: 2, items: vec![] },
+///     ]
+/// );
+/// ```
+pub fn nest<R, K, P, C>(
+    rows: impl IntoIterator<Item = R>,
+    mut key: impl FnMut(&R) -> K,
+    mut parent: impl FnMut(&R) -> P,
+    mut child: impl FnMut(&R) -> Option<C>,
+) -> Vec<(P, Vec<C>)>
+where
+    K: Eq + Hash,
+{
+    let mut indices = HashMap::new();
+    let mut parents: Vec<(P, Vec<C>)> = vec![];
+    for row in rows {
+        let index = *indices.entry(key(&row)).or_insert_with(|| {
+            parents.push((parent(&row), vec![]));
+            parents.len() - 1
+        });
+        if let Some(child) = child(&row) {
+            parents[index].1.push(child);
+        }
+    }
+    parents
+}
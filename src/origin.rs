@@ -0,0 +1,44 @@
+use crate::{Fragment, IdentFragment, OrderByFragment, SchemaName, WhereFragment};
+
+/// The values of the `Sql` struct, which can have the call site of the fragment.
+#[doc(hidden)]
+pub trait Origin {
+    fn origin(&self) -> Option<&'static str>;
+}
+
+impl Origin for Fragment {
+    fn origin(&self) -> Option<&'static str> {
+        Fragment::origin(self)
+    }
+}
+
+impl Origin for OrderByFragment {
+    fn origin(&self) -> Option<&'static str> {
+        OrderByFragment::origin(self)
+    }
+}
+
+impl Origin for WhereFragment {
+    fn origin(&self) -> Option<&'static str> {
+        WhereFragment::origin(self)
+    }
+}
+
+impl Origin for IdentFragment {
+    fn origin(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+impl Origin for SchemaName {
+    fn origin(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+/// Records the call site of a fragment of a query, for [Query::fragment_origins](crate::Query::fragment_origins).
+#[doc(hidden)]
+pub fn record<'a, T: Origin>(value: &'a T, origins: &mut Vec<&'static str>) -> &'a T {
+    origins.extend(value.origin());
+    value
+}
@@ -0,0 +1,144 @@
+use std::{borrow::Cow, sync::OnceLock};
+
+use crate::{Query, StatementKind};
+
+static ATTRIBUTES: OnceLock<OtelAttributes> = OnceLock::new();
+
+/// The attributes of the spans of the `otel` feature that are the same for every execution,
+/// following the OpenTelemetry semantic conventions for database clients.
+///
+/// The server is not known to the executions, so the peer attributes are only recorded
+/// when they are installed once at startup.
+///
+/// ```
+/// # use pg_named_args::OtelAttributes;
+/// let attributes = OtelAttributes::new()
+///     .server("db.internal", 5432)
+///     .database("weather")
+///     .max_statement_len(2048);
+/// assert_eq!(
+///     attributes.statement("SELECT *\n    FROM weather_reports\n    WHERE location = $1"),
+///     "SELECT * FROM weather_reports WHERE location = $1"
+/// );
+/// attributes.install().unwrap();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct OtelAttributes {
+    server: Option<(String, u16)>,
+    database: Option<String>,
+    max_statement_len: Option<usize>,
+}
+
+impl OtelAttributes {
+    /// Creates the attributes without the peer attributes, recording the full statement.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `server.address` and `server.port`.
+    pub fn server(mut self, address: impl Into<String>, port: u16) -> Self {
+        self.server = Some((address.into(), port));
+        self
+    }
+
+    /// Sets `db.name`.
+    pub fn database(mut self, name: impl Into<String>) -> Self {
+        self.database = Some(name.into());
+        self
+    }
+
+    /// Truncates `db.statement` to the number of bytes, marking the truncation with `...`.
+    pub fn max_statement_len(mut self, len: usize) -> Self {
+        self.max_statement_len = Some(len);
+        self
+    }
+
+    /// Uses the attributes for all executions, this can be done only once.
+    ///
+    /// Returns the attributes back when they were already installed.
+    pub fn install(self) -> Result<(), Self> {
+        ATTRIBUTES.set(self)
+    }
+
+    /// The `db.statement` of the query, with the whitespace outside of the quotes collapsed
+    /// and truncated to the maximum length.
+    pub fn statement<'a>(&self, sql: &'a str) -> Cow<'a, str> {
+        let mut statement = collapse_whitespace(sql);
+        if let Some(len) = self.max_statement_len {
+            if statement.len() > len {
+                let end = (0..=len).rev().find(|&i| statement.is_char_boundary(i));
+                let mut truncated = statement[..end.unwrap_or(0)].to_owned();
+                truncated.push_str("...");
+                statement = Cow::Owned(truncated);
+            }
+        }
+        statement
+    }
+
+    pub(crate) fn server_address(&self) -> Option<&str> {
+        self.server.as_ref().map(|(address, _)| address.as_str())
+    }
+
+    pub(crate) fn server_port(&self) -> Option<u16> {
+        self.server.as_ref().map(|&(_, port)| port)
+    }
+
+    pub(crate) fn database_name(&self) -> Option<&str> {
+        self.database.as_deref()
+    }
+}
+
+/// The installed attributes, or the defaults.
+pub(crate) fn attributes() -> &'static OtelAttributes {
+    static DEFAULT: OtelAttributes = OtelAttributes {
+        server: None,
+        database: None,
+        max_statement_len: None,
+    };
+    ATTRIBUTES.get().unwrap_or(&DEFAULT)
+}
+
+/// The `db.operation` of the query, the first keyword for the statements other than
+/// `SELECT`, `INSERT`, `UPDATE` and `DELETE`.
+pub(crate) fn operation(query: &Query) -> Cow<'static, str> {
+    match query.kind() {
+        StatementKind::Select => "SELECT".into(),
+        StatementKind::Insert => "INSERT".into(),
+        StatementKind::Update => "UPDATE".into(),
+        StatementKind::Delete => "DELETE".into(),
+        StatementKind::Other => {
+            let sql = query.sql().trim_start();
+            let end = sql
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(sql.len());
+            sql[..end].to_ascii_uppercase().into()
+        }
+    }
+}
+
+fn collapse_whitespace(sql: &str) -> Cow<'_, str> {
+    let sql = sql.trim();
+    let mut out = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut space = false;
+    for c in sql.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c.is_whitespace() => {
+                space = true;
+                continue;
+            }
+            None => {}
+        }
+        if std::mem::take(&mut space) {
+            out.push(' ');
+        }
+        out.push(c);
+    }
+    match out == sql {
+        true => Cow::Borrowed(sql),
+        false => Cow::Owned(out),
+    }
+}
@@ -0,0 +1,17 @@
+use postgres_types::ToSql;
+
+/// A value that can be bound as a parameter, which is every `ToSql + Sync` value.
+///
+/// The macros bind the arguments through this trait, so an argument that can't be bound gets
+/// an error that explains the common fixes.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "the argument of type `{Self}` can't be bound as a parameter of the query",
+    label = "this argument doesn't implement `ToSql`",
+    note = "a value that can be serialized can be bound as `jsonb` with `postgres_types::Json`",
+    note = "arrays are bound from a slice or a `Vec`, and `NULL` from an `Option`",
+    note = "enums and composite types can derive `ToSql` with the `derive` feature"
+)]
+pub trait Param: ToSql + Sync {}
+
+impl<T: ToSql + Sync> Param for T {}
@@ -0,0 +1,33 @@
+use std::{future::Future, pin::Pin};
+
+use tokio_postgres::{NoTls, Transaction};
+
+/// Runs the test of the [pg_test](crate::pg_test) attribute with a transaction.
+#[doc(hidden)]
+pub fn pg_test<T>(
+    test: impl for<'a> FnOnce(&'a Transaction<'a>) -> Pin<Box<dyn Future<Output = T> + 'a>>,
+) -> T {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build the runtime");
+
+    runtime.block_on(async {
+        let url = std::env::var("DATABASE_URL").expect("`DATABASE_URL` should be set");
+        let (mut client, connection) = tokio_postgres::connect(&url, NoTls)
+            .await
+            .expect("failed to connect to the database");
+        tokio::spawn(connection);
+
+        let txn = client
+            .transaction()
+            .await
+            .expect("failed to start the transaction");
+        let res = test(&txn).await;
+        // when the test panics, the transaction is rolled back by closing the connection.
+        txn.rollback()
+            .await
+            .expect("failed to roll back the transaction");
+        res
+    })
+}
@@ -0,0 +1,203 @@
+/// The keywords that are written in upper case.
+const KEYWORDS: &[&str] = &[
+    "all",
+    "and",
+    "any",
+    "as",
+    "asc",
+    "between",
+    "by",
+    "case",
+    "cast",
+    "conflict",
+    "cross",
+    "default",
+    "delete",
+    "desc",
+    "distinct",
+    "do",
+    "else",
+    "end",
+    "except",
+    "exists",
+    "false",
+    "from",
+    "full",
+    "group",
+    "having",
+    "ilike",
+    "in",
+    "inner",
+    "insert",
+    "intersect",
+    "into",
+    "is",
+    "join",
+    "lateral",
+    "left",
+    "like",
+    "limit",
+    "natural",
+    "not",
+    "null",
+    "nulls",
+    "offset",
+    "on",
+    "or",
+    "order",
+    "outer",
+    "recursive",
+    "returning",
+    "right",
+    "select",
+    "set",
+    "then",
+    "true",
+    "union",
+    "update",
+    "using",
+    "values",
+    "when",
+    "where",
+    "with",
+];
+
+/// The keywords that start a clause on a new line.
+const CLAUSES: &[&str] = &[
+    "cross",
+    "except",
+    "from",
+    "full",
+    "group",
+    "having",
+    "inner",
+    "intersect",
+    "join",
+    "left",
+    "limit",
+    "offset",
+    "order",
+    "returning",
+    "right",
+    "select",
+    "set",
+    "union",
+    "values",
+    "where",
+];
+
+/// The keywords before `JOIN` that already started the clause.
+const JOIN_PREFIXES: &[&str] = &[
+    "cross", "full", "inner", "left", "natural", "outer", "right",
+];
+
+/// Formats the SQL for reading, see [Query::pretty_sql](crate::Query::pretty_sql).
+pub(crate) fn pretty(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len() * 2);
+    // whether the clauses are put on new lines, for every open parenthesis
+    let mut groups = vec![true];
+    let mut space = false;
+    let mut newline = false;
+    let mut after_paren = false;
+    let mut previous = String::new();
+
+    let mut rest = sql.trim();
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            space = true;
+            rest = rest.trim_start();
+            continue;
+        }
+        let len = token_len(rest);
+        let token = &rest[..len];
+        rest = &rest[len..];
+
+        let lower = token.to_ascii_lowercase();
+        // `left(...)` and `right(...)` are functions
+        let function = matches!(&*lower, "left" | "right") && rest.starts_with('(');
+        let keyword = !function && KEYWORDS.contains(&&*lower);
+        if after_paren && matches!(&*lower, "select" | "with" | "values") {
+            *groups.last_mut().unwrap() = true;
+        }
+        let clause = keyword
+            && groups.last() == Some(&true)
+            && CLAUSES.contains(&&*lower)
+            && !(lower == "join" && JOIN_PREFIXES.contains(&&*previous))
+            && !after_paren;
+
+        if !out.is_empty() && (clause || newline) {
+            out.push('\n');
+            out.push_str(&"  ".repeat(groups.len() - 1));
+        } else if space && !out.is_empty() {
+            out.push(' ');
+        }
+        match keyword {
+            true => out.push_str(&token.to_ascii_uppercase()),
+            false => out.push_str(token),
+        }
+
+        space = false;
+        newline = token == ";" || token.starts_with("--");
+        after_paren = token == "(";
+        match token {
+            "(" => groups.push(false),
+            ")" if groups.len() > 1 => {
+                groups.pop();
+            }
+            _ => {}
+        }
+        previous = lower;
+    }
+    out
+}
+
+/// The length of the token at the start of the SQL, which doesn't start with whitespace.
+///
+/// Quoted strings and identifiers, dollar-quoted strings and comments are single tokens.
+fn token_len(sql: &str) -> usize {
+    let bytes = sql.as_bytes();
+    let until = |from: usize, end: &str| {
+        sql[from..]
+            .find(end)
+            .map_or(sql.len(), |i| from + i + end.len())
+    };
+    match bytes[0] {
+        b'\'' | b'"' => quoted_len(sql, false),
+        b'e' | b'E' if bytes.get(1) == Some(&b'\'') => 1 + quoted_len(&sql[1..], true),
+        b'-' if sql.starts_with("--") => sql.find('\n').unwrap_or(sql.len()),
+        b'/' if sql.starts_with("/*") => until(2, "*/"),
+        b'$' => {
+            let end = sql[1..].find(|c: char| !(c.is_alphanumeric() || c == '_'));
+            match end.map(|i| i + 1) {
+                // a dollar-quoted string like `$$...$$` or `$tag$...$tag$`
+                Some(i) if bytes[i] == b'$' && !bytes[1].is_ascii_digit() => {
+                    until(i + 1, &sql[..=i])
+                }
+                Some(i) => i,
+                None => sql.len(),
+            }
+        }
+        c if c.is_ascii_alphanumeric() || c == b'_' || !c.is_ascii() => sql
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '$')))
+            .unwrap_or(sql.len()),
+        _ => 1,
+    }
+}
+
+/// The length of the quoted string or identifier at the start of the SQL, optionally with
+/// backslash escapes. A doubled quote is part of the token.
+fn quoted_len(sql: &str, escapes: bool) -> usize {
+    let bytes = sql.as_bytes();
+    let quote = bytes[0];
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if escapes => i += 1,
+            x if x == quote && bytes.get(i + 1) == Some(&quote) => i += 1,
+            x if x == quote => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    sql.len()
+}
@@ -0,0 +1,427 @@
+use std::{borrow::Cow, fmt};
+
+use postgres_types::ToSql;
+
+use crate::Identifier;
+
+/// A rewritten query together with information about the query.
+///
+/// This is returned by the [query!](crate::query!) macro.
+///
+/// With the `serde` feature, the query and its information can be serialized, for example for
+/// structured logs or external analysis, the parameters are not part of it.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Query {
+    sql: Cow<'static, str>,
+    fingerprint: u64,
+    names: &'static [&'static str],
+    location: &'static str,
+    pub(crate) kind: StatementKind,
+    template: Option<&'static str>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    fragment_origins: Vec<&'static str>,
+}
+
+/// The kind of statement of a query, which is detected at compile time.
+///
+/// A `WITH` query has the kind of its main statement. Statements that are not detected,
+/// including the statements generated by the helpers of this crate, are [StatementKind::Other].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+#[non_exhaustive]
+pub enum StatementKind {
+    /// A `SELECT`, `VALUES` or `TABLE` statement.
+    Select,
+    /// An `INSERT` statement.
+    Insert,
+    /// An `UPDATE` statement.
+    Update,
+    /// A `DELETE` statement.
+    Delete,
+    /// Any other statement.
+    Other,
+}
+
+impl Query {
+    /// The rewritten query, which uses positional arguments.
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// A stable hash of the rewritten query, which can be used to label metrics,
+    /// traces and rate limiters per query.
+    ///
+    /// The hash is calculated at compile time with [fingerprint].
+    /// Fragments are not part of the hash, so all variants of a query with fragments
+    /// share the same fingerprint.
+    /// The sqlcommenter comment of the `comment` option and the `PREPARE` of the `prepare`
+    /// option are not part of the hash either, so it doesn't change when the call site moves.
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// The names of the arguments, in the order of the parameters.
+    /// So `$1` refers to the first name.
+    pub fn names(&self) -> &'static [&'static str] {
+        self.names
+    }
+
+    /// The file and line of the macro call that created the query, like `src/main.rs:12`.
+    ///
+    /// The location is empty for the queries created by the helpers of this crate.
+    pub fn location(&self) -> &'static str {
+        self.location
+    }
+
+    /// The query as it is written in the macro, with the named arguments, when the
+    /// `template` option is given. Logs and error reports can show this instead of the
+    /// positional arguments.
+    pub fn template(&self) -> Option<&'static str> {
+        self.template
+    }
+
+    /// The call sites of the fragments of the query, in the order of the fragments, so a
+    /// middleware can log which variant of a fragment was used, see [Fragment::origin](crate::Fragment::origin).
+    ///
+    /// This is only recorded with the `provenance` feature, and only for fragments created by
+    /// the [fragment!](crate::fragment!) macro.
+    pub fn fragment_origins(&self) -> &[&'static str] {
+        &self.fragment_origins
+    }
+
+    /// The kind of statement, which can be used to route, audit or meter the reads and the
+    /// writes differently.
+    ///
+    /// Note that a `SELECT` statement can still write, for example with a locking clause or
+    /// a data modifying `WITH` query. The `readonly` option rejects those at compile time.
+    pub fn kind(&self) -> StatementKind {
+        self.kind
+    }
+
+    /// The index of the named argument in the parameters, `$1` has index 0.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|x| *x == name)
+    }
+
+    /// The name of the argument at the index in the parameters, `$1` has index 0.
+    pub fn name_of(&self, index: usize) -> Option<&'static str> {
+        self.names.get(index).copied()
+    }
+
+    /// The query prefixed with `EXPLAIN (FORMAT JSON)`, which returns the plan of the query.
+    ///
+    /// The fingerprint and the names are the same as of the original query.
+    /// With the `serde` feature enabled, the result can be read as `explain::Explain`.
+    pub fn explain(&self) -> Query {
+        self.wrapped("EXPLAIN (FORMAT JSON) ", "")
+    }
+
+    /// The query prefixed with `EXPLAIN (ANALYZE, FORMAT JSON)`, which executes the query
+    /// and returns the plan with the actual timings, see [Query::explain].
+    ///
+    /// Note that data modifying statements are executed as well.
+    pub fn explain_analyze(&self) -> Query {
+        self.wrapped("EXPLAIN (ANALYZE, FORMAT JSON) ", "")
+    }
+
+    /// The query wrapped in `SELECT EXISTS(...)`, which returns whether the query returns
+    /// any rows as a single boolean.
+    ///
+    /// The fingerprint and the names are the same as of the original query.
+    pub fn exists(&self) -> Query {
+        self.wrapped("SELECT EXISTS(", ")")
+    }
+
+    /// The query wrapped in `COPY (...) TO STDOUT (FORMAT BINARY)`, which exports the rows
+    /// in the binary copy format.
+    ///
+    /// `COPY` doesn't accept bind parameters, so the arguments need to be inlined before the
+    /// execution, like `ClientExt::copy_out_named` does.
+    pub fn copy_out(&self) -> Query {
+        self.wrapped("COPY (", ") TO STDOUT (FORMAT BINARY)")
+    }
+
+    /// The statement with `RETURNING` the column appended, like the generated key of an
+    /// `INSERT`.
+    pub fn returning(&self, column: &Identifier) -> Query {
+        // on a new line, in case the statement ends with a line comment
+        self.wrapped("", &format!("\nRETURNING {column}"))
+    }
+
+    pub(crate) fn wrapped(&self, prefix: &str, suffix: &str) -> Query {
+        let sql = self.sql.trim_end().trim_end_matches(';');
+        // the suffix goes on a new line when the query ends with a line comment
+        let comment = sql.lines().next_back().unwrap_or_default().contains("--");
+        let separator = match comment && !suffix.is_empty() && !suffix.starts_with('\n') {
+            true => "\n",
+            false => "",
+        };
+        Query {
+            sql: format!("{prefix}{sql}{separator}{suffix}").into(),
+            ..self.clone()
+        }
+    }
+
+    /// Renders the query with the values of the parameters substituted, for log messages and
+    /// bug reports. Every value is labeled with its name, e.g. `{$location = 'sweden'}`,
+    /// which also makes sure the result can't be executed.
+    ///
+    /// The values are rendered on a best-effort basis using their `Debug` implementation.
+    pub fn debug_sql(&self, params: &[&(dyn ToSql + Sync)]) -> String {
+        let sql = self.sql();
+        let mut out = String::new();
+        let mut rest = sql;
+        while let Some(dollar) = rest.find('$') {
+            out.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            let digits = rest
+                .find(|x: char| !x.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let idx = rest[..digits]
+                .parse::<usize>()
+                .ok()
+                .and_then(|x| x.checked_sub(1));
+            match idx.and_then(|idx| Some((self.name_of(idx)?, params.get(idx)?))) {
+                Some((name, value)) => {
+                    out.push_str(&format!(
+                        "{{${name} = {}}}",
+                        debug_value(&format!("{value:?}"))
+                    ));
+                    rest = &rest[digits..];
+                }
+                None => out.push('$'),
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// The query formatted for log output and error messages, with the keywords in upper case
+    /// and the clauses on separate lines, indented by the nesting of the subqueries.
+    ///
+    /// Only the whitespace and the case of the keywords are changed, the executed query is
+    /// [Query::sql], which is left untouched.
+    ///
+    /// ```
+    /// # use pg_named_args::query;
+    /// let location = "sweden";
+    /// let (query, _) = query!(
+    ///     "select * from weather_reports where location = $location and station_id in (select id from stations where active) order by reported_at desc",
+    ///     Args { location }
+    /// );
+    /// assert_eq!(
+    ///     query.pretty_sql(),
+    ///     "SELECT *
+    /// FROM weather_reports
+    /// WHERE location = $1 AND station_id IN (SELECT id
+    ///   FROM stations
+    ///   WHERE active)
+    /// ORDER BY reported_at DESC"
+    /// );
+    /// ```
+    pub fn pretty_sql(&self) -> String {
+        crate::pretty::pretty(self.sql())
+    }
+
+    /// Renders a diagnostic for an error at a position in the query, with a caret below the
+    /// position. The positional arguments in the shown line are replaced by their names.
+    ///
+    /// The position is the 1-based character index reported by PostgreSQL.
+    pub fn render_error(&self, message: &str, position: usize) -> String {
+        let sql = self.sql();
+        let offset = sql
+            .char_indices()
+            .nth(position.saturating_sub(1))
+            .map_or(sql.len(), |(i, _)| i);
+        let start = sql[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = sql[offset..].find('\n').map_or(sql.len(), |i| offset + i);
+        let line_number = sql[..start].matches('\n').count() + 1;
+
+        let target = offset - start;
+        let mut line = String::new();
+        let mut caret = None;
+        let mut consumed = 0;
+        let mut rest = &sql[start..end];
+        while let Some(x) = rest.chars().next() {
+            let mut len = x.len_utf8();
+            let mut name = None;
+            if x == '$' {
+                let digits = rest[1..].find(|x: char| !x.is_ascii_digit());
+                let digits = digits.unwrap_or(rest.len() - 1);
+                name = rest[1..=digits]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|idx| self.name_of(idx.checked_sub(1)?));
+                if name.is_some() {
+                    len += digits;
+                }
+            }
+
+            if caret.is_none() && target < consumed + len {
+                caret = Some(line.chars().count());
+            }
+            match name {
+                Some(name) => {
+                    line.push('$');
+                    line.push_str(name);
+                }
+                None => line.push_str(&rest[..len]),
+            }
+            consumed += len;
+            rest = &rest[len..];
+        }
+        let caret = caret.unwrap_or(line.chars().count());
+
+        let pad = " ".repeat(line_number.to_string().len());
+        let indent = " ".repeat(caret);
+        format!("error: {message}\n{pad} |\n{line_number} | {line}\n{pad} | {indent}^")
+    }
+
+    /// Renders a diagnostic for an error returned by the database for this query,
+    /// see [Query::render_error].
+    ///
+    /// Returns `None` when the error doesn't contain a position in the query.
+    #[cfg(feature = "tokio-postgres")]
+    pub fn render_db_error(&self, err: &tokio_postgres::Error) -> Option<String> {
+        use tokio_postgres::error::ErrorPosition;
+
+        let err = err.as_db_error()?;
+        match err.position()? {
+            ErrorPosition::Original(position) => {
+                Some(self.render_error(err.message(), *position as usize))
+            }
+            ErrorPosition::Internal { .. } => None,
+        }
+    }
+
+    /// Creates a query from SQL generated at runtime by this crate.
+    #[cfg(any(feature = "tokio-postgres", feature = "sea-query"))]
+    pub(crate) fn from_sql(sql: impl Into<Cow<'static, str>>) -> Self {
+        let sql = sql.into();
+        Self {
+            fingerprint: fingerprint(&sql),
+            sql,
+            names: &[],
+            location: "",
+            kind: StatementKind::Other,
+            template: None,
+            fragment_origins: vec![],
+        }
+    }
+
+    #[doc(hidden)]
+    /// This is the constructor used by the [query!](crate::query!) macro.
+    /// It is not intended to be used manually.
+    pub fn new_unchecked(
+        sql: impl Into<Cow<'static, str>>,
+        fingerprint: u64,
+        names: &'static [&'static str],
+        location: &'static str,
+        kind: StatementKind,
+    ) -> Self {
+        Self {
+            sql: sql.into(),
+            fingerprint,
+            names,
+            location,
+            kind,
+            template: None,
+            fragment_origins: vec![],
+        }
+    }
+
+    #[doc(hidden)]
+    /// Adds the template, used by the [query!](crate::query!) macro for the `template` option.
+    pub fn with_template(self, template: &'static str) -> Self {
+        Self {
+            template: Some(template),
+            ..self
+        }
+    }
+
+    #[doc(hidden)]
+    /// Adds the call sites of the fragments, used by the [query!](crate::query!) macro.
+    pub fn with_fragment_origins(self, fragment_origins: Vec<&'static str>) -> Self {
+        Self {
+            fragment_origins,
+            ..self
+        }
+    }
+}
+
+impl AsRef<str> for Query {
+    fn as_ref(&self) -> &str {
+        self.sql()
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.sql())
+    }
+}
+
+/// Calculates the fingerprint of a query, which is the 64 bit FNV-1a hash.
+///
+/// For a query without fragments, this is equal to [Query::fingerprint].
+pub const fn fingerprint(sql: &str) -> u64 {
+    let bytes = sql.as_bytes();
+    let mut hash = 0xcbf29ce484222325_u64;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Converts the `Debug` output of a value to a SQL literal, if it is recognized.
+fn debug_value(value: &str) -> String {
+    if value == "None" {
+        return "NULL".to_owned();
+    }
+    if let Some(inner) = value
+        .strip_prefix("Some(")
+        .and_then(|x| x.strip_suffix(')'))
+    {
+        return debug_value(inner);
+    }
+    let Some(inner) = value.strip_prefix('"').and_then(|x| x.strip_suffix('"')) else {
+        return value.to_owned();
+    };
+
+    let mut out = String::from("'");
+    let mut chars = inner.chars();
+    while let Some(x) = chars.next() {
+        let x = match x {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some('u') => {
+                    let code: String = chars.by_ref().skip(1).take_while(|&x| x != '}').collect();
+                    u32::from_str_radix(&code, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .unwrap_or(char::REPLACEMENT_CHARACTER)
+                }
+                Some(x) => x,
+                None => break,
+            },
+            x => x,
+        };
+        if x == '\'' {
+            out.push('\'');
+        }
+        out.push(x);
+    }
+    out.push('\'');
+    out
+}
@@ -0,0 +1,55 @@
+use std::{error::Error, fmt};
+
+use postgres_types::{to_sql_checked, IsNull, ToSql, Type};
+
+/// Marks an argument as sensitive, see [Redacted].
+pub fn redact<T>(value: T) -> Redacted<T> {
+    Redacted(value)
+}
+
+/// A sensitive argument, which is bound like the wrapped value, but whose `Debug`
+/// implementation shows `[REDACTED]` instead of the value.
+///
+/// This makes sure the value doesn't end up in the output of [Query::debug_sql](crate::Query::debug_sql)
+/// or any other logging based on `Debug`.
+///
+/// ```
+/// # use pg_named_args::{query, redact};
+/// let name = "fred";
+/// let password = "yabba dabba doo";
+/// let (query, params) = query!(
+///     "SELECT * FROM users WHERE name = $name AND password = crypt($password, password)",
+///     Args {
+///         name,
+///         password: redact(password),
+///     }
+/// );
+/// assert_eq!(
+///     query.debug_sql(params),
+///     "SELECT * FROM users WHERE name = {$name = 'fred'} AND password = crypt({$password = [REDACTED]}, password)"
+/// );
+/// ```
+#[derive(Clone, Copy, Default)]
+pub struct Redacted<T>(pub T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl<T: ToSql> ToSql for Redacted<T> {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut bytes::BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        self.0.to_sql(ty, out)
+    }
+
+    fn accepts(ty: &Type) -> bool {
+        T::accepts(ty)
+    }
+
+    to_sql_checked!();
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::hash_map::RandomState,
+    error::Error as StdError,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use tokio_postgres::error::SqlState;
+
+/// The number of times the body of [retry_serializable!](crate::retry_serializable!) is retried.
+#[doc(hidden)]
+pub const MAX_RETRIES: u32 = 5;
+
+/// Runs the body in a `SERIALIZABLE` transaction, which is retried when it fails with a
+/// serialization failure (`40001`) or a deadlock (`40P01`).
+///
+/// The body is retried up to 5 times, waiting with an exponential backoff with jitter in
+/// between, using the timer of the Tokio runtime. The transaction is committed when the body
+/// returns `Ok`, a serialization failure of the commit is retried as well.
+/// The client needs to be a `tokio_postgres::Client`, the body is the same as of
+/// [transaction!](crate::transaction!) and the error type needs to implement [RetryableError].
+///
+/// ```no_run
+/// # use pg_named_args::{query, retry_serializable, Executor};
+/// # async fn example(client: &mut tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+/// let name = "Fred";
+/// retry_serializable!(client, |txn| {
+///     let (query, params) = query!("SELECT count(*) FROM flintstone", Args {});
+///     let count: i64 = txn.query_named(&query, params).await?[0].get(0);
+///     let (query, params) = query!(
+///         "INSERT INTO flintstone(name, number) VALUES($name, $count)",
+///         Args { name, count }
+///     );
+///     txn.execute_named(&query, params).await?;
+///     Ok(())
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! retry_serializable {
+    ($client:expr, |$txn:ident| -> $ret:ty $body:block) => {
+        async {
+            let mut attempt = 0;
+            loop {
+                let res: $ret = async {
+                    let $txn = $client
+                        .build_transaction()
+                        .isolation_level($crate::__private::tokio_postgres::IsolationLevel::Serializable)
+                        .start()
+                        .await?;
+                    let res: $ret = async { $body }.await;
+                    match res {
+                        Ok(value) => {
+                            $txn.commit().await?;
+                            Ok(value)
+                        }
+                        Err(err) => {
+                            let _ = $txn.rollback().await;
+                            Err(err)
+                        }
+                    }
+                }
+                .await;
+                match res {
+                    Err(err)
+                        if attempt < $crate::__private::MAX_RETRIES
+                            && $crate::RetryableError::is_retryable(&err) =>
+                    {
+                        $crate::__private::backoff(attempt).await;
+                        attempt += 1;
+                    }
+                    res => break res,
+                }
+            }
+        }
+    };
+    ($client:expr, |$txn:ident| $body:block) => {
+        $crate::retry_serializable!(
+            $client,
+            |$txn| -> ::std::result::Result<_, $crate::__private::tokio_postgres::Error> $body
+        )
+    };
+}
+
+/// An error that tells whether the transaction can be retried, see
+/// [retry_serializable!](crate::retry_serializable!).
+///
+/// The boxed errors are retryable when a `tokio_postgres::Error` in their chain of sources is.
+/// Implement this for the error type of the application, for example by delegating to the
+/// wrapped `tokio_postgres::Error`.
+pub trait RetryableError {
+    /// Whether the error is a serialization failure or a deadlock.
+    fn is_retryable(&self) -> bool;
+}
+
+impl RetryableError for tokio_postgres::Error {
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self.code(),
+            Some(&SqlState::T_R_SERIALIZATION_FAILURE | &SqlState::T_R_DEADLOCK_DETECTED)
+        )
+    }
+}
+
+impl RetryableError for crate::Error {
+    fn is_retryable(&self) -> bool {
+        match self {
+            crate::Error::Db(err) => err.is_retryable(),
+            crate::Error::Inline(_)
+            | crate::Error::MigrationChanged(_)
+            | crate::Error::NotFound
+            | crate::Error::TooManyRows => false,
+        }
+    }
+}
+
+impl RetryableError for Box<dyn StdError> {
+    fn is_retryable(&self) -> bool {
+        chain_is_retryable(&**self)
+    }
+}
+
+impl RetryableError for Box<dyn StdError + Send + Sync> {
+    fn is_retryable(&self) -> bool {
+        chain_is_retryable(&**self)
+    }
+}
+
+fn chain_is_retryable(err: &(dyn StdError + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<tokio_postgres::Error>() {
+            return err.is_retryable();
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// Waits before the next attempt, between half and the whole of `10ms * 2^attempt`.
+#[doc(hidden)]
+pub async fn backoff(attempt: u32) {
+    tokio::time::sleep(backoff_delay(attempt)).await;
+}
+
+fn backoff_delay(attempt: u32) -> Duration {
+    let delay = Duration::from_millis(10) * 2u32.pow(attempt);
+    let jitter = RandomState::new().build_hasher().finish() % 1000;
+    delay / 2 + delay / 2 * jitter as u32 / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use super::*;
+
+    #[derive(Debug)]
+    struct Wrapper(Box<dyn StdError + Send + Sync>);
+
+    impl fmt::Display for Wrapper {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("wrapper")
+        }
+    }
+
+    impl StdError for Wrapper {
+        fn source(&self) -> Option<&(dyn StdError + 'static)> {
+            Some(&*self.0)
+        }
+    }
+
+    #[test]
+    fn chain_without_postgres_error_should_not_be_retryable() {
+        let err = std::io::Error::other("closed");
+        let err: Box<dyn StdError> = Box::new(Wrapper(Box::new(err)));
+        assert!(!err.is_retryable());
+        assert!(!crate::Error::NotFound.is_retryable());
+    }
+
+    #[test]
+    fn backoff_delay_should_stay_within_the_jitter_bounds() {
+        for attempt in 0..MAX_RETRIES {
+            let max = Duration::from_millis(10) * 2u32.pow(attempt);
+            for _ in 0..100 {
+                let delay = backoff_delay(attempt);
+                assert!(
+                    delay >= max / 2 && delay <= max,
+                    "{delay:?} for attempt {attempt}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use postgres_types::ToSql;
+
+use crate::{Executor, Query, StatementKind};
+
+/// An [Executor] that routes the queries to a primary or to one of the replicas, based on the
+/// [kind of statement](Query::kind).
+///
+/// `SELECT` statements are sent to the replicas in turn, all other statements to the primary.
+/// Note that a `SELECT` statement can still write, like `SELECT ... FOR UPDATE`, the `readonly`
+/// option of the macros rejects those at compile time.
+///
+/// With [Router::read_your_writes] enabled, the reads are sent to the primary as well after the
+/// first write through the router, so the reads see the writes that are not replicated yet.
+/// Use a router per request or unit of work in that case.
+///
+/// ```no_run
+/// # use pg_named_args::{query, Executor, Router};
+/// # async fn example<E: Executor + Sync>(primary: E, replica: E) -> Result<(), E::Error> {
+/// let router = Router::new(primary, vec![replica]).read_your_writes(true);
+///
+/// let name = "Fred";
+/// let (query, params) = query!("SELECT * FROM flintstone WHERE name = $name", Args { name });
+/// let rows = router.query_named(&query, params).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Router<E> {
+    primary: E,
+    replicas: Vec<E>,
+    next: AtomicUsize,
+    read_your_writes: bool,
+    written: AtomicBool,
+}
+
+impl<E> Router<E> {
+    /// Creates a router, without replicas all queries are sent to the primary.
+    pub fn new(primary: E, replicas: Vec<E>) -> Self {
+        Self {
+            primary,
+            replicas,
+            next: AtomicUsize::new(0),
+            read_your_writes: false,
+            written: AtomicBool::new(false),
+        }
+    }
+
+    /// Sends the reads to the primary after the first write through the router.
+    pub fn read_your_writes(mut self, enabled: bool) -> Self {
+        self.read_your_writes = enabled;
+        self
+    }
+
+    /// The primary, for the queries that always need to see the latest data.
+    pub fn primary(&self) -> &E {
+        &self.primary
+    }
+
+    /// The next replica in turn, or the primary without replicas.
+    pub fn replica(&self) -> &E {
+        if self.replicas.is_empty() {
+            return &self.primary;
+        }
+        let idx = self.next.fetch_add(1, Ordering::Relaxed) % self.replicas.len();
+        &self.replicas[idx]
+    }
+
+    /// The executor the query is sent to.
+    pub fn route(&self, query: &Query) -> &E {
+        if query.kind() != StatementKind::Select {
+            self.written.store(true, Ordering::Relaxed);
+            return &self.primary;
+        }
+        if self.read_your_writes && self.written.load(Ordering::Relaxed) {
+            return &self.primary;
+        }
+        self.replica()
+    }
+}
+
+impl<E: Executor + Sync> Executor for Router<E> {
+    type Row = E::Row;
+    type Error = E::Error;
+
+    async fn query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<E::Row>, E::Error> {
+        self.route(query).query_named(query, params).await
+    }
+
+    async fn execute_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, E::Error> {
+        self.route(query).execute_named(query, params).await
+    }
+}
@@ -0,0 +1,51 @@
+use sea_query::PostgresQueryBuilder;
+use sea_query_postgres::{PostgresBinder, PostgresValues};
+
+use crate::{Query, StatementKind};
+
+impl Query {
+    /// Builds a `sea-query` statement with the Postgres query builder, so statements from the
+    /// query builder can be executed like the queries of the macros.
+    ///
+    /// The values are returned separately, `PostgresValues::as_params` gives the parameters.
+    /// The query has no names and no location, the kind is detected from the first keyword.
+    ///
+    /// The conversion only goes this way. The parameters of the macros are `ToSql` trait
+    /// objects, which can only be serialized for a given type and can't be read back, so they
+    /// can't be turned into the typed `sea_query::Value`s. Both sides meet at the
+    /// `&[&(dyn ToSql + Sync)]` parameters, which is what the execution helpers take.
+    ///
+    /// ```
+    /// # use pg_named_args::{Query, StatementKind};
+    /// use sea_query::{Alias, Expr};
+    ///
+    /// let statement = sea_query::Query::select()
+    ///     .column(Alias::new("report"))
+    ///     .from(Alias::new("weather_reports"))
+    ///     .and_where(Expr::col(Alias::new("location")).eq("sweden"))
+    ///     .to_owned();
+    /// let (query, values) = Query::from_sea_query(&statement);
+    /// assert_eq!(
+    ///     query.sql(),
+    ///     r#"SELECT "report" FROM "weather_reports" WHERE "location" = $1"#
+    /// );
+    /// assert_eq!(query.kind(), StatementKind::Select);
+    /// assert_eq!(values.as_params().len(), 1);
+    /// ```
+    /// ```ignore
+    /// let rows = client.query_named(&query, &values.as_params()).await?;
+    /// ```
+    pub fn from_sea_query<S: PostgresBinder>(statement: &S) -> (Query, PostgresValues) {
+        let (sql, values) = statement.build_postgres(PostgresQueryBuilder);
+        let kind = match sql.split_whitespace().next() {
+            Some("SELECT") => StatementKind::Select,
+            Some("INSERT") => StatementKind::Insert,
+            Some("UPDATE") => StatementKind::Update,
+            Some("DELETE") => StatementKind::Delete,
+            _ => StatementKind::Other,
+        };
+        let mut query = Query::from_sql(sql);
+        query.kind = kind;
+        (query, values)
+    }
+}
@@ -0,0 +1,41 @@
+/// Configuration parameters that are set for the current transaction only, like `SET LOCAL`.
+///
+/// The settings are applied with `set_config(name, value, true)` using bind parameters,
+/// so the names and values never need to be quoted. This is useful for row level security
+/// setups, which need settings like `app.tenant_id` on every statement.
+///
+/// ```no_run
+/// # use pg_named_args::{query, Executor, ExecutorExt, Settings};
+/// # async fn example(client: &mut tokio_postgres::Client) -> Result<(), tokio_postgres::Error> {
+/// let settings = Settings::new()
+///     .set("app.tenant_id", 42)
+///     .set("statement_timeout", "5s");
+///
+/// let txn = client.transaction().await?;
+/// txn.set_local(&settings).await?;
+/// let (query, params) = query!("SELECT * FROM orders", Args {});
+/// let rows = txn.query_named(&query, params).await?;
+/// txn.commit().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    pub(crate) settings: Vec<(String, String)>,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a setting, a later setting with the same name overrides the earlier one.
+    pub fn set(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+        self.settings.push((name.into(), value.to_string()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+}
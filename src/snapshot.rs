@@ -0,0 +1,51 @@
+use std::fmt;
+
+use crate::Query;
+
+/// The rewritten query and the names of its arguments in a stable form, for snapshot tests.
+///
+/// The display format lists the arguments followed by the query, so changes to a query show
+/// up as a diff of the SQL in code review. The location is not part of the display format,
+/// so it doesn't change when unrelated code moves, but it can be used to name the snapshot.
+/// With the `serde` feature enabled, the snapshot can be serialized.
+///
+/// ```
+/// # use pg_named_args::query;
+/// let name = "Fred";
+/// let (query, _) = query!("SELECT * FROM flintstone WHERE name = $name", Args { name });
+/// assert_eq!(
+///     query.snapshot().to_string(),
+///     "-- $1: name\nSELECT * FROM flintstone WHERE name = $1\n"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct Snapshot {
+    /// The file and line of the macro call, see [Query::location].
+    pub location: String,
+    /// The names of the arguments, in the order of the parameters.
+    pub names: Vec<String>,
+    /// The rewritten query.
+    pub sql: String,
+}
+
+impl Query {
+    /// Returns the query in a stable form for snapshot tests, see [Snapshot].
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            location: self.location().to_owned(),
+            names: self.names().iter().map(|x| x.to_string()).collect(),
+            sql: self.sql().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, name) in self.names.iter().enumerate() {
+            writeln!(f, "-- ${}: {name}", idx + 1)?;
+        }
+        writeln!(f, "{}", self.sql)
+    }
+}
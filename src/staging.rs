@@ -0,0 +1,93 @@
+use std::fmt::Write;
+
+use postgres_types::Type;
+
+use crate::Identifier;
+
+/// A temporary table for bulk upserts, which is loaded with binary `COPY` and merged into the
+/// target table with a statement written with [query!](crate::query!).
+///
+/// The names are validated identifiers and the types are schema qualified, so the statements
+/// can't be used for injection. The table is created, loaded, merged and dropped by
+/// `TransactionExt::copy_merge_named` of the `tokio-postgres` feature.
+///
+/// ```
+/// # use pg_named_args::{Identifier, StagingTable};
+/// # use pg_named_args::postgres_types::Type;
+/// let staging = StagingTable::new(Identifier::new("staged_reports").unwrap())
+///     .column(Identifier::new("location").unwrap(), Type::TEXT)
+///     .column(Identifier::new("temperature").unwrap(), Type::FLOAT8);
+/// assert_eq!(
+///     staging.create_sql(),
+///     r#"CREATE TEMP TABLE "staged_reports" ("location" "pg_catalog"."text", "temperature" "pg_catalog"."float8") ON COMMIT DROP"#
+/// );
+/// assert_eq!(
+///     staging.copy_sql(),
+///     r#"COPY "staged_reports" ("location", "temperature") FROM STDIN (FORMAT BINARY)"#
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct StagingTable {
+    name: Identifier,
+    columns: Vec<(Identifier, Type)>,
+}
+
+impl StagingTable {
+    /// Creates the table without columns.
+    pub fn new(name: Identifier) -> Self {
+        Self {
+            name,
+            columns: vec![],
+        }
+    }
+
+    /// Adds a column of the type, the rows are written in the order of the columns.
+    pub fn column(mut self, name: Identifier, ty: Type) -> Self {
+        self.columns.push((name, ty));
+        self
+    }
+
+    /// The name of the table, to refer to it in the merge statement.
+    pub fn name(&self) -> &Identifier {
+        &self.name
+    }
+
+    /// The types of the columns.
+    pub fn types(&self) -> Vec<Type> {
+        self.columns.iter().map(|(_, ty)| ty.clone()).collect()
+    }
+
+    /// The `CREATE TEMP TABLE` statement, the table is dropped at the end of the transaction.
+    pub fn create_sql(&self) -> String {
+        let mut sql = format!("CREATE TEMP TABLE {} (", self.name);
+        for (i, (name, ty)) in self.columns.iter().enumerate() {
+            if i > 0 {
+                sql.push_str(", ");
+            }
+            let schema = quote(ty.schema());
+            write!(sql, "{name} {schema}.{}", quote(ty.name())).unwrap();
+        }
+        sql.push_str(") ON COMMIT DROP");
+        sql
+    }
+
+    /// The `COPY` statement that loads the rows.
+    pub fn copy_sql(&self) -> String {
+        let columns = self.columns.iter().map(|(name, _)| name.to_string());
+        format!(
+            "COPY {} ({}) FROM STDIN (FORMAT BINARY)",
+            self.name,
+            columns.collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    /// The `DROP TABLE` statement.
+    pub fn drop_sql(&self) -> String {
+        format!("DROP TABLE {}", self.name)
+    }
+}
+
+/// Quotes the name of a type, which comes from the database.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
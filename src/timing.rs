@@ -0,0 +1,127 @@
+use std::{
+    fmt,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use postgres_types::ToSql;
+
+use crate::{Executor, Query};
+
+/// Awaits the future, returning its output together with the time it took.
+///
+/// ```no_run
+/// # use pg_named_args::{query, timed, Executor};
+/// # async fn example<E: Executor>(client: &E) -> Result<(), E::Error> {
+/// let name = "Fred";
+/// let (query, params) = query!("SELECT * FROM flintstone WHERE name = $name", Args { name });
+/// let (rows, elapsed) = timed(client.query_named(&query, params)).await;
+/// let rows = rows?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn timed<F: Future>(future: F) -> (F::Output, Duration) {
+    let start = Instant::now();
+    let res = future.await;
+    (res, start.elapsed())
+}
+
+/// An observer for [Timed] that logs the executions taking longer than the threshold.
+///
+/// The slow executions are logged as a `tracing` warning with the hexadecimal fingerprint,
+/// the names of the parameters and the duration of the query.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use pg_named_args::{log_slow_queries, Executor, Timed};
+/// # fn example<E: Executor + Sync>(client: E) {
+/// let client = Timed::new(client, log_slow_queries(Duration::from_millis(500)));
+/// # }
+/// ```
+#[cfg(feature = "tracing")]
+pub fn log_slow_queries(threshold: Duration) -> impl Fn(&Query, Duration) + Clone + Send + Sync {
+    move |query, elapsed| {
+        if elapsed > threshold {
+            tracing::warn!(
+                fingerprint = format_args!("{:016x}", query.fingerprint()),
+                db.parameters = ?query.names(),
+                duration_ms = elapsed.as_secs_f64() * 1000.0,
+                "slow query",
+            );
+        }
+    }
+}
+
+/// An [Executor] that reports the duration of every execution to an observer.
+///
+/// The observer is called with the query after the execution, also when it failed.
+///
+/// ```no_run
+/// # use pg_named_args::{query, Executor, Timed};
+/// # async fn example<E: Executor + Sync>(client: E) -> Result<(), E::Error> {
+/// let client = Timed::new(client, |query, elapsed| {
+///     println!("{:016x} took {elapsed:?}", query.fingerprint());
+/// });
+///
+/// let name = "Fred";
+/// let (query, params) = query!("SELECT * FROM flintstone WHERE name = $name", Args { name });
+/// let rows = client.query_named(&query, params).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Timed<E, F> {
+    inner: E,
+    observer: F,
+}
+
+impl<E, F> Timed<E, F>
+where
+    F: Fn(&Query, Duration),
+{
+    /// Wraps the executor, calling the observer after every execution.
+    pub fn new(inner: E, observer: F) -> Self {
+        Self { inner, observer }
+    }
+
+    /// The wrapped executor.
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+impl<E: fmt::Debug, F> fmt::Debug for Timed<E, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Timed")
+            .field("inner", &self.inner)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, F> Executor for Timed<E, F>
+where
+    E: Executor + Sync,
+    F: Fn(&Query, Duration) + Sync,
+{
+    type Row = E::Row;
+    type Error = E::Error;
+
+    async fn query_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<E::Row>, E::Error> {
+        let (res, elapsed) = timed(self.inner.query_named(query, params)).await;
+        (self.observer)(query, elapsed);
+        res
+    }
+
+    async fn execute_named(
+        &self,
+        query: &Query,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, E::Error> {
+        let (res, elapsed) = timed(self.inner.execute_named(query, params)).await;
+        (self.observer)(query, elapsed);
+        res
+    }
+}
@@ -0,0 +1,47 @@
+/// Transposes a list of tuples into a list per column, to bind composite keys as one array per
+/// column, like `WHERE (a, b) IN (SELECT * FROM UNNEST($a, $b))`.
+///
+/// This is implemented for slices of tuples with 2 up to 6 elements.
+///
+/// ```
+/// # use pg_named_args::{query_args, Transpose};
+/// let keys = [(1, "sweden"), (2, "netherlands")];
+/// let (id, location) = keys.transpose();
+/// let (query, params) = query_args!(
+///     r"DELETE FROM weather_reports WHERE ($[id, location]) IN (SELECT * FROM UNNEST($[..]))",
+///     Args { id, location }
+/// );
+/// assert_eq!(
+///     query,
+///     "DELETE FROM weather_reports WHERE (id, location) IN (SELECT * FROM UNNEST($1, $2))"
+/// );
+/// ```
+pub trait Transpose {
+    /// The tuple with a list per column.
+    type Columns;
+
+    /// Returns the elements of the tuples as a list per column.
+    fn transpose(&self) -> Self::Columns;
+}
+
+macro_rules! impl_transpose {
+    ($($t:ident $idx:tt),+) => {
+        impl<$($t: Clone),+> Transpose for [($($t,)+)] {
+            type Columns = ($(Vec<$t>,)+);
+
+            fn transpose(&self) -> Self::Columns {
+                let mut columns = ($(Vec::<$t>::with_capacity(self.len()),)+);
+                for row in self {
+                    $(columns.$idx.push(row.$idx.clone());)+
+                }
+                columns
+            }
+        }
+    };
+}
+
+impl_transpose!(A 0, B 1);
+impl_transpose!(A 0, B 1, C 2);
+impl_transpose!(A 0, B 1, C 2, D 3);
+impl_transpose!(A 0, B 1, C 2, D 3, E 4);
+impl_transpose!(A 0, B 1, C 2, D 3, E 4, F 5);
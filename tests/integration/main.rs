@@ -1,4 +1,4 @@
-use pg_named_args::{fragment, query_args};
+use pg_named_args::{fingerprint, fragment, nest, query, query_args};
 
 #[test]
 fn query_args_should_support_identifiers_as_values() {
@@ -87,3 +87,1902 @@ fn query_args_should_accept_fragment() {
     assert_eq!(query.trim(), expected_query);
     assert_eq!(args.len(), 1);
 }
+
+#[test]
+fn query_args_should_dedent_query() {
+    let a = 1;
+    let (query, params) = query_args!(
+        dedent,
+        r"
+        SELECT *
+        FROM fred_flintstone
+        WHERE a = $a
+        ",
+        Args { a }
+    );
+    assert_eq!(query, "SELECT *\nFROM fred_flintstone\nWHERE a = $1");
+    assert_eq!(params.len(), 1);
+}
+
+#[test]
+fn query_args_should_minify_query() {
+    let a = 1;
+    let (query, params) = query_args!(
+        minify,
+        r"
+        SELECT * -- all columns
+        FROM fred_flintstone
+        WHERE a = $a /* the $b here is ignored */
+        ",
+        Args { a }
+    );
+    assert_eq!(query, "SELECT * FROM fred_flintstone WHERE a = $1");
+    assert_eq!(params.len(), 1);
+}
+
+#[test]
+fn query_args_should_append_sqlcommenter_comment() {
+    let a = 1;
+    let (query, _) = query_args!(
+        comment(app = "checkout"),
+        "SELECT * FROM fred_flintstone WHERE a = $a;",
+        Args { a }
+    );
+    let expected_query = format!(
+        "SELECT * FROM fred_flintstone WHERE a = $1 /*app='checkout',file='tests%2Fintegration%2Fmain.rs%3A{}'*/;",
+        line!() - 7
+    );
+    assert_eq!(query, &expected_query);
+
+    // the fingerprint doesn't change when the call site moves
+    let (query, _) = query!(
+        comment,
+        "SELECT * FROM fred_flintstone WHERE a = $a;",
+        Args { a }
+    );
+    assert_eq!(
+        query.fingerprint(),
+        fingerprint("SELECT * FROM fred_flintstone WHERE a = $1;")
+    );
+}
+
+#[test]
+fn query_should_contain_fingerprint() {
+    let b = 1;
+    let (query, params) = query!("SELECT * FROM fred_flintstone WHERE b = $b", Args { b });
+    assert_eq!(query.sql(), "SELECT * FROM fred_flintstone WHERE b = $1");
+    assert_eq!(query.fingerprint(), fingerprint(query.sql()));
+    assert_eq!(params.len(), 1);
+
+    let f = fragment!("b");
+    let (query2, _) = query!(
+        "SELECT * FROM {x} WHERE ${a} = $b",
+        Sql { a: f },
+        Args { b }
+    );
+    assert_eq!(query2.sql(), "SELECT * FROM {x} WHERE b = $1");
+    assert_ne!(query.fingerprint(), query2.fingerprint());
+}
+
+#[test]
+fn query_should_map_names_to_indices() {
+    let (a, b) = (1, 2);
+    let (query, _) = query!(
+        "INSERT INTO fred_flintstone($[b, a]) VALUES($[..]) RETURNING $b",
+        Args { a, b }
+    );
+    assert_eq!(query.names(), ["b", "a"]);
+    assert_eq!(query.index_of("a"), Some(1));
+    assert_eq!(query.index_of("c"), None);
+    assert_eq!(query.name_of(0), Some("b"));
+    assert_eq!(query.name_of(2), None);
+}
+
+#[test]
+fn query_should_render_error_with_names() {
+    let (location, start, end) = ("sweden", 2020, 2030);
+    let (query, _) = query!(
+        r"SELECT report
+FROM weather_reports
+WHERE location = $location AND time BETWEEN $start AN $end",
+        Args {
+            location,
+            start,
+            end
+        }
+    );
+    let position = query.sql().find("AN $3").unwrap() + 1;
+    let expected = r#"error: syntax error at or near "AN"
+  |
+3 | WHERE location = $location AND time BETWEEN $start AN $end
+  |                                                    ^"#;
+    assert_eq!(
+        query.render_error(r#"syntax error at or near "AN""#, position),
+        expected
+    );
+
+    let position = query.sql().find("$3").unwrap() + 1;
+    let rendered = query.render_error("invalid input", position);
+    assert!(rendered.ends_with("\n  |                                                       ^"));
+}
+
+#[test]
+fn query_should_render_debug_sql() {
+    let (name, age, nickname) = ("Fred \"the\" O'Flintstone", 42, None::<&str>);
+    let (query, params) = query!(
+        r"INSERT INTO fred_flintstone($[name, age, nickname]) VALUES($[..])",
+        Args {
+            name,
+            age,
+            nickname
+        }
+    );
+    assert_eq!(
+        query.debug_sql(params),
+        r#"INSERT INTO fred_flintstone(name, age, nickname) VALUES({$name = 'Fred "the" O''Flintstone'}, {$age = 42}, {$nickname = NULL})"#
+    );
+}
+
+#[test]
+fn query_should_support_explain() {
+    let b = 1;
+    let (query, _) = query!("SELECT * FROM fred_flintstone WHERE b = $b", Args { b });
+    let explain = query.explain_analyze();
+    assert_eq!(
+        explain.sql(),
+        "EXPLAIN (ANALYZE, FORMAT JSON) SELECT * FROM fred_flintstone WHERE b = $1"
+    );
+    assert_eq!(explain.fingerprint(), query.fingerprint());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn explain_should_parse_plan() {
+    use pg_named_args::explain::Explain;
+
+    let json = r#"[{
+        "Plan": {
+            "Node Type": "Index Scan",
+            "Relation Name": "fred_flintstone",
+            "Alias": "fred_flintstone",
+            "Index Name": "fred_flintstone_pkey",
+            "Startup Cost": 0.15,
+            "Total Cost": 8.17,
+            "Plan Rows": 1,
+            "Plan Width": 40,
+            "Actual Startup Time": 0.01,
+            "Actual Total Time": 0.011,
+            "Actual Rows": 0,
+            "Actual Loops": 1
+        },
+        "Planning Time": 0.05,
+        "Execution Time": 0.02
+    }]"#;
+    let explain = Explain::from_json(json).unwrap();
+    assert_eq!(explain.plan.node_type, "Index Scan");
+    assert_eq!(
+        explain.plan.index_name.as_deref(),
+        Some("fred_flintstone_pkey")
+    );
+    assert!(explain.plan.plans.is_empty());
+    assert_eq!(explain.execution_time, Some(0.02));
+}
+
+#[cfg(feature = "tokio-postgres")]
+#[test]
+fn query_should_inline_literals() {
+    let (a, b, c, d, e) = (true, 42_i64, 1.5_f64, None::<i32>, vec![0xde_u8, 0xad]);
+    let f = "it's a \\ '$1'";
+    let (query, params) = query!(
+        "SELECT $a, $b, $c, $d, $e, $f, '$a', \"$a\"",
+        Args { a, b, c, d, e, f }
+    );
+    assert_eq!(
+        query.inline(params).unwrap(),
+        r#"SELECT 'true', '42', '1.5', NULL,  E'\\xdead',  E'it''s a \\ ''$1''', '$1', "$1""#
+    );
+
+    let g = std::collections::HashMap::<String, Option<String>>::new();
+    let (query, params) = query!("SELECT $g", Args { g });
+    assert_eq!(query.inline(params).unwrap_err().name(), "g");
+}
+
+#[test]
+fn identifier_should_validate_and_quote() {
+    use pg_named_args::Identifier;
+
+    let channel = Identifier::new("jobs").unwrap();
+    assert_eq!(channel.as_str(), "jobs");
+    assert_eq!(channel.to_string(), "\"jobs\"");
+    assert_eq!(
+        Identifier::new("a\"; DROP TABLE x; --")
+            .unwrap()
+            .to_string(),
+        "\"a\"\"; DROP TABLE x; --\""
+    );
+
+    assert!(Identifier::new("").is_err());
+    assert!(Identifier::new("a\0b").is_err());
+    assert!(Identifier::new("x".repeat(63)).is_ok());
+    let err = Identifier::new("x".repeat(64)).unwrap_err();
+    assert!(err.to_string().contains("longer than 63 bytes"));
+}
+
+#[test]
+fn batch_should_share_args() {
+    let name = "Fred";
+    let town = "Bedrock";
+    let table = fragment!("residence");
+    let statements = pg_named_args::batch_args!(
+        minify,
+        [
+            "INSERT INTO flintstone(name) VALUES($name)",
+            "INSERT INTO ${table}($[town, name]) VALUES($[..])",
+            "SELECT 1",
+        ],
+        Args { name, town },
+        Sql { table }
+    );
+    assert_eq!(statements[0].0, "INSERT INTO flintstone(name) VALUES($1)");
+    assert_eq!(statements[0].1.len(), 1);
+    assert_eq!(
+        statements[1].0,
+        "INSERT INTO residence(town, name) VALUES($1, $2)"
+    );
+    assert_eq!(statements[1].1.len(), 2);
+    assert_eq!(statements[2].0, "SELECT 1");
+    assert!(statements[2].1.is_empty());
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+fn mock_client_should_record_calls() {
+    use pg_named_args::{Executor, MockClient, MockError};
+    use std::{future::Future, pin::pin, task};
+
+    fn block_on<T>(f: impl Future<Output = T>) -> T {
+        let mut cx = task::Context::from_waker(task::Waker::noop());
+        match pin!(f).poll(&mut cx) {
+            task::Poll::Ready(res) => res,
+            task::Poll::Pending => unreachable!(),
+        }
+    }
+
+    let client = MockClient::<i32>::new();
+    client.push_rows(vec![1, 2]);
+    client.push_error("duplicate key");
+
+    let (id, name) = (7, Some("Fred"));
+    let (query, params) = query!(
+        "UPDATE flintstone SET name = $name WHERE id = $id",
+        Args { id, name }
+    );
+    assert_eq!(block_on(client.execute_named(&query, params)), Ok(2));
+    assert_eq!(
+        block_on(client.query_named(&query, params)),
+        Err(MockError("duplicate key".to_owned()))
+    );
+    assert_eq!(block_on(client.query_named(&query, params)), Ok(vec![]));
+
+    let calls = client.calls();
+    assert_eq!(calls.len(), 3);
+    assert_eq!(calls[0].0, "UPDATE flintstone SET name = $1 WHERE id = $2");
+    assert_eq!(calls[0].1, ["Some(\"Fred\")", "7"]);
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+fn assert_query_eq_should_normalize() {
+    use pg_named_args::assert_query_eq;
+
+    let (query, _) = query!(
+        r"
+        SELECT name , 'Fred  Flintstone'
+        FROM flintstone
+        WHERE id IN ( $a, $b );
+        ",
+        Args { a: 1, b: 2 }
+    );
+    assert_query_eq!(
+        query,
+        "select name, 'Fred  Flintstone' from FLINTSTONE where id in($1,$2);"
+    );
+
+    let res = std::panic::catch_unwind(|| {
+        assert_query_eq!("SELECT 'Fred'", "SELECT 'fred'");
+    });
+    let message = *res.unwrap_err().downcast::<String>().unwrap();
+    assert!(
+        message.ends_with("          ^ first difference"),
+        "{message}"
+    );
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+fn snapshot_should_contain_call_site() {
+    let (name, town) = ("Fred", "Bedrock");
+    let (query, _) = query!(
+        "INSERT INTO flintstone($[name, town]) VALUES($[..])",
+        Args { name, town }
+    );
+    let snapshot = query.snapshot();
+    assert_eq!(query.location(), snapshot.location);
+    assert!(snapshot.location.starts_with("tests/integration/main.rs:"));
+    assert_eq!(
+        snapshot.to_string(),
+        "-- $1: name\n-- $2: town\nINSERT INTO flintstone(name, town) VALUES($1, $2)\n"
+    );
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn pg_test_should_provide_transaction(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::Executor;
+
+    let (query, params) = query!("SELECT $a::int + 1", Args { a: 1 });
+    let rows = txn.query_named(&query, params).await.unwrap();
+    assert_eq!(rows[0].get::<_, i32>(0), 2);
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn insert_batched_should_insert_every_row(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::{insert_batched, Executor};
+
+    txn.batch_execute("CREATE TEMP TABLE numbers (n int, label text)")
+        .await
+        .unwrap();
+    let numbers: Vec<i32> = (0..40000).collect();
+    let label = "number";
+    let count = insert_batched!(
+        txn,
+        batch = &numbers,
+        "INSERT INTO numbers(n, label) VALUES $for(n in $batch) { ($n, $label) }",
+        Args { batch, label }
+    )
+    .await
+    .unwrap();
+    assert_eq!(count, 40000);
+
+    let (query, params) = query!(
+        "SELECT count(*), sum(n) FROM numbers WHERE label = 'number'",
+        Args {}
+    );
+    let row = &txn.query_named(&query, params).await.unwrap()[0];
+    assert_eq!(row.get::<_, i64>(0), 40000);
+    assert_eq!(row.get::<_, i64>(1), 40000 * 39999 / 2);
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn retryable_error_should_be_found_in_the_source_chain(
+    txn: &tokio_postgres::Transaction<'_>,
+) {
+    use pg_named_args::RetryableError;
+    use std::{error::Error, fmt};
+
+    #[derive(Debug)]
+    struct Wrapper(tokio_postgres::Error);
+
+    impl fmt::Display for Wrapper {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("wrapper")
+        }
+    }
+
+    impl Error for Wrapper {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    let err = txn
+        .batch_execute("DO $$ BEGIN RAISE EXCEPTION USING ERRCODE = '40001'; END $$")
+        .await
+        .unwrap_err();
+    assert!(err.is_retryable());
+    let err: Box<dyn Error + Send + Sync> = Box::new(Wrapper(err));
+    assert!(err.is_retryable());
+    let err: Box<dyn Error> = Box::new(Wrapper(err.downcast::<Wrapper>().unwrap().0));
+    assert!(err.is_retryable());
+    let err = pg_named_args::Error::Db(err.downcast::<Wrapper>().unwrap().0);
+    assert!(err.is_retryable());
+}
+
+#[cfg(all(feature = "pg-test", feature = "serde", feature = "test-support"))]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn plan_snapshot_should_compare_the_shape(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::explain::assert_plan_snapshot;
+
+    txn.batch_execute("CREATE TEMP TABLE reports (id int PRIMARY KEY, location text)")
+        .await
+        .unwrap();
+    let path = std::env::temp_dir().join(format!("pg_named_args_plan_{}.txt", std::process::id()));
+    let id = 7;
+    let (query, params) = query!("SELECT * FROM reports WHERE id = $id", Args { id });
+    assert_plan_snapshot(txn, &query, params, &path).await;
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "Index Scan on reports using reports_pkey\n"
+    );
+    assert_plan_snapshot(txn, &query, params, &path).await;
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+#[ignore = "requires a database in DATABASE_URL"]
+fn blocking_client_should_be_an_executor() {
+    use pg_named_args::{block_on, Blocking, Executor};
+
+    async fn add_one<E: Executor>(client: &E) -> Result<Vec<E::Row>, E::Error> {
+        let (query, params) = query!("SELECT $a::int + 1", Args { a: 1 });
+        client.query_named(&query, params).await
+    }
+
+    let url = std::env::var("DATABASE_URL").unwrap();
+    let client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+    let client = Blocking::new(client);
+    let rows = block_on(add_one(&client)).unwrap();
+    assert_eq!(rows[0].get::<_, i32>(0), 2);
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+#[ignore = "requires a database in DATABASE_URL"]
+fn executor_helpers_should_work_with_every_executor() {
+    use pg_named_args::{block_on, Blocking, ExecutorExt, Timed};
+
+    async fn count<E: ExecutorExt>(client: &E) -> Result<i64, pg_named_args::Error> {
+        let (query, params) = query!("SELECT count(*) FROM generate_series(1, $n)", Args { n: 3 });
+        Ok(client.query_one_named(&query, params).await?.try_get(0)?)
+    }
+
+    let url = std::env::var("DATABASE_URL").unwrap();
+    let client = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+    let client = Timed::new(Blocking::new(client), |_, _| {});
+    assert_eq!(block_on(count(&client)).unwrap(), 3);
+    let (query, params) = query!("SELECT 1 WHERE $a", Args { a: true });
+    assert!(block_on(client.exists_named(&query, params)).unwrap());
+}
+
+#[cfg(feature = "postgres")]
+#[test]
+#[ignore = "requires a database in DATABASE_URL"]
+fn router_should_keep_a_cursor_on_one_executor() {
+    use futures_util::TryStreamExt;
+    use pg_named_args::{block_on, Blocking, ExecutorExt, Router};
+
+    let url = std::env::var("DATABASE_URL").unwrap();
+    let mut primary = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+    let mut replica = postgres::Client::connect(&url, postgres::NoTls).unwrap();
+    let router = Router::new(
+        Blocking::new(primary.transaction().unwrap()),
+        vec![Blocking::new(replica.transaction().unwrap())],
+    );
+
+    let (query, params) = query!("SELECT generate_series(1, $n)", Args { n: 5 });
+    let rows = block_on(
+        router
+            .query_cursor_named(&query, params, 2)
+            .try_collect::<Vec<_>>(),
+    );
+    assert_eq!(rows.unwrap().len(), 5);
+}
+
+#[cfg(feature = "deadpool")]
+#[test]
+fn deadpool_objects_should_be_executors() {
+    fn executor<E: pg_named_args::Executor>() {}
+
+    executor::<deadpool_postgres::Object>();
+    executor::<deadpool_postgres::Transaction<'_>>();
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn query_timeout_should_cancel_the_slow_queries(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::TransactionExt;
+    use std::time::Duration;
+
+    let seconds = 0.01;
+    let (query, params) = query!("SELECT pg_sleep($seconds)", Args { seconds });
+    txn.query_timeout_named(&query, params, Duration::from_secs(5))
+        .await
+        .unwrap();
+    let row = txn.query_one("SHOW statement_timeout", &[]).await.unwrap();
+    assert_eq!(row.get::<_, String>(0), "0");
+
+    let seconds = 5.0;
+    let (query, params) = query!("SELECT pg_sleep($seconds)", Args { seconds });
+    let err = txn
+        .query_timeout_named(&query, params, Duration::from_millis(10))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.code(),
+        Some(&tokio_postgres::error::SqlState::QUERY_CANCELED)
+    );
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn query_one_should_report_the_number_of_rows(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::{Error, ExecutorExt};
+
+    let count = 1;
+    let (query, params) = query!("SELECT generate_series(1, $count)", Args { count });
+    let row = txn.query_one_named(&query, params).await.unwrap();
+    assert_eq!(row.get::<_, i32>(0), 1);
+
+    let count = 0;
+    let (query, params) = query!("SELECT generate_series(1, $count)", Args { count });
+    let err = txn.query_one_named(&query, params).await.unwrap_err();
+    assert!(matches!(err, Error::NotFound));
+    let row = txn.query_opt_named(&query, params).await.unwrap();
+    assert!(row.is_none());
+
+    let count = 2;
+    let (query, params) = query!("SELECT generate_series(1, $count)", Args { count });
+    let err = txn.query_opt_named(&query, params).await.unwrap_err();
+    assert!(matches!(err, Error::TooManyRows));
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn insert_returning_should_return_the_key(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::insert_returning;
+
+    txn.batch_execute(
+        "CREATE TEMP TABLE stations (id serial PRIMARY KEY, code text UNIQUE, name text)",
+    )
+    .await
+    .unwrap();
+    let code = "SE";
+    let name = "Stockholm";
+    let id: i32 = insert_returning!(
+        txn,
+        "INSERT INTO stations ($[code, name]) VALUES ($[..])",
+        Args { code, name }
+    )
+    .await
+    .unwrap();
+    assert_eq!(id, 1);
+
+    let name = "Göteborg";
+    let code: String = insert_returning!(
+        txn,
+        returning = code,
+        "INSERT INTO stations (code, name) VALUES ('GB', $name) -- the code is fixed",
+        Args { name }
+    )
+    .await
+    .unwrap();
+    assert_eq!(code, "GB");
+
+    let res: Result<i32, _> = insert_returning!(
+        txn,
+        "INSERT INTO stations ($[code, name]) VALUES ($[..]) ON CONFLICT DO NOTHING",
+        Args { code, name }
+    )
+    .await;
+    assert!(matches!(res, Err(pg_named_args::Error::NotFound)));
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn upsert_should_update_the_existing_row(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::upsert_args;
+
+    txn.batch_execute(
+        "CREATE TEMP TABLE reports (location text, time text, report text, PRIMARY KEY (location, time))",
+    )
+    .await
+    .unwrap();
+    let (location, time) = ("sweden", "monday");
+    for report in ["sunny", "rainy"] {
+        let (query, params) = upsert_args!(
+            conflict(location, time),
+            r"INSERT INTO reports ($[location, time, report]) VALUES ($[..])",
+            Args {
+                location,
+                time,
+                report
+            }
+        );
+        txn.execute(query, params).await.unwrap();
+    }
+    let rows = txn.query("SELECT report FROM reports", &[]).await.unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get::<_, &str>(0), "rainy");
+}
+
+#[cfg(feature = "pg-test")]
+#[pg_named_args::pg_test]
+#[ignore = "requires a database in DATABASE_URL"]
+async fn copy_merge_should_upsert_the_rows(txn: &tokio_postgres::Transaction<'_>) {
+    use pg_named_args::{Executor, Identifier, StagingTable, TransactionExt};
+    use tokio_postgres::types::{ToSql, Type};
+
+    txn.batch_execute("CREATE TEMP TABLE reports (location text PRIMARY KEY, temperature float8)")
+        .await
+        .unwrap();
+    txn.batch_execute("INSERT INTO reports VALUES ('sweden', 1.5)")
+        .await
+        .unwrap();
+    let staging = StagingTable::new(Identifier::new("staged_reports").unwrap())
+        .column(Identifier::new("location").unwrap(), Type::TEXT)
+        .column(Identifier::new("temperature").unwrap(), Type::FLOAT8);
+    let reports = [("sweden", -3.5), ("norway", 2.0)];
+    let rows = reports
+        .iter()
+        .map(|(location, temperature)| [location as &(dyn ToSql + Sync), temperature]);
+    let (merge, params) = query!(
+        r"
+        INSERT INTO reports SELECT * FROM staged_reports
+        ON CONFLICT (location) DO UPDATE SET temperature = excluded.temperature
+        ",
+        Args {}
+    );
+    let count = txn
+        .copy_merge_named(&staging, rows, &merge, params)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+
+    let (query, params) = query!(
+        "SELECT sum(temperature), to_regclass('staged_reports') IS NULL FROM reports",
+        Args {}
+    );
+    let row = &txn.query_named(&query, params).await.unwrap()[0];
+    assert_eq!(row.get::<_, f64>(0), -1.5);
+    assert!(row.get::<_, bool>(1));
+}
+
+#[test]
+fn group_should_support_quoted_identifiers() {
+    let (id, order_total) = (1, 2.5);
+    let (query, _) = query_args!(
+        r#"INSERT INTO orders($[id, "Order Total",]) VALUES($[..])"#,
+        Args { id, order_total }
+    );
+    assert_eq!(
+        query,
+        r#"INSERT INTO orders(id, "Order Total") VALUES($1, $2)"#
+    );
+}
+
+#[test]
+fn like_modifier_should_escape_value() {
+    let term = "50%";
+    let (query, params) = query!(
+        "SELECT * FROM reports WHERE a LIKE $term~starts_with OR b ILIKE $term~contains",
+        Args { term }
+    );
+    let pattern = r"replace(replace(replace($1, '\', '\\'), '%', '\%'), '_', '\_')";
+    assert_eq!(
+        query.sql(),
+        format!("SELECT * FROM reports WHERE a LIKE ({pattern} || '%') OR b ILIKE ('%' || {pattern} || '%')")
+    );
+    assert_eq!(params.len(), 1);
+    assert_eq!(pg_named_args::escape_like(r"a\b_c"), r"a\\b\_c");
+}
+
+#[test]
+fn exists_should_wrap_query() {
+    let name = "Fred";
+    let (query, params) = pg_named_args::exists_args!(
+        dedent,
+        r"
+        SELECT 1
+        FROM flintstone
+        WHERE name = $name;
+        ",
+        Args { name }
+    );
+    assert_eq!(
+        query,
+        "SELECT EXISTS(SELECT 1\nFROM flintstone\nWHERE name = $1)"
+    );
+    assert_eq!(params.len(), 1);
+
+    let (query, _) = query!(
+        "SELECT 1 FROM flintstone WHERE name = $name;",
+        Args { name }
+    );
+    let exists = query.exists();
+    assert_eq!(
+        exists.sql(),
+        "SELECT EXISTS(SELECT 1 FROM flintstone WHERE name = $1)"
+    );
+    assert_eq!(exists.fingerprint(), query.fingerprint());
+}
+
+#[test]
+fn wrapped_queries_should_close_after_a_trailing_comment() {
+    let id = 1;
+    let (query, _) =
+        pg_named_args::exists_args!("SELECT 1 FROM t WHERE id = $id -- trailing", Args { id });
+    assert_eq!(
+        query,
+        "SELECT EXISTS(SELECT 1 FROM t WHERE id = $1 -- trailing\n)"
+    );
+
+    let (query, _) = query!("SELECT 1 FROM t WHERE id = $id -- trailing", Args { id });
+    assert_eq!(
+        query.exists().sql(),
+        "SELECT EXISTS(SELECT 1 FROM t WHERE id = $1 -- trailing\n)"
+    );
+    assert_eq!(
+        query.copy_out().sql(),
+        "COPY (SELECT 1 FROM t WHERE id = $1 -- trailing\n) TO STDOUT (FORMAT BINARY)"
+    );
+}
+
+#[test]
+fn schema_should_be_quoted() {
+    use pg_named_args::SchemaName;
+
+    let schema = SchemaName::new("tenant \"a\"").unwrap();
+    let filter = fragment!("AND active");
+    let id = 1;
+    let (query, params) = query!(
+        r#"SELECT * FROM $schema.orders JOIN $schema."Items" ON true WHERE id = $id ${filter}"#,
+        Args { id },
+        Sql { schema, filter }
+    );
+    assert_eq!(
+        query.sql(),
+        r#"SELECT * FROM "tenant ""a""".orders JOIN "tenant ""a"""."Items" ON true WHERE id = $1 AND active"#
+    );
+    assert_eq!(params.len(), 1);
+    assert_eq!(
+        query.fingerprint(),
+        fingerprint(r#"SELECT * FROM {}.orders JOIN {}."Items" ON true WHERE id = $1 {}"#)
+    );
+
+    let err = SchemaName::allowed("public", &["tenant_a"]).unwrap_err();
+    assert!(err.to_string().contains("allow-list"));
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn composite_array_should_bind_rows() {
+    use pg_named_args::postgres_types::{self, Field, IsNull, Kind, ToSql, Type};
+
+    #[derive(Debug, ToSql)]
+    #[postgres(name = "flintstone_row")]
+    struct FlintstoneRow {
+        name: String,
+        age: i32,
+    }
+
+    let rows = vec![
+        FlintstoneRow {
+            name: "Fred".to_owned(),
+            age: 35,
+        },
+        FlintstoneRow {
+            name: "Wilma".to_owned(),
+            age: 33,
+        },
+    ];
+    let (query, params) = query!(
+        "INSERT INTO flintstone(name, age) SELECT * FROM UNNEST($rows::flintstone_row[])",
+        Args { rows }
+    );
+    assert_eq!(
+        query.sql(),
+        "INSERT INTO flintstone(name, age) SELECT * FROM UNNEST($1::flintstone_row[])"
+    );
+
+    let fields = vec![
+        Field::new("name".to_owned(), Type::TEXT),
+        Field::new("age".to_owned(), Type::INT4),
+    ];
+    let row = Type::new(
+        "flintstone_row".to_owned(),
+        0,
+        Kind::Composite(fields),
+        "public".to_owned(),
+    );
+    let array = Type::new(
+        "_flintstone_row".to_owned(),
+        0,
+        Kind::Array(row),
+        "public".to_owned(),
+    );
+    let mut buf = bytes::BytesMut::new();
+    let res = params[0].to_sql_checked(&array, &mut buf).unwrap();
+    assert!(matches!(res, IsNull::No));
+}
+
+#[test]
+fn prepare_should_return_statement_pair() {
+    let (name, age) = ("Fred", 35);
+    let (prepare, execute, params) = query_args!(
+        prepare(update_age),
+        "UPDATE flintstone SET age = $age WHERE name = $name",
+        Args { name, age }
+    );
+    assert_eq!(
+        prepare,
+        "PREPARE update_age AS UPDATE flintstone SET age = $1 WHERE name = $2"
+    );
+    assert_eq!(execute, "EXECUTE update_age($1, $2)");
+    assert_eq!(params.len(), 2);
+
+    let (prepare, execute, _) = query!(
+        prepare(count),
+        "SELECT count(*) FROM flintstone WHERE name = $name",
+        Args { name }
+    );
+    assert_eq!(
+        prepare.sql(),
+        "PREPARE count AS SELECT count(*) FROM flintstone WHERE name = $1"
+    );
+    assert_eq!(execute.sql(), "EXECUTE count($1)");
+    assert_eq!(execute.names(), ["name"]);
+    assert_eq!(execute.fingerprint(), fingerprint("EXECUTE count($1)"));
+    assert_eq!(
+        prepare.fingerprint(),
+        fingerprint("SELECT count(*) FROM flintstone WHERE name = $1")
+    );
+}
+
+#[test]
+fn query_should_detect_statement_kind() {
+    use pg_named_args::StatementKind;
+
+    let a = 1;
+    let tests = [
+        (query!("SELECT $a", Args { a }).0, StatementKind::Select),
+        (
+            query!("-- comment\n(VALUES ($a))", Args { a }).0,
+            StatementKind::Select,
+        ),
+        (
+            query!("insert into t(a) values($a)", Args { a }).0,
+            StatementKind::Insert,
+        ),
+        (
+            query!(
+                "WITH x AS (SELECT $a) UPDATE t SET a = x.a FROM x",
+                Args { a }
+            )
+            .0,
+            StatementKind::Update,
+        ),
+        (
+            query!("DELETE FROM t WHERE a = $a", Args { a }).0,
+            StatementKind::Delete,
+        ),
+        (query!("VACUUM t", Args {}).0, StatementKind::Other),
+    ];
+    for (query, kind) in tests {
+        assert_eq!(query.kind(), kind, "{query}");
+    }
+
+    let (query, _) = query!("SELECT $a", Args { a });
+    assert_eq!(query.exists().kind(), StatementKind::Select);
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+fn router_should_route_by_statement_kind() {
+    use pg_named_args::{MockClient, Router};
+
+    let router = Router::new(
+        MockClient::<()>::new(),
+        vec![MockClient::new(), MockClient::new()],
+    )
+    .read_your_writes(true);
+    let a = 1;
+    let (select, _) = query!("SELECT $a", Args { a });
+    let (update, _) = query!("UPDATE t SET a = $a", Args { a });
+
+    let first = router.route(&select) as *const _;
+    let second = router.route(&select) as *const _;
+    assert_ne!(first, second);
+    assert!(!std::ptr::eq(first, router.primary()));
+
+    assert!(std::ptr::eq(router.route(&update), router.primary()));
+    assert!(std::ptr::eq(router.route(&select), router.primary()));
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+fn timed_should_observe_every_execution() {
+    use pg_named_args::{Executor, MockClient, Timed};
+    use std::{future::Future, pin::pin, sync::Mutex, task};
+
+    fn block_on<T>(f: impl Future<Output = T>) -> T {
+        let mut cx = task::Context::from_waker(task::Waker::noop());
+        match pin!(f).poll(&mut cx) {
+            task::Poll::Ready(res) => res,
+            task::Poll::Pending => unreachable!(),
+        }
+    }
+
+    let observed = Mutex::new(vec![]);
+    let client = Timed::new(MockClient::<i32>::new(), |query, _| {
+        observed.lock().unwrap().push(query.fingerprint());
+    });
+    client.inner().push_error("timeout");
+
+    let a = 1;
+    let (select, params) = query!("SELECT $a", Args { a });
+    assert!(block_on(client.query_named(&select, params)).is_err());
+    let (update, params) = query!("UPDATE t SET a = $a", Args { a });
+    assert_eq!(block_on(client.execute_named(&update, params)), Ok(0));
+    assert_eq!(
+        *observed.lock().unwrap(),
+        [select.fingerprint(), update.fingerprint()]
+    );
+}
+
+#[cfg(feature = "tokio-postgres")]
+#[test]
+fn copy_out_should_wrap_the_inlined_query() {
+    let location = "sweden";
+    let (query, params) = query!(
+        "SELECT * FROM weather_reports WHERE location = $location;",
+        Args { location }
+    );
+    assert_eq!(
+        query.copy_out().inline(params).unwrap(),
+        "COPY (SELECT * FROM weather_reports WHERE location = 'sweden') TO STDOUT (FORMAT BINARY)"
+    );
+}
+
+#[test]
+fn csv_copy_should_quote_the_options_and_fields() {
+    use pg_named_args::{CsvCopy, CsvRecord, CsvRow, Identifier, SchemaName};
+
+    struct Row(i32, Option<&'static str>);
+
+    impl CsvRow for Row {
+        fn write_csv(&self, record: &mut CsvRecord<'_>) {
+            record.field(self.0).option(self.1);
+        }
+    }
+
+    let copy = CsvCopy::new(
+        Identifier::new("t").unwrap(),
+        vec![
+            Identifier::new("id").unwrap(),
+            Identifier::new("na\"me").unwrap(),
+        ],
+    )
+    .schema(SchemaName::new("tenant").unwrap())
+    .null("NULL")
+    .delimiter(';')
+    .header(true);
+    assert_eq!(
+        copy.sql(),
+        r#"COPY "tenant"."t" ("id", "na""me") FROM STDIN (FORMAT CSV, DELIMITER ';', NULL 'NULL', HEADER true)"#
+    );
+
+    let mut csv = String::new();
+    copy.write_rows(
+        &mut csv,
+        [
+            Row(1, Some("a;b")),
+            Row(2, Some("NULL")),
+            Row(3, None),
+            Row(4, Some("say \"hi\"\n")),
+        ],
+    );
+    assert_eq!(
+        csv,
+        "id;\"na\"\"me\"\n1;\"a;b\"\n2;\"NULL\"\n3;NULL\n4;\"say \"\"hi\"\"\n\"\n"
+    );
+}
+
+#[test]
+fn advisory_lock_should_hash_the_names() {
+    use pg_named_args::AdvisoryLock;
+
+    const LOCK: AdvisoryLock = AdvisoryLock::pair("import", "reports");
+    assert_eq!(LOCK.class(), Some("import"));
+    assert_eq!(LOCK.name(), "reports");
+    assert_eq!(LOCK, AdvisoryLock::pair("import", "reports"));
+    assert_ne!(LOCK, AdvisoryLock::pair("export", "reports"));
+    assert_ne!(AdvisoryLock::new("reports"), AdvisoryLock::new("stations"));
+}
+
+#[cfg(feature = "tokio-postgres")]
+#[test]
+fn migrations_should_inline_the_arguments() {
+    use pg_named_args::Migrations;
+
+    let location = "sweden";
+    let migrations = Migrations::new().add(
+        1,
+        "fill the locations",
+        [query!(
+            "UPDATE t SET location = $location",
+            Args { location }
+        )],
+    );
+    assert!(migrations.is_ok());
+
+    let point = std::time::SystemTime::UNIX_EPOCH;
+    let migrations = Migrations::new().add(
+        1,
+        "fill the times",
+        [query!("UPDATE t SET time = $point", Args { point })],
+    );
+    assert_eq!(migrations.unwrap_err().name(), "point");
+}
+
+#[cfg(feature = "tokio-postgres")]
+#[test]
+#[should_panic = "the migration 1 should come after 2"]
+fn migrations_should_be_in_order() {
+    use pg_named_args::Migrations;
+
+    let _ = Migrations::new()
+        .add(2, "second", [query!("SELECT 2", Args {})])
+        .and_then(|x| x.add(1, "first", [query!("SELECT 1", Args {})]));
+}
+
+#[test]
+fn template_option_should_keep_the_named_query() {
+    let (location, limit) = ("sweden", 10);
+    let (query, _) = query!(
+        template,
+        "SELECT * FROM reports WHERE location = $location LIMIT $limit",
+        Args { location, limit }
+    );
+    assert_eq!(
+        query.template(),
+        Some("SELECT * FROM reports WHERE location = $location LIMIT $limit")
+    );
+    assert_eq!(query.exists().template(), query.template());
+
+    let (query, _) = query!("SELECT $limit", Args { limit });
+    assert_eq!(query.template(), None);
+}
+
+#[test]
+fn expression_arguments_should_be_bound() {
+    struct User {
+        id: i32,
+        name: &'static str,
+    }
+    let user = User {
+        id: 1,
+        name: "Fred",
+    };
+    let (query, params) = query!(
+        template,
+        "UPDATE users SET name = $(user.name) WHERE id = $(user.id) AND id <> $( user.id )",
+    );
+    assert_eq!(
+        query.sql(),
+        "UPDATE users SET name = $1 WHERE id = $2 AND id <> $2"
+    );
+    assert_eq!(query.names(), ["user.name", "user.id"]);
+    assert_eq!(params.len(), 2);
+    assert_eq!(
+        query.template(),
+        Some("UPDATE users SET name = $(user.name) WHERE id = $(user.id) AND id <> $(user.id)")
+    );
+
+    let ids = [1, 2];
+    let (query, params) = query_args!(
+        "SELECT * FROM users WHERE id = ANY($(&ids[..])) AND name = $name",
+        Args { name: "Fred" }
+    );
+    assert_eq!(
+        query,
+        "SELECT * FROM users WHERE id = ANY($1) AND name = $2"
+    );
+    assert_eq!(params.len(), 2);
+}
+
+#[test]
+fn capture_should_bind_the_locals() {
+    let (location, time) = ("sweden", "monday");
+    let (query, params) = query_args!(
+        capture,
+        "INSERT INTO weather_reports($[location, time]) VALUES($[..]) RETURNING $location",
+    );
+    assert_eq!(
+        query,
+        "INSERT INTO weather_reports(location, time) VALUES($1, $2) RETURNING $1"
+    );
+    assert_eq!(params.len(), 2);
+
+    let verbose = true;
+    let (query, params) = query!(
+        capture,
+        "SELECT * FROM weather_reports WHERE location = $location $when(verbose) { AND report = $report }",
+        Args { report: "rainy" }
+    );
+    assert_eq!(
+        query.sql(),
+        "SELECT * FROM weather_reports WHERE location = $1  AND report = $2 "
+    );
+    assert_eq!(format!("{:?}", params[1]), format!("{:?}", "rainy"));
+}
+
+#[test]
+fn json_object_should_bind_the_fields() {
+    let (name, email, id) = ("Fred", "fred@bedrock.com", 1);
+    let (query, params) = query_args!(
+        "SELECT $json{name, email} AS payload FROM users WHERE id = $id AND name = $name",
+        Args { name, email, id }
+    );
+    assert_eq!(
+        query,
+        "SELECT jsonb_build_object('name', $1, 'email', $2) AS payload FROM users WHERE id = $3 AND name = $1"
+    );
+    assert_eq!(params.len(), 3);
+}
+
+#[cfg(feature = "test-support")]
+#[test]
+fn hooked_should_call_the_middleware() {
+    use pg_named_args::{Executor, Hooked, Middleware, MockClient, Query};
+    use std::{fmt, future::Future, pin::pin, sync::Mutex, task, time::Duration};
+
+    fn block_on<T>(f: impl Future<Output = T>) -> T {
+        let mut cx = task::Context::from_waker(task::Waker::noop());
+        match pin!(f).poll(&mut cx) {
+            task::Poll::Ready(res) => res,
+            task::Poll::Pending => unreachable!(),
+        }
+    }
+
+    #[derive(Default)]
+    struct Record(Mutex<Vec<String>>);
+
+    impl Middleware for Record {
+        fn before_execute(&self, query: &Query) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("before {:?}", query.names()));
+        }
+
+        fn after_execute(
+            &self,
+            _query: &Query,
+            _elapsed: Duration,
+            result: Result<u64, &dyn fmt::Display>,
+        ) {
+            let result = result.map_err(ToString::to_string);
+            self.0.lock().unwrap().push(format!("after {result:?}"));
+        }
+    }
+
+    let client = Hooked::new(MockClient::<i32>::new(), Record::default());
+    client.inner().push_rows(vec![1, 2]);
+    client.inner().push_error("timeout");
+
+    let a = 1;
+    let (select, params) = query!("SELECT $a", Args { a });
+    assert_eq!(
+        block_on(client.query_named(&select, params)),
+        Ok(vec![1, 2])
+    );
+    let (update, params) = query!("UPDATE t SET a = $a", Args { a });
+    assert!(block_on(client.execute_named(&update, params)).is_err());
+    assert_eq!(
+        *client.middleware().0.lock().unwrap(),
+        [
+            r#"before ["a"]"#,
+            "after Ok(2)",
+            r#"before ["a"]"#,
+            r#"after Err("timeout")"#,
+        ]
+    );
+}
+
+#[cfg(feature = "otel")]
+#[test]
+fn otel_statement_should_be_collapsed_and_truncated() {
+    use pg_named_args::OtelAttributes;
+
+    let statement = "SELECT 'a  b',\n  \"c  d\"\nFROM t";
+    assert_eq!(
+        OtelAttributes::new().statement(statement),
+        "SELECT 'a  b', \"c  d\" FROM t"
+    );
+    assert_eq!(
+        OtelAttributes::new()
+            .max_statement_len(9)
+            .statement(statement),
+        "SELECT 'a..."
+    );
+    assert_eq!(
+        OtelAttributes::new().max_statement_len(2).statement("é é"),
+        "é..."
+    );
+}
+
+#[test]
+fn pretty_sql_should_only_change_the_layout() {
+    let (query, _) = query!(
+        "with recent as (select * from reports where at > now() - '1 day'::interval)
+        select left(name, 3), 'from  where' as \"select\", extract(year from at)
+        from recent left join stations using (station_id)
+        -- the latest first
+        order by at desc; delete from reports",
+        Args {}
+    );
+    assert_eq!(
+        query.pretty_sql(),
+        "WITH recent AS (SELECT *
+  FROM reports
+  WHERE at > now() - '1 day'::interval)
+SELECT left(name, 3), 'from  where' AS \"select\", extract(year FROM at)
+FROM recent
+LEFT JOIN stations USING (station_id) -- the latest first
+ORDER BY at DESC;
+DELETE
+FROM reports"
+    );
+}
+
+#[test]
+fn nest_should_group_the_children_by_the_parent() {
+    let rows = [
+        ("fred", Some("pebbles")),
+        ("barney", Some("bamm-bamm")),
+        ("dino", None),
+        ("fred", Some("pebbles")),
+    ];
+    let parents = nest(rows, |row| row.0, |row| row.0.len(), |row| row.1);
+    assert_eq!(
+        parents,
+        [
+            (4, vec!["pebbles", "pebbles"]),
+            (6, vec!["bamm-bamm"]),
+            (4, vec![])
+        ]
+    );
+}
+
+#[test]
+fn fragment_should_interpolate_fragments() {
+    let col = fragment!("created_at");
+    let descending = true;
+    let dir = match descending {
+        true => fragment!("DESC"),
+        false => fragment!("ASC"),
+    };
+    let order_by = fragment!("ORDER BY {col} {dir}, {{id}}", col, dir = dir.clone());
+    assert_eq!(order_by.get(), "ORDER BY created_at DESC, {id}");
+
+    let (query, _) = query!(
+        "SELECT * FROM reports ${order_by}",
+        Args {},
+        Sql { order_by }
+    );
+    assert_eq!(
+        query.sql(),
+        "SELECT * FROM reports ORDER BY created_at DESC, {id}"
+    );
+    assert_eq!(dir.get(), "DESC");
+}
+
+#[test]
+fn fragment_defaults_should_be_used_when_left_out() {
+    let (query, _) = query!(
+        "SELECT * FROM reports ${filter = WHERE true} ${order_by = ORDER BY id}",
+        Args {}
+    );
+    assert_eq!(query.sql(), "SELECT * FROM reports WHERE true ORDER BY id");
+
+    let order_by = fragment!("ORDER BY created_at DESC");
+    let (query, _) = query!(
+        "SELECT * FROM reports ${filter = WHERE true} ${order_by = ORDER BY id}",
+        Args {},
+        Sql { order_by }
+    );
+    assert_eq!(
+        query.sql(),
+        "SELECT * FROM reports WHERE true ORDER BY created_at DESC"
+    );
+}
+
+#[test]
+fn fragment_should_extend_groups() {
+    let (a, b) = (1, 2);
+    let tests = [
+        (
+            fragment!("created_by, created_at"),
+            fragment!("current_user, now()"),
+            "INSERT INTO reports(a, b, created_by, created_at) VALUES($1, $2, current_user, now())",
+        ),
+        (
+            fragment!(""),
+            fragment!(""),
+            "INSERT INTO reports(a, b) VALUES($1, $2)",
+        ),
+    ];
+    for (audit, audit_values, expected) in tests {
+        let (query, _) = query!(
+            "INSERT INTO reports($[a, b, ${audit}]) VALUES($[.., ${audit_values}])",
+            Args { a, b },
+            Sql {
+                audit,
+                audit_values
+            }
+        );
+        assert_eq!(query.sql(), expected);
+    }
+}
+
+#[test]
+fn optional_columns_should_only_be_written_when_some() {
+    let id = 7;
+    let tests = [
+        (
+            Some("sunny"),
+            Some(21.5),
+            "UPDATE reports SET (report, temperature) = ROW($2, $3) WHERE id = $1",
+            3,
+        ),
+        (
+            None,
+            Some(21.5),
+            "UPDATE reports SET (temperature) = ROW($2) WHERE id = $1",
+            2,
+        ),
+    ];
+    for (report, temperature, expected, params) in tests {
+        let (query, args) = query!(
+            "UPDATE reports SET ($[report?, temperature?]) = ROW($[..]) WHERE id = $id",
+            Args {
+                id,
+                report,
+                temperature
+            }
+        );
+        assert_eq!(query.sql(), expected);
+        assert_eq!(args.len(), params);
+        assert_eq!(query.names(), ["id"]);
+    }
+
+    let (location, report) = ("sweden", None::<&str>);
+    let (query, args) = query!(
+        "INSERT INTO reports($[location, report?]) VALUES($[..])",
+        Args { location, report }
+    );
+    assert_eq!(query.sql(), "INSERT INTO reports(location) VALUES($1)");
+    assert_eq!(args.len(), 1);
+}
+
+#[test]
+fn fragment_kinds_should_fill_their_slots() {
+    use pg_named_args::{IdentFragment, Identifier, OrderByFragment, WhereFragment};
+
+    let column = IdentFragment::new(&Identifier::new("Location").unwrap());
+    let filter = WhereFragment::new(fragment!("verified"));
+    let (query, _) = query!(
+        "SELECT ${column: ident} FROM reports WHERE ${filter: where} ORDER BY ${order_by: order_by = id}",
+        Args {},
+        Sql { column, filter }
+    );
+    assert_eq!(
+        query.sql(),
+        r#"SELECT "Location" FROM reports WHERE verified ORDER BY id"#
+    );
+
+    let order_by = OrderByFragment::new(fragment!("time DESC"));
+    let (query, _) = query!(
+        "SELECT * FROM reports ORDER BY ${order_by: order_by}",
+        Args {},
+        Sql { order_by }
+    );
+    assert_eq!(query.sql(), "SELECT * FROM reports ORDER BY time DESC");
+}
+
+#[test]
+fn where_clause_should_leave_out_empty_fragments() {
+    let location = "sweden";
+    let tests = [
+        (fragment!(""), fragment!(""), "SELECT * FROM reports "),
+        (
+            fragment!("verified"),
+            fragment!(""),
+            "SELECT * FROM reports WHERE (verified)",
+        ),
+        (
+            fragment!("verified"),
+            fragment!("a OR b"),
+            "SELECT * FROM reports WHERE (verified) AND (a OR b)",
+        ),
+    ];
+    for (verified, other, expected) in tests {
+        let (query, _) = query!(
+            "SELECT * FROM reports $where{ ${verified} AND ${other} }",
+            Args {},
+            Sql { verified, other }
+        );
+        assert_eq!(query.sql(), expected);
+    }
+
+    let verified = fragment!("");
+    let (query, args) = query!(
+        "SELECT * FROM reports $where{ location = $location AND ${verified} AND (a AND b) }",
+        Args { location },
+        Sql { verified }
+    );
+    assert_eq!(
+        query.sql(),
+        "SELECT * FROM reports WHERE (location = $1) AND ((a AND b))"
+    );
+    assert_eq!(args.len(), 1);
+
+    // the fragment restricts both sides of the `OR`
+    let tenant = fragment!("tenant_id = 3");
+    let (query, args) = query!(
+        "SELECT * FROM reports $where{ location = $location OR verified AND ${tenant} }",
+        Args { location },
+        Sql { tenant }
+    );
+    assert_eq!(
+        query.sql(),
+        "SELECT * FROM reports WHERE (location = $1 OR verified) AND (tenant_id = 3)"
+    );
+    assert_eq!(args.len(), 1);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn query_should_serialize_its_information() {
+    let id = 1;
+    let (query, _) = query!("SELECT * FROM reports WHERE id = $id", Args { id });
+    let json = serde_json::to_value(&query).unwrap();
+    assert_eq!(json["sql"], "SELECT * FROM reports WHERE id = $1");
+    assert_eq!(json["fingerprint"], query.fingerprint());
+    assert_eq!(json["names"], serde_json::json!(["id"]));
+    assert_eq!(json["location"], query.location());
+    assert_eq!(json["kind"], "select");
+    assert_eq!(json["template"], serde_json::Value::Null);
+}
+
+#[test]
+fn buffer_should_be_reused_for_the_parameters() {
+    let rows = [("sweden", "sunny"), ("norway", "rainy")];
+    let mut buffer = Vec::new();
+    for row in &rows {
+        let (query, args) = query_args!(
+            buffer(&mut buffer),
+            "INSERT INTO reports(location, report) VALUES($location, $report)",
+            Args {
+                location: row.0,
+                report: row.1
+            }
+        );
+        assert_eq!(
+            query,
+            "INSERT INTO reports(location, report) VALUES($1, $2)"
+        );
+        assert_eq!(args.len(), 2);
+    }
+    assert_eq!(buffer.len(), 2);
+
+    let reports = ["sunny", "rainy"];
+    let (query, args) = query!(
+        buffer(&mut buffer),
+        "INSERT INTO reports(report) VALUES $for(r in $reports) { ($r) }",
+        Args { reports: &reports }
+    );
+    assert_eq!(
+        query.sql(),
+        "INSERT INTO reports(report) VALUES  ($1) , ($2) "
+    );
+    assert_eq!(args.len(), 2);
+
+    let reports: [&str; 0] = [];
+    let (_, args) = query_args!(
+        buffer(&mut buffer),
+        "SELECT * FROM reports WHERE report IN ($for(r in $reports) { $r })",
+        Args { reports: &reports }
+    );
+    assert!(args.is_empty());
+}
+
+#[test]
+fn array_should_have_the_number_of_parameters_as_length() {
+    fn execute<const N: usize>(
+        _: &str,
+        params: [&(dyn pg_named_args::postgres_types::ToSql + Sync); N],
+    ) -> usize {
+        params.len()
+    }
+
+    let location = "sweden";
+    let (query, args) = query_args!(
+        array,
+        "SELECT * FROM reports WHERE location = $location OR source = $location || $suffix",
+        Args {
+            location,
+            suffix: String::from("-station")
+        }
+    );
+    let args: [_; 2] = args;
+    assert_eq!(execute(query, args), 2);
+
+    let (query, args) = query!(array, "SELECT 1 WHERE true", Args {});
+    assert_eq!(query.sql(), "SELECT 1 WHERE true");
+    assert_eq!(args.len(), 0);
+}
+
+#[test]
+fn max_rows_should_cap_the_selects() {
+    let location = "sweden";
+    let (query, _) = query_args!(
+        max_rows(100),
+        "SELECT * FROM reports WHERE location = $location",
+        Args { location }
+    );
+    assert_eq!(query, "SELECT * FROM reports WHERE location = $1 LIMIT 100");
+
+    let limit = 500_i64;
+    let (query, _) = query_args!(
+        max_rows(100),
+        "SELECT * FROM reports LIMIT $limit",
+        Args { limit }
+    );
+    assert_eq!(query, "SELECT * FROM reports LIMIT LEAST($1, 100::bigint)");
+
+    let (query, _) = query_args!(
+        max_rows(100),
+        "DELETE FROM reports WHERE location = $location",
+        Args { location }
+    );
+    assert_eq!(query, "DELETE FROM reports WHERE location = $1");
+}
+
+#[cfg(feature = "provenance")]
+#[test]
+fn fragment_origins_should_be_recorded() {
+    use pg_named_args::{Fragment, WhereFragment};
+
+    let order_by = fragment!("ORDER BY time DESC");
+    let origin = format!("tests/integration/main.rs:{} (integration)", line!() - 1);
+    assert_eq!(order_by.origin(), Some(&*origin));
+    let filter = WhereFragment::new(fragment!("verified"));
+    let (query, _) = query!(
+        "SELECT * FROM reports WHERE ${filter: where} ${order_by} ${limit}",
+        Args {},
+        Sql {
+            filter,
+            order_by,
+            limit: Fragment::default()
+        }
+    );
+    assert_eq!(query.fragment_origins().len(), 2);
+    assert_eq!(query.fragment_origins()[1], origin);
+}
+
+#[test]
+fn membership_modifiers_should_choose_the_form_of_the_list() {
+    let ids = vec![1, 2, 3];
+    let (query, _) = query_args!(
+        "SELECT * FROM reports WHERE id = $ids~any OR parent = $ids~unnest",
+        Args { ids: &ids }
+    );
+    assert_eq!(
+        query,
+        "SELECT * FROM reports WHERE id = ANY($1) OR parent = ANY(SELECT unnest($1))"
+    );
+
+    let tests = [
+        (
+            vec![1, 2],
+            "SELECT * FROM reports WHERE id = ANY($1) AND verified = $2",
+        ),
+        (
+            vec![1, 2, 3],
+            "SELECT * FROM reports WHERE id = ANY(SELECT unnest($1)) AND verified = $2",
+        ),
+    ];
+    for (ids, expected) in tests {
+        let (query, args) = query!(
+            unnest_above(2),
+            "SELECT * FROM reports WHERE id = $ids~in AND verified = $verified",
+            Args {
+                ids: &ids,
+                verified: true
+            }
+        );
+        assert_eq!(query.sql(), expected);
+        assert_eq!(query.names(), ["ids", "verified"]);
+        assert_eq!(args.len(), 2);
+    }
+}
+
+#[test]
+fn values_should_be_separate_from_the_columns() {
+    use pg_named_args::values_args;
+
+    let location = "sweden";
+    let order_total = 10;
+    let (columns, values, params) = values_args!(
+        r#"
+        $[
+            location,
+            "Order Total", -- in cents
+        ]
+        "#,
+        Args {
+            location,
+            order_total
+        }
+    );
+    assert_eq!(columns, r#"location, "Order Total""#);
+    assert_eq!(values, "$1, $2");
+    assert_eq!(params.len(), 2);
+}
+
+#[test]
+fn adjacent_literals_should_be_concatenated() {
+    let location = "sweden";
+    let (query, params) = query_args!(
+        "SELECT report FROM weather_reports ",
+        r"WHERE location = $location ",
+        "AND time = $(\"monday\")",
+        Args { location }
+    );
+    assert_eq!(
+        query,
+        "SELECT report FROM weather_reports WHERE location = $1 AND time = $2"
+    );
+    assert_eq!(params.len(), 2);
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn cli_should_not_explain_the_fragments() {
+    let dir = std::env::temp_dir().join(format!("pg_named_args_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let entry = r#"{"name":null,"sql":"SELECT * FROM t WHERE ${where}","fragments":["where"],"params":[],"call_site":"src/a.rs:1:1","fingerprint":"0"}"#;
+    std::fs::write(dir.join("query-0-0.json"), entry).unwrap();
+
+    // without any query to explain, no database is needed
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_pg_named_args"))
+        .arg("--explain")
+        .arg(&dir)
+        .env_remove("DATABASE_URL")
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("-- not explained: the fragments ${where} are only known at runtime"),
+        "{stdout}"
+    );
+}
+
+#[cfg(feature = "cli")]
+#[test]
+#[ignore = "requires PostgreSQL 16 or later in DATABASE_URL"]
+fn cli_should_explain_the_prepared_statement() {
+    let dir = std::env::temp_dir().join(format!("pg_named_args_prepare_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let entry = r#"{"name":"q","sql":"PREPARE q AS SELECT 1","params":[],"call_site":"src/a.rs:1:1","fingerprint":"0"}"#;
+    std::fs::write(dir.join("query-0-0.json"), entry).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_pg_named_args"))
+        .arg("--explain")
+        .arg(&dir)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("--   Result"), "{stdout}");
+}
+
+#[test]
+fn hints_should_be_quoted_and_validated() {
+    use pg_named_args::Hint;
+
+    let a = 1;
+    let (query, _) = query!(
+        "SELECT * FROM t JOIN u ON t.a = u.a WHERE t.a = $a;",
+        Args { a }
+    );
+    let hints = [
+        Hint::seq_scan("t").unwrap(),
+        Hint::rows(&["t", "u"], 10).unwrap(),
+    ];
+    assert_eq!(
+        query.with_hints(&hints).sql(),
+        r#"/*+ SeqScan("t") Rows("t" "u" #10) */ SELECT * FROM t JOIN u ON t.a = u.a WHERE t.a = $1"#
+    );
+    assert_eq!(query.with_hints(&[]).sql(), query.sql());
+    assert!(Hint::seq_scan("t */ DROP TABLE t; /*").is_err());
+    assert!(Hint::index_scan("t", "").is_err());
+}
+
+#[test]
+fn lock_mode_should_be_a_fragment() {
+    use pg_named_args::LockMode;
+
+    let id = 1;
+    let lock = Some(LockMode::no_key_update().nowait());
+    let (query, _) = query_args!(
+        "SELECT * FROM jobs WHERE id = $id ${lock}",
+        Args { id },
+        Sql { lock: lock.into() }
+    );
+    assert_eq!(
+        query,
+        "SELECT * FROM jobs WHERE id = $1 FOR NO KEY UPDATE NOWAIT"
+    );
+
+    let lock: Option<LockMode> = None;
+    let (query, _) = query_args!(
+        "SELECT * FROM jobs WHERE id = $id ${lock}",
+        Args { id },
+        Sql { lock: lock.into() }
+    );
+    assert_eq!(query, "SELECT * FROM jobs WHERE id = $1 ");
+}
+
+#[test]
+fn text_search_modifier_should_bind_the_config() {
+    let search = "rain";
+    let config = "english";
+    let (query, args) = query_args!(
+        "SELECT * FROM t WHERE d @@ $search~plain($config) ORDER BY ts_rank(d, $search~plain($config)) DESC",
+        Args { search, config }
+    );
+    assert_eq!(
+        query,
+        "SELECT * FROM t WHERE d @@ plainto_tsquery($2::regconfig, $1) ORDER BY ts_rank(d, plainto_tsquery($2::regconfig, $1)) DESC"
+    );
+    assert_eq!(args.len(), 2);
+}
+
+#[test]
+fn when_should_include_sections_at_runtime() {
+    for include_archived in [false, true] {
+        let owner = 1;
+        let since = 2020;
+        let (query, args) = query!(
+            "SELECT * FROM t WHERE owner = $owner $when(include_archived) { OR archived_since > $since } ORDER BY id",
+            Args { owner, include_archived, since }
+        );
+        if include_archived {
+            assert_eq!(
+                query.sql(),
+                "SELECT * FROM t WHERE owner = $1  OR archived_since > $2  ORDER BY id"
+            );
+            assert_eq!(query.names(), ["owner", "since"]);
+        } else {
+            assert_eq!(query.sql(), "SELECT * FROM t WHERE owner = $1  ORDER BY id");
+            assert_eq!(query.names(), ["owner"]);
+        }
+        assert_eq!(args.len(), query.names().len());
+    }
+}
+
+#[test]
+fn for_should_repeat_the_body_for_every_item() {
+    struct Reading {
+        location: &'static str,
+        value: f64,
+    }
+
+    let readings = [
+        Reading {
+            location: "sweden",
+            value: 1.5,
+        },
+        Reading {
+            location: "norway",
+            value: 2.5,
+        },
+    ];
+    let source = "sensor";
+    let (query, params) = query!(
+        "INSERT INTO readings(source, location, value) VALUES $for(r in $readings) { ($source, $r.location, $r.value) } RETURNING id",
+        Args { source, readings: &readings }
+    );
+    assert_eq!(
+        query.sql(),
+        "INSERT INTO readings(source, location, value) VALUES  ($1, $2, $3) , ($1, $4, $5)  RETURNING id"
+    );
+    assert_eq!(query.names(), ["source"]);
+    assert_eq!(params.len(), 5);
+    assert_eq!(format!("{:?}", params[4]), "2.5");
+
+    let ids = [1, 2];
+    let (query, params) = query_args!(
+        "$for(id in $ids, UNION ALL) { SELECT * FROM t WHERE id = $id } ORDER BY 1",
+        Args { ids }
+    );
+    assert_eq!(
+        query,
+        " SELECT * FROM t WHERE id = $1 UNION ALL SELECT * FROM t WHERE id = $2  ORDER BY 1"
+    );
+    assert_eq!(params.len(), 2);
+}
+
+#[test]
+fn match_should_include_the_matching_arm() {
+    #[derive(Clone, Copy)]
+    enum Mode {
+        Fast,
+        Exact { limit: bool },
+    }
+
+    let name = "Fred";
+    let limit = 10;
+    for mode in [
+        Mode::Fast,
+        Mode::Exact { limit: false },
+        Mode::Exact { limit: true },
+    ] {
+        let (query, params) = query!(
+            "SELECT * FROM t WHERE $match(mode) { Mode::Fast => { name % $name }, Mode::Exact { limit: false } => { name = $name }, Mode::Exact { .. } => { name = $name LIMIT $limit } }",
+            Args { name, mode, limit }
+        );
+        let expected = match mode {
+            Mode::Fast => "SELECT * FROM t WHERE  name % $1 ",
+            Mode::Exact { limit: false } => "SELECT * FROM t WHERE  name = $1 ",
+            Mode::Exact { .. } => "SELECT * FROM t WHERE  name = $1 LIMIT $2 ",
+        };
+        assert_eq!(query.sql(), expected);
+        assert_eq!(params.len(), query.names().len());
+    }
+}
+
+#[test]
+fn qmark_should_use_question_marks() {
+    let name = "Fred";
+    let age = 40;
+    let (query, (p1, p2, p3)) = query!(
+        qmark,
+        "INSERT INTO t($[name, age]) VALUES($[..]) ON CONFLICT (name) DO UPDATE SET name = $name",
+        Args { name, age }
+    );
+    assert_eq!(
+        query.sql(),
+        "INSERT INTO t(name, age) VALUES(?, ?) ON CONFLICT (name) DO UPDATE SET name = ?"
+    );
+    assert_eq!(query.names(), ["name", "age", "name"]);
+    assert_eq!((*p1, *p2, *p3), ("Fred", 40, "Fred"));
+
+    let (query, ()) = query_args!(qmark, "SELECT 1", Args {});
+    assert_eq!(query, "SELECT 1");
+}
+
+#[cfg(feature = "sea-query")]
+#[test]
+fn sea_query_statement_should_become_a_query() {
+    use pg_named_args::{Query, StatementKind};
+    use sea_query::{Alias, Expr};
+
+    let statement = sea_query::Query::delete()
+        .from_table(Alias::new("t"))
+        .and_where(Expr::col(Alias::new("a")).eq(1))
+        .to_owned();
+    let (query, values) = Query::from_sea_query(&statement);
+    assert_eq!(query.sql(), r#"DELETE FROM "t" WHERE "a" = $1"#);
+    assert_eq!(query.kind(), StatementKind::Delete);
+    assert!(query.names().is_empty());
+    assert_eq!(values.as_params().len(), 1);
+}
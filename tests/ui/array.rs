@@ -0,0 +1,10 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let reports = ["sunny", "rainy"];
+    let _ = query_args!(
+        array,
+        "INSERT INTO reports(report) VALUES $for(r in $reports) { ($r) }",
+        Args { reports: &reports }
+    );
+}
@@ -0,0 +1,7 @@
+use pg_named_args::{batch_args, query_args};
+
+fn main() {
+    let a = 1;
+    query_args!(["$a", "$a"], Args { a });
+    batch_args!("$a", Args { a });
+}
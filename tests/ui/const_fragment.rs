@@ -0,0 +1,7 @@
+use pg_named_args::const_fragment;
+
+const FILTER: &str = "WHERE location = $location";
+
+fn main() {
+    let _ = const_fragment!(FILTER);
+}
@@ -0,0 +1,7 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let _ = query_args!("SELECT $(1 +)");
+    let _ = query_args!("SELECT $(missing)");
+    let _ = query_args!("SELECT $(f(1)");
+}
@@ -0,0 +1,12 @@
+use pg_named_args::fragment;
+
+fn main() {
+    let col = "created_at";
+    let _ = fragment!("ORDER BY {col}", col);
+
+    // a fragment from the scope can't be captured like in `format!`
+    let dir = fragment!("DESC");
+    let _ = fragment!("ORDER BY id {dir}, {col}", dir);
+    let _ = fragment!("ORDER BY id", dir);
+    let _ = fragment!("ORDER BY {dir:?}", dir);
+}
@@ -0,0 +1,10 @@
+use pg_named_args::{fragment, query_args, OrderByFragment};
+
+fn main() {
+    let filter = OrderByFragment::new(fragment!("created_at DESC"));
+    let _ = query_args!(
+        "SELECT * FROM reports WHERE ${filter: where}",
+        Args {},
+        Sql { filter }
+    );
+}
@@ -0,0 +1,13 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let order_total = 10;
+    let _ = query_args!(
+        r#"INSERT INTO orders ($["2fa", order_total]) VALUES ($[..])"#,
+        Args { order_total }
+    );
+    let _ = query_args!(
+        r#"INSERT INTO orders ($["Order Total", order_total]) VALUES ($[..])"#,
+        Args { order_total }
+    );
+}
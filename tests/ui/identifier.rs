@@ -0,0 +1,5 @@
+use pg_named_args::identifier;
+
+fn main() {
+    let _ = identifier!("");
+}
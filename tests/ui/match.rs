@@ -0,0 +1,12 @@
+use pg_named_args::query_args;
+
+enum Mode {
+    Fast,
+    Exact,
+}
+
+fn main() {
+    let mode = Mode::Exact;
+    let a = 1;
+    query_args!("SELECT $a, $match(mode) { Fast => { 1 }, Exact => { 2 } }", Args { a, mode });
+}
@@ -0,0 +1,11 @@
+use pg_named_args::query_args;
+
+enum Mode {
+    Fast,
+    Exact,
+}
+
+fn main() {
+    let mode = Mode::Exact;
+    query_args!("SELECT $match(mode) { Mode::Fast => { 1 } }", Args { mode });
+}
@@ -0,0 +1,10 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let location = "sweden";
+    let _ = query_args!(
+        max_rows(100),
+        "SELECT * FROM reports WHERE location = $location LIMIT 1000",
+        Args { location }
+    );
+}
@@ -0,0 +1,15 @@
+use pg_named_args::query_args;
+
+struct Report {
+    location: String,
+}
+
+fn main() {
+    let report = Report {
+        location: "sweden".to_owned(),
+    };
+    let _ = query_args!(
+        "INSERT INTO reports(location) VALUES($report)",
+        Args { report }
+    );
+}
@@ -0,0 +1,9 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let a = "x";
+    let b = true;
+    query_args!(qmark, "SELECT * FROM t WHERE a LIKE $a~contains", Args { a });
+    query_args!(qmark, "SELECT * FROM t $when(b) { WHERE a = $a }", Args { a, b });
+    query_args!(qmark, "SELECT $json{a, b} FROM t", Args { a, b });
+}
@@ -0,0 +1,5 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let _ = query_args!("SELECT * FROM reports WHERE id = ?", Args {});
+}
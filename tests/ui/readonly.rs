@@ -0,0 +1,8 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let a = 1;
+    query_args!(readonly, "DELETE FROM t WHERE a = $a", Args { a });
+    query_args!(readonly, "SELECT * FROM t WHERE a = $a FOR UPDATE", Args { a });
+    query_args!(readonly, "SELECT * FROM t WHERE a = $a", Args { a });
+}
@@ -0,0 +1,10 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let user_id = 1;
+    let _ = query_args!(
+        "SELECT * FROM users WHERE id = $user_id OR parent = $userId",
+        Args { user_id }
+    );
+    let _ = query_args!("SELECT * FROM users WHERE id = $user_id", Args { userId: user_id });
+}
@@ -0,0 +1,8 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let name = "Fred";
+    query_args!(strict_insert, "INSERT INTO t (name) VALUES ($name)", Args { name });
+    query_args!(strict_insert, "INSERT INTO t ($[name]) VALUES ($name)", Args { name });
+    query_args!(strict_insert, "INSERT INTO t ($[name]) VALUES ($[..])", Args { name });
+}
@@ -0,0 +1,10 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let location = "sweden";
+    let _ = query_args!(
+        template,
+        "SELECT * FROM reports WHERE location = $location",
+        Args { location }
+    );
+}
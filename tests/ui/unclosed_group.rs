@@ -0,0 +1,8 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let a = 1;
+    let b = 2;
+    let c = 3;
+    query_args!("INSERT INTO t($[a, b) VALUES($[..]) WHERE c = $c", Args { a, b, c });
+}
@@ -0,0 +1,7 @@
+use pg_named_args::query_args;
+
+fn main() {
+    let a = 1;
+    query_args!(indent, "$a", Args { a });
+    query_args!(dedent, dedent, "$a", Args { a });
+}
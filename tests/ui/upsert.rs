@@ -0,0 +1,20 @@
+use pg_named_args::{query_args, upsert_args};
+
+fn main() {
+    let location = "sweden";
+    let report = "sunny";
+    let _ = upsert_args!(
+        r"INSERT INTO weather_reports ($[location, report]) VALUES ($[..])",
+        Args { location, report }
+    );
+    let _ = upsert_args!(
+        conflict(time),
+        r"INSERT INTO weather_reports ($[location, report]) VALUES ($[..])",
+        Args { location, report }
+    );
+    let _ = query_args!(
+        conflict(location),
+        r"INSERT INTO weather_reports ($[location, report]) VALUES ($[..])",
+        Args { location, report }
+    );
+}
@@ -0,0 +1,8 @@
+use pg_named_args::values_args;
+
+fn main() {
+    let location = "sweden";
+    let report = Some("sunny");
+    let _ = values_args!("($[location])", Args { location });
+    let _ = values_args!("$[location, report?]", Args { location, report });
+}
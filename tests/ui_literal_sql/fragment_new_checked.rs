@@ -0,0 +1,5 @@
+use pg_named_args::Fragment;
+
+fn main() {
+    let _ = Fragment::new_checked("id DESC");
+}
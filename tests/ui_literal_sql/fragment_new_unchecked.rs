@@ -0,0 +1,6 @@
+use pg_named_args::Fragment;
+
+fn main() {
+    let sql: &'static str = String::from("1; DROP TABLE t").leak();
+    let _ = Fragment::new_unchecked(sql);
+}
@@ -0,0 +1,5 @@
+use pg_named_args::Fragment;
+
+fn main() {
+    let _ = Fragment::new_unchecked_owned(String::from("1; DROP TABLE t"));
+}
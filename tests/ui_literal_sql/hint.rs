@@ -0,0 +1,5 @@
+use pg_named_args::Hint;
+
+fn main() {
+    let _ = Hint::seq_scan("reports");
+}
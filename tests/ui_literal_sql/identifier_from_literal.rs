@@ -0,0 +1,6 @@
+use pg_named_args::Identifier;
+
+fn main() {
+    let name: &'static str = String::from("reports").leak();
+    let _ = Identifier::from_literal(name);
+}
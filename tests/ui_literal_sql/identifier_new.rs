@@ -0,0 +1,5 @@
+use pg_named_args::Identifier;
+
+fn main() {
+    let _ = Identifier::new("reports");
+}
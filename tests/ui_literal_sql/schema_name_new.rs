@@ -0,0 +1,5 @@
+use pg_named_args::SchemaName;
+
+fn main() {
+    let _ = SchemaName::new("tenant_a");
+}